@@ -1,5 +1,6 @@
 extern crate rulinalg;
 
+use rulinalg::error::{Error, ErrorKind};
 use rulinalg::matrix::{BaseMatrix, Matrix};
 use rulinalg::vector::Vector;
 
@@ -26,7 +27,10 @@ pub struct KalmanState {
 }
 
 impl KalmanFilter {
-    pub fn filter(&self, data: &Vec<Vector<f64>>) -> (Vec<KalmanState>, Vec<KalmanState>) {
+    /// Returns the filtered and predicted estimates, or an error if an
+    /// innovation covariance could not be inverted.
+    pub fn filter(&self, data: &[Vector<f64>])
+                -> Result<(Vec<KalmanState>, Vec<KalmanState>), Error> {
 
         let t: usize = data.len();
 
@@ -38,34 +42,43 @@ impl KalmanFilter {
                                     p: (self.p0).clone() });
 
         for k in 0..t {
-            filtered.push(update_step(self, &predicted[k], &data[k]));
+            filtered.push(update_step(self, &predicted[k], &data[k])?);
             predicted.push(predict_step(self, &filtered[k]));
         }
 
-        (filtered, predicted)
+        Ok((filtered, predicted))
     }
 
+    /// Returns the smoothed estimates, or an error if `predicted` is shorter
+    /// than `filtered` or a predicted covariance could not be inverted.
+    /// Empty input gives empty output.
     pub fn smooth(&self,
-                filtered: &Vec<KalmanState>,
-                predicted: &Vec<KalmanState>)
-                -> Vec<KalmanState> {
+                filtered: &[KalmanState],
+                predicted: &[KalmanState])
+                -> Result<Vec<KalmanState>, Error> {
 
         let t: usize = filtered.len();
         let mut smoothed: Vec<KalmanState> = Vec::with_capacity(t);
+        if predicted.len() < t {
+            return Err(Error::new(ErrorKind::InvalidArg,
+                                  "Fewer predicted than filtered estimates."));
+        }
 
         // Do Kalman smoothing in reverse order
-        let mut init = (filtered[t - 1]).clone();
-        smoothed.push((filtered[t - 1]).clone());
-
-        for k in 1..t {
-            smoothed.push(smoothing_step(self, &init,
-                                        &filtered[t-k-1],
-                                        &predicted[t-k]));
-            init = (&smoothed[k]).clone();
+        let mut init = match filtered.last() {
+            Some(last) => last.clone(),
+            None => return Ok(smoothed),
+        };
+        smoothed.push(init.clone());
+
+        for (filt, pred) in filtered.iter().rev().skip(1)
+            .zip(predicted[1..t].iter().rev()) {
+            init = smoothing_step(self, &init, filt, pred)?;
+            smoothed.push(init.clone());
         }
 
         smoothed.reverse();
-        smoothed
+        Ok(smoothed)
     }
 }
 
@@ -84,33 +97,32 @@ pub fn predict_step(kalman_filter: &KalmanFilter,
 pub fn update_step(kalman_filter: &KalmanFilter,
                 pred: &KalmanState,
                 measure: &Vector<f64>)
-                -> KalmanState {
+                -> Result<KalmanState, Error> {
 
     let identity = Matrix::<f64>::identity(kalman_filter.x0.size());
 
     // Compute Kalman gain
     let k: Matrix<f64> = &pred.p * &kalman_filter.h.transpose() *
         (&kalman_filter.h * &pred.p * &kalman_filter.h.transpose() + &kalman_filter.r)
-        .inverse()
-        .expect("Kalman gain computation failed due to failure to invert.");
+        .inverse()?;
 
     // Update state variable and covariance
     let x = &pred.x + &k * (measure - &kalman_filter.h * &pred.x);
     let p = (identity - &k * &kalman_filter.h) * &pred.p;
 
-    KalmanState { x: x, p: p }
+    Ok(KalmanState { x, p })
 
 }
 
 pub fn filter_step(kalman_filter: &KalmanFilter,
                 init: &KalmanState,
                 measure: &Vector<f64>)
-                -> (KalmanState, KalmanState) {
+                -> Result<(KalmanState, KalmanState), Error> {
 
     let pred = predict_step(kalman_filter, init);
-    let upd = update_step(kalman_filter, &pred, measure);
+    let upd = update_step(kalman_filter, &pred, measure)?;
 
-    (KalmanState { x: upd.x, p: upd.p }, KalmanState { x: pred.x, p: pred.p })
+    Ok((KalmanState { x: upd.x, p: upd.p }, KalmanState { x: pred.x, p: pred.p }))
 }
 
 
@@ -118,15 +130,14 @@ fn smoothing_step(kalman_filter: &KalmanFilter,
                 init: &KalmanState,
                 filtered: &KalmanState,
                 predicted: &KalmanState)
-                -> KalmanState {
+                -> Result<KalmanState, Error> {
 
     let j: Matrix<f64> = &filtered.p * &kalman_filter.f.transpose() *
-        &predicted.p.clone().inverse()
-        .expect("Predicted state covariance matrix could not be inverted.");
+        &predicted.p.clone().inverse()?;
     let x: Vector<f64> = &filtered.x + &j * (&init.x - &predicted.x);
     let p: Matrix<f64> = &filtered.p + &j * (&init.p - &predicted.p) * &j.transpose();
 
-    KalmanState { x: x, p: p }
+    Ok(KalmanState { x, p })
 
 }
 // example
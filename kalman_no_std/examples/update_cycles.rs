@@ -0,0 +1,259 @@
+//! Measure the cost of the Kalman update step.
+//!
+//! Run with `cargo run --release --features std --example update_cycles`.
+//! On x86_64, the time stamp counter is read, so the figures are reference
+//! cycles. Elsewhere, nanoseconds are reported. Each figure is the median of
+//! many repetitions, with a Joseph form covariance update.
+//!
+//! `inverse` is the update with an explicit inverse of the innovation
+//! covariance, as `ObservationModel::update` was implemented before the
+//! update used Cholesky solves and a workspace. `update` writes its result
+//! back to the estimate, as a filter loop does. `step_in_place` includes the
+//! prediction.
+//!
+//! Reference cycles on an x86_64 virtual machine, median of nine runs:
+//!
+//! | case             | inverse | update | update_in_place | step_in_place |
+//! |------------------|--------:|-------:|----------------:|--------------:|
+//! | 4/2 static       |     294 |    394 |             400 |           552 |
+//! | 4/2 `Dyn`        |       - |   2212 |            1540 |          2152 |
+//! | 9/6 static       |    4050 |   3850 |            3792 |          4540 |
+//! | 9/6 `Dyn`        |       - |  10154 |            8658 |         12682 |
+//!
+//! With static dimensions, `update_in_place` and `step_in_place` call the
+//! value-returning steps, so they cost the same as `update`. With `Dyn`
+//! dimensions, they avoid the allocations of the temporaries. The Cholesky
+//! solve is slower than the explicit inverse of a 2x2 innovation covariance,
+//! but faster for six observations.
+
+use kalman_no_std::{
+    CovarianceUpdateMethod, KalmanFilterNoControl, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl, UpdateWorkspace,
+};
+use nalgebra::{Const, DMatrix, DVector, DimMin, Dyn, SMatrix, SVector};
+
+const SAMPLES: usize = 20_001;
+
+struct Model<const S: usize, const O: usize> {
+    f: SMatrix<f64, S, S>,
+    ft: SMatrix<f64, S, S>,
+    q: SMatrix<f64, S, S>,
+    h: SMatrix<f64, O, S>,
+    ht: SMatrix<f64, S, O>,
+    r: SMatrix<f64, O, O>,
+}
+
+impl<const S: usize, const O: usize> Model<S, O> {
+    fn new() -> Self {
+        let f = SMatrix::<f64, S, S>::from_fn(|i, j| match j.checked_sub(i) {
+            Some(0) => 1.0,
+            Some(1) => 0.1,
+            _ => 0.0,
+        });
+        let h = SMatrix::<f64, O, S>::from_fn(|i, j| if i == j { 1.0 } else { 0.0 });
+        Self {
+            f,
+            ft: f.transpose(),
+            q: SMatrix::identity() * 1e-3,
+            h,
+            ht: h.transpose(),
+            r: SMatrix::identity() * 0.1,
+        }
+    }
+
+    /// The update with an explicit inverse, for comparison.
+    fn inverse_update(
+        &self,
+        prior: &StateAndCovariance<f64, Const<S>>,
+        observation: &SVector<f64, O>,
+    ) -> StateAndCovariance<f64, Const<S>> {
+        let p = prior.covariance();
+        let s = self.h * p * self.ht + self.r;
+        let s_inv = s.cholesky().unwrap().inverse();
+        let k = p * self.ht * s_inv;
+        let state = prior.state() + k * (observation - self.h * prior.state());
+        let one_minus_kh = SMatrix::<f64, S, S>::identity() - k * self.h;
+        let covariance = one_minus_kh * p * one_minus_kh.transpose() + k * self.r * k.transpose();
+        StateAndCovariance::new(state, covariance)
+    }
+}
+
+impl<const S: usize, const O: usize> TransitionModelLinearNoControl<f64, Const<S>> for Model<S, O> {
+    fn F(&self) -> &SMatrix<f64, S, S> {
+        &self.f
+    }
+    fn FT(&self) -> &SMatrix<f64, S, S> {
+        &self.ft
+    }
+    fn Q(&self) -> &SMatrix<f64, S, S> {
+        &self.q
+    }
+}
+
+impl<const S: usize, const O: usize> ObservationModel<f64, Const<S>, Const<O>> for Model<S, O>
+where
+    Const<O>: DimMin<Const<O>, Output = Const<O>>,
+{
+    fn H(&self) -> &SMatrix<f64, O, S> {
+        &self.h
+    }
+    fn HT(&self) -> &SMatrix<f64, S, O> {
+        &self.ht
+    }
+    fn R(&self) -> &SMatrix<f64, O, O> {
+        &self.r
+    }
+}
+
+/// The same model with runtime dimensions.
+struct DynModel {
+    f: DMatrix<f64>,
+    ft: DMatrix<f64>,
+    q: DMatrix<f64>,
+    h: DMatrix<f64>,
+    ht: DMatrix<f64>,
+    r: DMatrix<f64>,
+}
+
+impl DynModel {
+    fn from_static<const S: usize, const O: usize>(model: &Model<S, O>) -> Self {
+        Self {
+            f: DMatrix::from_column_slice(S, S, model.f.as_slice()),
+            ft: DMatrix::from_column_slice(S, S, model.ft.as_slice()),
+            q: DMatrix::from_column_slice(S, S, model.q.as_slice()),
+            h: DMatrix::from_column_slice(O, S, model.h.as_slice()),
+            ht: DMatrix::from_column_slice(S, O, model.ht.as_slice()),
+            r: DMatrix::from_column_slice(O, O, model.r.as_slice()),
+        }
+    }
+}
+
+impl TransitionModelLinearNoControl<f64, Dyn> for DynModel {
+    fn F(&self) -> &DMatrix<f64> {
+        &self.f
+    }
+    fn FT(&self) -> &DMatrix<f64> {
+        &self.ft
+    }
+    fn Q(&self) -> &DMatrix<f64> {
+        &self.q
+    }
+}
+
+impl ObservationModel<f64, Dyn, Dyn> for DynModel {
+    fn H(&self) -> &DMatrix<f64> {
+        &self.h
+    }
+    fn HT(&self) -> &DMatrix<f64> {
+        &self.ht
+    }
+    fn R(&self) -> &DMatrix<f64> {
+        &self.r
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn now() -> u64 {
+    // SAFETY: `rdtsc` is available on every x86_64 processor.
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn now() -> u64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+fn median(mut f: impl FnMut()) -> u64 {
+    let mut samples: Vec<u64> = (0..SAMPLES)
+        .map(|_| {
+            let start = now();
+            f();
+            now() - start
+        })
+        .collect();
+    samples.sort_unstable();
+    samples[SAMPLES / 2]
+}
+
+/// Time `update`, `update_in_place` and `step_in_place` of a model.
+fn measure<SS, OS, M>(
+    model: &M,
+    prior: &StateAndCovariance<f64, SS>,
+    observation: &nalgebra::OVector<f64, OS>,
+    workspace: &mut UpdateWorkspace<f64, SS, OS>,
+) -> [u64; 3]
+where
+    SS: nalgebra::Dim,
+    OS: nalgebra::Dim + DimMin<OS, Output = OS>,
+    M: ObservationModel<f64, SS, OS> + TransitionModelLinearNoControl<f64, SS>,
+    nalgebra::DefaultAllocator: nalgebra::allocator::Allocator<f64, SS, SS>
+        + nalgebra::allocator::Allocator<f64, SS>
+        + nalgebra::allocator::Allocator<f64, OS, SS>
+        + nalgebra::allocator::Allocator<f64, SS, OS>
+        + nalgebra::allocator::Allocator<f64, OS, OS>
+        + nalgebra::allocator::Allocator<f64, OS>
+        + nalgebra::allocator::Allocator<(usize, usize), OS>,
+{
+    let method = CovarianceUpdateMethod::JosephForm;
+    let kf = KalmanFilterNoControl::new(model, model);
+    // Each case starts from `prior` and leaves the posterior in `estimate`,
+    // as a filter loop does.
+    let mut estimate = prior.clone();
+    let update = median(|| {
+        estimate.clone_from(prior);
+        estimate = model.update(&estimate, observation, method).unwrap();
+        std::hint::black_box(&estimate);
+    });
+    let update_in_place = median(|| {
+        estimate.clone_from(prior);
+        model
+            .update_in_place(&mut estimate, observation, method, workspace)
+            .unwrap();
+        std::hint::black_box(&estimate);
+    });
+    let step_in_place = median(|| {
+        estimate.clone_from(prior);
+        kf.step_in_place(&mut estimate, observation, method, workspace)
+            .unwrap();
+        std::hint::black_box(&estimate);
+    });
+    [update, update_in_place, step_in_place]
+}
+
+fn bench<const S: usize, const O: usize>()
+where
+    Const<O>: DimMin<Const<O>, Output = Const<O>>,
+{
+    let model = Model::<S, O>::new();
+    let prior = StateAndCovariance::new(SVector::<f64, S>::zeros(), SMatrix::identity());
+    let observation = SVector::<f64, O>::from_element(0.5);
+    let inverse = median(|| {
+        std::hint::black_box(model.inverse_update(&prior, &observation));
+    });
+    let mut workspace = UpdateWorkspace::new(Const::<S>, Const::<O>);
+    let [update, update_in_place, step_in_place] =
+        measure(&model, &prior, &observation, &mut workspace);
+    println!(
+        "{S}-state/{O}-observation: inverse {inverse}, update {update}, \
+         update_in_place {update_in_place}, step_in_place {step_in_place}"
+    );
+
+    let model = DynModel::from_static(&model);
+    let prior = StateAndCovariance::new(DVector::zeros(S), DMatrix::identity(S, S));
+    let observation = DVector::from_element(O, 0.5);
+    let mut workspace = UpdateWorkspace::new(Dyn(S), Dyn(O));
+    let [update, update_in_place, step_in_place] =
+        measure(&model, &prior, &observation, &mut workspace);
+    println!(
+        "{S}-state/{O}-observation, Dyn: update {update}, \
+         update_in_place {update_in_place}, step_in_place {step_in_place}"
+    );
+}
+
+fn main() {
+    bench::<4, 2>();
+    bench::<9, 6>();
+}
@@ -0,0 +1,381 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, DimName, Matrix2, Matrix3, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::{is_nan, Error, ErrorKind};
+
+/// Maximum number of Riccati iterations used to find steady-state gains.
+const MAX_RICCATI_ITERATIONS: usize = 100_000;
+
+/// Return `ErrorKind::NonPositiveTimeStep` unless `dt` is positive.
+fn check_dt<R: RealField + Copy>(dt: R) -> Result<(), Error> {
+    if is_nan(dt) || dt <= R::zero() {
+        return Err(ErrorKind::NonPositiveTimeStep.into());
+    }
+    Ok(())
+}
+
+/// Gains of an [`AlphaBetaFilter`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlphaBetaGains<R: RealField + Copy> {
+    /// Position gain.
+    pub alpha: R,
+    /// Velocity gain. The velocity correction is `beta / dt` times the residual.
+    pub beta: R,
+}
+
+impl<R: RealField + Copy> AlphaBetaGains<R> {
+    /// Optimal steady-state gains for a tracking index `lambda`
+    ///
+    /// The tracking index is `sigma_w * dt^2 / sigma_v`, the ratio of the
+    /// position change due to (piecewise constant) random acceleration with
+    /// standard deviation `sigma_w` to the measurement noise standard
+    /// deviation `sigma_v`. Uses the closed form of Kalata (1984).
+    pub fn from_tracking_index(lambda: R) -> Self {
+        let two: R = na::convert(2.0);
+        let four: R = na::convert(4.0);
+        let eight: R = na::convert(8.0);
+        let r = (four + lambda - (eight * lambda + lambda * lambda).sqrt()) / four;
+        let alpha = R::one() - r * r;
+        let beta = two * (two - alpha) - four * (R::one() - alpha).sqrt();
+        Self { alpha, beta }
+    }
+
+    /// Steady-state Kalman gains for the constant velocity model
+    ///
+    /// The process noise is the continuous white noise acceleration model
+    /// used by `ConstantVelocity2DModel`, `noise_scale * [dt^3/3, dt^2/2;
+    /// dt^2/2, dt]`, so gains tuned here match a Kalman filter with the same
+    /// parameters once it has converged. `measurement_variance` is the
+    /// variance of the position measurement.
+    ///
+    /// Returns `ErrorKind::NonPositiveTimeStep` if `dt` is not positive.
+    pub fn from_noise(dt: R, noise_scale: R, measurement_variance: R) -> Result<Self, Error> {
+        check_dt(dt)?;
+        let two: R = na::convert(2.0);
+        let three: R = na::convert(3.0);
+        #[rustfmt::skip]
+        let f = Matrix2::new(R::one(), dt,
+                             R::zero(), R::one());
+        #[rustfmt::skip]
+        let q = Matrix2::new(dt * dt * dt / three, dt * dt / two,
+                             dt * dt / two, dt) * noise_scale;
+        let k = steady_state_gain(&f, &q, measurement_variance);
+        Ok(Self {
+            alpha: k.x,
+            beta: k.y * dt,
+        })
+    }
+}
+
+/// Gains of an [`AlphaBetaGammaFilter`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlphaBetaGammaGains<R: RealField + Copy> {
+    /// Position gain.
+    pub alpha: R,
+    /// Velocity gain. The velocity correction is `beta / dt` times the residual.
+    pub beta: R,
+    /// Acceleration gain. The acceleration correction is `gamma / dt^2` times
+    /// the residual.
+    pub gamma: R,
+}
+
+impl<R: RealField + Copy> AlphaBetaGammaGains<R> {
+    /// Optimal steady-state gains for a tracking index `lambda`
+    ///
+    /// The tracking index is `sigma_w * dt^2 / sigma_v`, where `sigma_w` is
+    /// the standard deviation of the (piecewise constant) random acceleration
+    /// increment and `sigma_v` the measurement noise standard deviation.
+    pub fn from_tracking_index(lambda: R) -> Self {
+        // The gains satisfy alpha = 1 - s^2, beta = 2 (1 - s)^2 and
+        // gamma = beta^2 / (2 alpha), where s is the unique root in (0, 1) of
+        // s^3 + (lambda/2 - 3) s^2 + (lambda/2 + 3) s - 1 = 0.
+        let half: R = na::convert(0.5);
+        let three: R = na::convert(3.0);
+        let b = lambda * half - three;
+        let c = lambda * half + three;
+        let cubic = |s: R| ((s + b) * s + c) * s - R::one();
+
+        let (mut lower, mut upper) = (R::zero(), R::one());
+        for _ in 0..64 {
+            let mid = (lower + upper) * half;
+            if cubic(mid) < R::zero() {
+                lower = mid;
+            } else {
+                upper = mid;
+            }
+        }
+        let s = (lower + upper) * half;
+
+        let two: R = na::convert(2.0);
+        let alpha = R::one() - s * s;
+        let beta = two * (R::one() - s) * (R::one() - s);
+        let gamma = beta * beta / (two * alpha);
+        Self { alpha, beta, gamma }
+    }
+
+    /// Steady-state Kalman gains for the constant acceleration model
+    ///
+    /// The process noise is continuous white noise jerk with the same scaling
+    /// convention as `ConstantVelocity2DModel`, `noise_scale * [dt^5/20,
+    /// dt^4/8, dt^3/6; dt^4/8, dt^3/3, dt^2/2; dt^3/6, dt^2/2, dt]`.
+    /// `measurement_variance` is the variance of the position measurement.
+    ///
+    /// Returns `ErrorKind::NonPositiveTimeStep` if `dt` is not positive.
+    pub fn from_noise(dt: R, noise_scale: R, measurement_variance: R) -> Result<Self, Error> {
+        check_dt(dt)?;
+        let c = |x: f64| -> R { na::convert(x) };
+        let dt2 = dt * dt;
+        let dt3 = dt2 * dt;
+        #[rustfmt::skip]
+        let f = Matrix3::new(R::one(), dt, dt2 / c(2.0),
+                             R::zero(), R::one(), dt,
+                             R::zero(), R::zero(), R::one());
+        #[rustfmt::skip]
+        let q = Matrix3::new(dt3 * dt2 / c(20.0), dt2 * dt2 / c(8.0), dt3 / c(6.0),
+                             dt2 * dt2 / c(8.0), dt3 / c(3.0), dt2 / c(2.0),
+                             dt3 / c(6.0), dt2 / c(2.0), dt) * noise_scale;
+        let k = steady_state_gain(&f, &q, measurement_variance);
+        Ok(Self {
+            alpha: k.x,
+            beta: k.y * dt,
+            gamma: k.z * dt2,
+        })
+    }
+}
+
+/// A fixed-gain alpha-beta tracker for a single coordinate
+///
+/// This is the steady-state form of a constant velocity Kalman filter. It
+/// processes one sample at a time and needs only a handful of multiplications
+/// per step.
+#[derive(Debug, Clone)]
+pub struct AlphaBetaFilter<R: RealField + Copy> {
+    dt: R,
+    gains: AlphaBetaGains<R>,
+    position: R,
+    velocity: R,
+}
+
+impl<R: RealField + Copy> AlphaBetaFilter<R> {
+    /// Create a new `AlphaBetaFilter` with sample interval `dt`.
+    ///
+    /// Returns `ErrorKind::NonPositiveTimeStep` if `dt` is not positive.
+    pub fn new(dt: R, gains: AlphaBetaGains<R>, position: R, velocity: R) -> Result<Self, Error> {
+        check_dt(dt)?;
+        Ok(Self {
+            dt,
+            gains,
+            position,
+            velocity,
+        })
+    }
+
+    /// Predict one interval ahead and correct with `measurement`
+    ///
+    /// If the measurement is NaN (not a number), only the prediction is
+    /// performed. Returns the new position estimate.
+    pub fn step(&mut self, measurement: R) -> R {
+        self.position += self.velocity * self.dt;
+        if !is_nan(measurement) {
+            let residual = measurement - self.position;
+            self.position += self.gains.alpha * residual;
+            self.velocity += self.gains.beta / self.dt * residual;
+        }
+        self.position
+    }
+
+    /// Get the position estimate.
+    #[inline]
+    pub fn position(&self) -> R {
+        self.position
+    }
+    /// Get the velocity estimate.
+    #[inline]
+    pub fn velocity(&self) -> R {
+        self.velocity
+    }
+    /// Get the gains.
+    #[inline]
+    pub fn gains(&self) -> &AlphaBetaGains<R> {
+        &self.gains
+    }
+}
+
+/// A fixed-gain alpha-beta-gamma tracker for a single coordinate
+///
+/// This is the steady-state form of a constant acceleration Kalman filter.
+#[derive(Debug, Clone)]
+pub struct AlphaBetaGammaFilter<R: RealField + Copy> {
+    dt: R,
+    gains: AlphaBetaGammaGains<R>,
+    position: R,
+    velocity: R,
+    acceleration: R,
+}
+
+impl<R: RealField + Copy> AlphaBetaGammaFilter<R> {
+    /// Create a new `AlphaBetaGammaFilter` with sample interval `dt`.
+    ///
+    /// Returns `ErrorKind::NonPositiveTimeStep` if `dt` is not positive.
+    pub fn new(
+        dt: R,
+        gains: AlphaBetaGammaGains<R>,
+        position: R,
+        velocity: R,
+        acceleration: R,
+    ) -> Result<Self, Error> {
+        check_dt(dt)?;
+        Ok(Self {
+            dt,
+            gains,
+            position,
+            velocity,
+            acceleration,
+        })
+    }
+
+    /// Predict one interval ahead and correct with `measurement`
+    ///
+    /// If the measurement is NaN (not a number), only the prediction is
+    /// performed. Returns the new position estimate.
+    pub fn step(&mut self, measurement: R) -> R {
+        let half: R = na::convert(0.5);
+        let dt = self.dt;
+        self.position += self.velocity * dt + self.acceleration * dt * dt * half;
+        self.velocity += self.acceleration * dt;
+        if !is_nan(measurement) {
+            let residual = measurement - self.position;
+            self.position += self.gains.alpha * residual;
+            self.velocity += self.gains.beta / dt * residual;
+            self.acceleration += self.gains.gamma / (dt * dt) * residual;
+        }
+        self.position
+    }
+
+    /// Get the position estimate.
+    #[inline]
+    pub fn position(&self) -> R {
+        self.position
+    }
+    /// Get the velocity estimate.
+    #[inline]
+    pub fn velocity(&self) -> R {
+        self.velocity
+    }
+    /// Get the acceleration estimate.
+    #[inline]
+    pub fn acceleration(&self) -> R {
+        self.acceleration
+    }
+    /// Get the gains.
+    #[inline]
+    pub fn gains(&self) -> &AlphaBetaGammaGains<R> {
+        &self.gains
+    }
+}
+
+/// Iterate the Riccati equation of a filter observing the first state
+/// component until the Kalman gain converges.
+fn steady_state_gain<R, D>(f: &OMatrix<R, D, D>, q: &OMatrix<R, D, D>, r: R) -> OVector<R, D>
+where
+    R: RealField + Copy,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+    DefaultAllocator: Allocator<R, D>,
+{
+    let tolerance = R::default_epsilon() * na::convert(16.0);
+    let mut p = q.clone();
+    let mut gain = OVector::<R, D>::zeros();
+    for _ in 0..MAX_RICCATI_ITERATIONS {
+        // Update with a scalar observation of the first component.
+        let s = match p.get((0, 0)) {
+            Some(p00) => *p00 + r,
+            None => return gain,
+        };
+        let new_gain: OVector<R, D> = p.column(0) / s;
+        // P is symmetric, so its first row is the transpose of its first column.
+        let p_col: OVector<R, D> = p.column(0).into_owned();
+        p.ger(-R::one(), &new_gain, &p_col, R::one());
+        // Predict, keeping P symmetric despite rounding.
+        p = (f * p * f.transpose() + q).symmetric_part();
+
+        let converged = (&new_gain - &gain).amax() <= tolerance * new_gain.amax();
+        gain = new_gain;
+        if converged {
+            break;
+        }
+    }
+    gain
+}
+
+#[test]
+fn test_alpha_beta_gamma_gains() {
+    // The closed forms agree with the steady-state Kalman gain for the
+    // discrete white noise acceleration (jerk) models.
+    for lambda in [0.04, 0.5, 3.0, 50.0] {
+        #[rustfmt::skip]
+        let f = Matrix2::new(1.0, 1.0,
+                             0.0, 1.0);
+        let g = na::Vector2::new(0.5, 1.0);
+        let k = steady_state_gain(&f, &(g * g.transpose() * lambda * lambda), 1.0);
+        let gains = AlphaBetaGains::from_tracking_index(lambda);
+        approx::assert_relative_eq!(gains.alpha, k[0], max_relative = 1e-9);
+        approx::assert_relative_eq!(gains.beta, k[1], max_relative = 1e-9);
+
+        #[rustfmt::skip]
+        let f = Matrix3::new(1.0, 1.0, 0.5,
+                             0.0, 1.0, 1.0,
+                             0.0, 0.0, 1.0);
+        let g = na::Vector3::new(0.5, 1.0, 1.0);
+        let k = steady_state_gain(&f, &(g * g.transpose() * lambda * lambda), 1.0);
+        let gains = AlphaBetaGammaGains::from_tracking_index(lambda);
+        approx::assert_relative_eq!(gains.alpha, k[0], max_relative = 1e-9);
+        approx::assert_relative_eq!(gains.beta, k[1], max_relative = 1e-9);
+        approx::assert_relative_eq!(gains.gamma, k[2], max_relative = 1e-9);
+    }
+}
+
+#[test]
+fn test_alpha_beta_tracks_ramp() {
+    let dt = 0.01f32;
+    let gains = AlphaBetaGains::from_noise(dt, 100.0, 0.01).unwrap();
+    let mut filter = AlphaBetaFilter::new(dt, gains, 0.0, 0.0).unwrap();
+    for i in 0..2000 {
+        let t = i as f32 * dt;
+        let measurement = if i % 10 == 0 { f32::NAN } else { 3.0 * t };
+        filter.step(measurement);
+    }
+    approx::assert_relative_eq!(filter.velocity(), 3.0, max_relative = 1e-3);
+
+    // A zero, negative or NaN interval would divide by zero in `step`.
+    for dt in [0.0, -0.01, f32::NAN] {
+        let kind = Some(ErrorKind::NonPositiveTimeStep);
+        assert_eq!(
+            AlphaBetaGains::from_noise(dt, 100.0, 0.01)
+                .err()
+                .map(|e| *e.kind()),
+            kind
+        );
+        assert_eq!(
+            AlphaBetaFilter::new(dt, gains, 0.0, 0.0)
+                .err()
+                .map(|e| *e.kind()),
+            kind
+        );
+        assert_eq!(
+            AlphaBetaGammaGains::from_noise(dt, 100.0, 0.01)
+                .err()
+                .map(|e| *e.kind()),
+            kind
+        );
+        let gains = AlphaBetaGammaGains::from_tracking_index(0.5);
+        assert_eq!(
+            AlphaBetaGammaFilter::new(dt, gains, 0.0, 0.0, 0.0)
+                .err()
+                .map(|e| *e.kind()),
+            kind
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use na::allocator::Allocator;
+use na::dimension::{DimMin, DimNameAdd, DimNameSum};
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, ErrorKind, ObservationModel, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+impl<R, SS> StateAndCovariance<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Append a clone `c = J x` of (part of) the state, for stochastic
+    /// cloning.
+    ///
+    /// `selection` is `J`; use the identity to clone the whole state. The
+    /// augmented state is `[x; J x]` with covariance
+    /// `[[P, P J^T], [J P, J P J^T]]`, so the clone is fully correlated with
+    /// the state it was taken from. Predict the augmented state with an
+    /// [`AugmentedTransitionModel`] to keep the clone fixed in time, and relate
+    /// it to later states with a [`RelativeObservationModel`].
+    pub fn augment_with_clone<CS>(
+        &self,
+        selection: &OMatrix<R, CS, SS>,
+    ) -> StateAndCovariance<R, DimNameSum<SS, CS>>
+    where
+        CS: DimName,
+        SS: DimNameAdd<CS>,
+        DefaultAllocator: Allocator<R, CS, SS>,
+        DefaultAllocator: Allocator<R, SS, CS>,
+        DefaultAllocator: Allocator<R, CS, CS>,
+        DefaultAllocator: Allocator<R, CS>,
+        DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+        DefaultAllocator: Allocator<R, DimNameSum<SS, CS>>,
+    {
+        let p = self.covariance();
+        let jp: OMatrix<R, CS, SS> = selection * p;
+        let jpjt: OMatrix<R, CS, CS> = &jp * selection.transpose();
+
+        let mut state = OVector::<R, DimNameSum<SS, CS>>::zeros();
+        state
+            .generic_view_mut((0, 0), (SS::name(), na::U1))
+            .copy_from(self.state());
+        state
+            .generic_view_mut((SS::dim(), 0), (CS::name(), na::U1))
+            .copy_from(&(selection * self.state()));
+
+        let mut covariance = OMatrix::<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>::zeros();
+        covariance
+            .generic_view_mut((0, 0), (SS::name(), SS::name()))
+            .copy_from(p);
+        covariance
+            .generic_view_mut((SS::dim(), 0), (CS::name(), SS::name()))
+            .copy_from(&jp);
+        covariance
+            .generic_view_mut((0, SS::dim()), (SS::name(), CS::name()))
+            .copy_from(&jp.transpose());
+        covariance
+            .generic_view_mut((SS::dim(), SS::dim()), (CS::name(), CS::name()))
+            .copy_from(&jpjt);
+        StateAndCovariance::new(state, covariance)
+    }
+
+    /// Keep the first `KS` components of the state, marginalizing out the
+    /// others.
+    ///
+    /// For a Gaussian, marginalizing is dropping the components and the
+    /// corresponding rows and columns of the covariance. Use this to remove
+    /// clones from an augmented state. Returns
+    /// `ErrorKind::DimensionMismatch` if `KS` is larger than `SS`.
+    pub fn marginalize<KS>(&self) -> Result<StateAndCovariance<R, KS>, Error>
+    where
+        KS: DimName,
+        DefaultAllocator: Allocator<R, KS, KS>,
+        DefaultAllocator: Allocator<R, KS>,
+    {
+        if KS::dim() > SS::dim() {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: KS::dim(),
+                actual: SS::dim(),
+            }
+            .into());
+        }
+        let state = self
+            .state()
+            .generic_view((0, 0), (KS::name(), na::U1))
+            .into_owned();
+        let covariance = self
+            .covariance()
+            .generic_view((0, 0), (KS::name(), KS::name()))
+            .into_owned();
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+}
+
+/// A transition model for a state augmented with clones
+///
+/// The original states evolve with the wrapped model and the `CS` clones,
+/// appended by
+/// [`augment_with_clone`](struct.StateAndCovariance.html#method.augment_with_clone),
+/// stay constant: `F = [[F, 0], [0, I]]` and `Q = [[Q, 0], [0, 0]]`.
+#[derive(Debug, Clone)]
+pub struct AugmentedTransitionModel<R, SS, CS>
+where
+    R: RealField,
+    SS: DimName + DimNameAdd<CS>,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+{
+    transition_model: OMatrix<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+    transition_model_transpose: OMatrix<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+    transition_noise_covariance: OMatrix<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+}
+
+impl<R, SS, CS> AugmentedTransitionModel<R, SS, CS>
+where
+    R: RealField,
+    SS: DimName + DimNameAdd<CS>,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+{
+    /// Create a new `AugmentedTransitionModel` from the model of the
+    /// original states.
+    pub fn new(model: &(impl TransitionModelLinearNoControl<R, SS> + ?Sized)) -> Self {
+        let mut transition_model = OMatrix::<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>::identity();
+        transition_model
+            .generic_view_mut((0, 0), (SS::name(), SS::name()))
+            .copy_from(model.F());
+        let mut transition_noise_covariance =
+            OMatrix::<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>::zeros();
+        transition_noise_covariance
+            .generic_view_mut((0, 0), (SS::name(), SS::name()))
+            .copy_from(model.Q());
+        Self {
+            transition_model_transpose: transition_model.transpose(),
+            transition_model,
+            transition_noise_covariance,
+        }
+    }
+}
+
+impl<R, SS, CS> TransitionModelLinearNoControl<R, DimNameSum<SS, CS>>
+    for AugmentedTransitionModel<R, SS, CS>
+where
+    R: RealField,
+    SS: DimName + DimNameAdd<CS>,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>>,
+{
+    fn F(&self) -> &OMatrix<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>> {
+        &self.transition_model
+    }
+    fn FT(&self) -> &OMatrix<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>> {
+        &self.transition_model_transpose
+    }
+    fn Q(&self) -> &OMatrix<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>> {
+        &self.transition_noise_covariance
+    }
+}
+
+/// An observation relating the current state to a cloned state
+///
+/// The observation is `z = H_s x + H_c c + v`, with `x` the current state
+/// and `c` the clone of an augmented state. For example, odometry measuring
+/// the displacement since the clone was taken has `H_s` selecting the
+/// position and `H_c = -I`.
+#[derive(Debug, Clone)]
+pub struct RelativeObservationModel<R, SS, CS, OS>
+where
+    R: RealField,
+    SS: DimName + DimNameAdd<CS>,
+    CS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, DimNameSum<SS, CS>>,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    observation_matrix: OMatrix<R, OS, DimNameSum<SS, CS>>,
+    observation_matrix_transpose: OMatrix<R, DimNameSum<SS, CS>, OS>,
+    observation_noise_covariance: OMatrix<R, OS, OS>,
+}
+
+impl<R, SS, CS, OS> RelativeObservationModel<R, SS, CS, OS>
+where
+    R: RealField,
+    SS: DimName + DimNameAdd<CS>,
+    CS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, OS, CS>,
+    DefaultAllocator: Allocator<R, OS, DimNameSum<SS, CS>>,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    /// Create a new `RelativeObservationModel` from `H_s`, `H_c` and the
+    /// observation noise covariance `R`.
+    pub fn new(
+        state_matrix: &OMatrix<R, OS, SS>,
+        clone_matrix: &OMatrix<R, OS, CS>,
+        observation_noise_covariance: OMatrix<R, OS, OS>,
+    ) -> Self {
+        let mut observation_matrix = OMatrix::<R, OS, DimNameSum<SS, CS>>::zeros();
+        observation_matrix
+            .generic_view_mut((0, 0), (OS::name(), SS::name()))
+            .copy_from(state_matrix);
+        observation_matrix
+            .generic_view_mut((0, SS::dim()), (OS::name(), CS::name()))
+            .copy_from(clone_matrix);
+        Self {
+            observation_matrix_transpose: observation_matrix.transpose(),
+            observation_matrix,
+            observation_noise_covariance,
+        }
+    }
+}
+
+impl<R, SS, CS, OS> ObservationModel<R, DimNameSum<SS, CS>, OS>
+    for RelativeObservationModel<R, SS, CS, OS>
+where
+    R: RealField,
+    SS: DimName + DimNameAdd<CS>,
+    CS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, DimNameSum<SS, CS>>,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>>,
+    DefaultAllocator: Allocator<R, OS, DimNameSum<SS, CS>>,
+    DefaultAllocator: Allocator<R, DimNameSum<SS, CS>, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    fn H(&self) -> &OMatrix<R, OS, DimNameSum<SS, CS>> {
+        &self.observation_matrix
+    }
+    fn HT(&self) -> &OMatrix<R, DimNameSum<SS, CS>, OS> {
+        &self.observation_matrix_transpose
+    }
+    fn R(&self) -> &OMatrix<R, OS, OS> {
+        &self.observation_noise_covariance
+    }
+}
+
+#[test]
+fn test_stochastic_cloning() {
+    use crate::CovarianceUpdateMethod;
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix3, Vector1, Vector2, Vector3, U1, U2};
+
+    struct ConstantVelocity {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for ConstantVelocity {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    let f = Matrix2::new(1.0, 1.0, 0.0, 1.0);
+    let motion = ConstantVelocity {
+        f,
+        ft: f.transpose(),
+        q: Matrix2::zeros(),
+    };
+
+    // Clone the position of [position, velocity].
+    let estimate = StateAndCovariance::new(Vector2::new(0.0, 1.0), Matrix2::identity());
+    let augmented = estimate.augment_with_clone(&Matrix1x2::new(1.0, 0.0));
+    #[rustfmt::skip]
+    let expected = Matrix3::new(
+        1.0, 0.0, 1.0,
+        0.0, 1.0, 0.0,
+        1.0, 0.0, 1.0,
+    );
+    assert_eq!(augmented.covariance(), &expected);
+
+    let augmented_motion = AugmentedTransitionModel::<f64, U2, U1>::new(&motion);
+    let prior = augmented_motion.predict(&augmented);
+    approx::assert_relative_eq!(prior.state(), &Vector3::new(1.0, 1.0, 0.0));
+
+    // Odometry: the displacement since the clone, observed as 3.
+    let odometry = RelativeObservationModel::<f64, U2, U1, U1>::new(
+        &Matrix1x2::new(1.0, 0.0),
+        &Matrix1::new(-1.0),
+        Matrix1::new(1.0),
+    );
+    let posterior = odometry
+        .update(
+            &prior,
+            &Vector1::new(3.0),
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap();
+    // The displacement is the velocity, with variance 1: S = 2, K = [1/2, 1/2, 0].
+    approx::assert_relative_eq!(
+        posterior.state(),
+        &Vector3::new(2.0, 2.0, 0.0),
+        epsilon = 1e-12
+    );
+
+    let marginal = posterior.marginalize::<U2>().unwrap();
+    approx::assert_relative_eq!(marginal.covariance()[(1, 1)], 0.5, epsilon = 1e-12);
+    assert!(posterior.marginalize::<na::U4>().is_err());
+}
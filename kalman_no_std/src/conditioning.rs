@@ -0,0 +1,253 @@
+use na::allocator::Allocator;
+use na::dimension::{DimDiff, DimSub, U1};
+use na::{DefaultAllocator, Dim, OMatrix, RealField};
+use nalgebra as na;
+
+/// Maximum number of iterations of the symmetric eigendecomposition.
+const MAX_EIGEN_ITERATIONS: usize = 1000;
+
+/// Counters of covariance repairs performed by [`CovarianceConditioning`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CovarianceHealth {
+    /// Number of times an asymmetric covariance was symmetrized.
+    pub symmetrized: u32,
+    /// Number of times eigenvalues were raised to the eigenvalue floor.
+    pub eigenvalues_clipped: u32,
+    /// Number of times eigenvalues were raised to cap the condition number.
+    pub condition_capped: u32,
+    /// Number of times the eigendecomposition did not converge.
+    pub eigen_failures: u32,
+    /// Number of times the covariance was reset to the default covariance.
+    pub resets: u32,
+    /// Number of observations that could not be used even after repair.
+    pub skipped_updates: u32,
+}
+
+impl CovarianceHealth {
+    /// Total number of repairs and skipped updates.
+    pub fn total(&self) -> u32 {
+        self.symmetrized
+            .saturating_add(self.eigenvalues_clipped)
+            .saturating_add(self.condition_capped)
+            .saturating_add(self.eigen_failures)
+            .saturating_add(self.resets)
+            .saturating_add(self.skipped_updates)
+    }
+}
+
+/// Policy for keeping covariance matrices symmetric and positive definite
+///
+/// Used by
+/// [`KalmanFilterNoControl::step_conditioned`](struct.KalmanFilterNoControl.html#method.step_conditioned)
+/// so that a filter degrades gracefully rather than stopping with an error
+/// such as `SingularInnovationCovariance`. Every repair is counted in a
+/// [`CovarianceHealth`].
+#[derive(Debug, Clone)]
+pub struct CovarianceConditioning<R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+{
+    /// Replace an asymmetric covariance by its symmetric part, `(P + P^T)/2`.
+    pub symmetrize: bool,
+    /// Raise eigenvalues below this value to it.
+    pub eigenvalue_floor: Option<R>,
+    /// Raise small eigenvalues so that the ratio of the largest to the
+    /// smallest eigenvalue is at most this value.
+    pub max_condition_number: Option<R>,
+    /// If an update still fails after repair, reset the prior covariance to
+    /// this value and retry. Otherwise, or if the retry also fails, the
+    /// observation is skipped and the prior is returned.
+    pub reset_covariance: Option<OMatrix<R, SS, SS>>,
+}
+
+impl<R, SS> CovarianceConditioning<R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+{
+    /// Create a new `CovarianceConditioning` which only symmetrizes.
+    pub fn new() -> Self {
+        Self {
+            symmetrize: true,
+            eigenvalue_floor: None,
+            max_condition_number: None,
+            reset_covariance: None,
+        }
+    }
+}
+
+impl<R, SS> Default for CovarianceConditioning<R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R, SS> CovarianceConditioning<R, SS>
+where
+    R: RealField,
+    SS: Dim + DimSub<U1>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, DimDiff<SS, U1>>,
+{
+    /// Repair `covariance` in place according to this policy.
+    ///
+    /// Returns `true` if the covariance was modified.
+    pub fn condition(
+        &self,
+        covariance: &mut OMatrix<R, SS, SS>,
+        health: &mut CovarianceHealth,
+    ) -> bool {
+        let mut repaired = false;
+        if self.symmetrize && *covariance != covariance.transpose() {
+            *covariance = covariance.symmetric_part();
+            health.symmetrized = health.symmetrized.saturating_add(1);
+            repaired = true;
+        }
+
+        if self.eigenvalue_floor.is_none() && self.max_condition_number.is_none() {
+            return repaired;
+        }
+
+        let eigen = match na::linalg::SymmetricEigen::try_new(
+            covariance.symmetric_part(),
+            R::default_epsilon(),
+            MAX_EIGEN_ITERATIONS,
+        ) {
+            Some(v) => v,
+            None => {
+                health.eigen_failures = health.eigen_failures.saturating_add(1);
+                return repaired;
+            }
+        };
+        let largest = eigen.eigenvalues.max();
+        let condition_floor = self
+            .max_condition_number
+            .as_ref()
+            .map(|max_condition| largest / max_condition.clone());
+        // Count the repair against whichever of the two floors is binding.
+        let (floor, capped) = match (self.eigenvalue_floor.clone(), condition_floor) {
+            (Some(a), Some(b)) if b > a => (b, true),
+            (Some(a), _) => (a, false),
+            (None, Some(b)) => (b, true),
+            (None, None) => return repaired,
+        };
+
+        if eigen.eigenvalues.iter().all(|value| *value >= floor) {
+            return repaired;
+        }
+        if capped {
+            health.condition_capped = health.condition_capped.saturating_add(1);
+        } else {
+            health.eigenvalues_clipped = health.eigenvalues_clipped.saturating_add(1);
+        }
+
+        let mut eigen = eigen;
+        eigen
+            .eigenvalues
+            .iter_mut()
+            .for_each(|value| *value = value.clone().max(floor.clone()));
+        *covariance = eigen.recompose().symmetric_part();
+        true
+    }
+}
+
+#[test]
+fn test_condition_covariance() {
+    use na::Matrix2;
+
+    let mut health = CovarianceHealth::default();
+    let mut conditioning = CovarianceConditioning::new();
+
+    // Eigenvalues are 3 and -1.
+    let mut covariance = Matrix2::new(1.0, 2.0, 2.0, 1.0);
+    assert!(!conditioning.condition(&mut covariance, &mut health));
+
+    conditioning.eigenvalue_floor = Some(1e-3);
+    assert!(conditioning.condition(&mut covariance, &mut health));
+    let eigenvalues = covariance.symmetric_eigenvalues();
+    approx::assert_relative_eq!(eigenvalues.min(), 1e-3, max_relative = 1e-9);
+    approx::assert_relative_eq!(eigenvalues.max(), 3.0, max_relative = 1e-9);
+    assert_eq!(health.eigenvalues_clipped, 1);
+
+    conditioning.max_condition_number = Some(100.0);
+    let mut covariance = Matrix2::new(1.0, 0.0, 1e-9, 1e-6);
+    assert!(conditioning.condition(&mut covariance, &mut health));
+    approx::assert_relative_eq!(
+        covariance,
+        Matrix2::new(1.0, 0.0, 0.0, 1e-2),
+        epsilon = 1e-9
+    );
+    assert_eq!(health.symmetrized, 1);
+    assert_eq!(health.condition_capped, 1);
+    assert_eq!(health.total(), 3);
+}
+
+#[test]
+fn test_step_conditioned() {
+    use crate::TransitionModelLinearNoControl;
+    use crate::{KalmanFilterNoControl, ObservationModel, StateAndCovariance};
+    use na::{Matrix2, Vector2, U2};
+
+    struct Identity {
+        identity: Matrix2<f64>,
+        noise: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for Identity {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.identity
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.identity
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.noise
+        }
+    }
+    impl ObservationModel<f64, U2, U2> for Identity {
+        fn H(&self) -> &Matrix2<f64> {
+            &self.identity
+        }
+        fn HT(&self) -> &Matrix2<f64> {
+            &self.identity
+        }
+        fn R(&self) -> &Matrix2<f64> {
+            &self.noise
+        }
+    }
+
+    let model = Identity {
+        identity: Matrix2::identity(),
+        noise: Matrix2::zeros(),
+    };
+    let kf = KalmanFilterNoControl::new(&model, &model);
+    let observation = Vector2::new(1.0, 2.0);
+
+    // With zero noise, an indefinite prior makes the innovation covariance
+    // indefinite and the update fails.
+    let bad = StateAndCovariance::new(Vector2::zeros(), Matrix2::new(1.0, 2.0, 2.0, 1.0));
+    assert!(kf.step(&bad, &observation).is_err());
+
+    let mut health = CovarianceHealth::default();
+    let mut conditioning = CovarianceConditioning::new();
+    let skipped = kf.step_conditioned(&bad, &observation, &conditioning, &mut health);
+    assert_eq!(skipped.state(), bad.state());
+    assert_eq!(health.skipped_updates, 1);
+
+    conditioning.eigenvalue_floor = Some(1e-6);
+    let posterior = kf.step_conditioned(&bad, &observation, &conditioning, &mut health);
+    approx::assert_relative_eq!(posterior.state(), &observation, epsilon = 1e-9);
+    // Both the prior and the collapsed posterior were clipped.
+    assert_eq!(health.eigenvalues_clipped, 2);
+    assert!(posterior.covariance().symmetric_eigenvalues().min() > 0.0);
+    assert_eq!(health.skipped_updates, 1);
+}
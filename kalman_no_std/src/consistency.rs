@@ -0,0 +1,415 @@
+use core::fmt;
+
+use na::allocator::Allocator;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{Error, ErrorKind, Innovation, StateAndCovariance};
+
+/// Quantile of the standard normal distribution
+///
+/// Uses the rational approximation 26.2.23 of Abramowitz and Stegun, with an
+/// absolute error below `4.5e-4`. `p` must be in `(0, 1)`.
+fn normal_quantile<R: RealField>(p: R) -> R {
+    let half: R = na::convert(0.5);
+    let (tail, sign) = if p < half {
+        (p, -R::one())
+    } else {
+        (R::one() - p, R::one())
+    };
+    let t = (na::convert::<f64, R>(-2.0) * tail.ln()).sqrt();
+    let c = |v: f64| -> R { na::convert(v) };
+    let numerator = c(2.515517) + t.clone() * (c(0.802853) + t.clone() * c(0.010328));
+    let denominator =
+        R::one() + t.clone() * (c(1.432788) + t.clone() * (c(0.189269) + t.clone() * c(0.001308)));
+    sign * (t - numerator / denominator)
+}
+
+/// Quantile of the chi-square distribution with `dof` degrees of freedom
+///
+/// Uses the Wilson-Hilferty approximation, which is accurate to a few
+/// percent for one degree of freedom and better for more. `p` must be in
+/// `(0, 1)`.
+pub fn chi_square_quantile<R: RealField>(p: R, dof: usize) -> R {
+    let k: R = na::convert(dof as f64);
+    let a = na::convert::<f64, R>(2.0 / 9.0) / k.clone();
+    let cube = R::one() - a.clone() + normal_quantile(p) * a.sqrt();
+    (k * cube.clone() * cube.clone() * cube).max(R::zero())
+}
+
+/// Two-sided interval containing a chi-square variable with probability
+/// `confidence`.
+fn chi_square_interval<R: RealField>(confidence: R, dof: usize) -> (R, R) {
+    let half: R = na::convert(0.5);
+    let tail = (R::one() - confidence) * half;
+    (
+        chi_square_quantile(tail.clone(), dof),
+        chi_square_quantile(R::one() - tail, dof),
+    )
+}
+
+/// Chi-square test of a normalized squared error (NEES or NIS)
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChiSquareTest<R: RealField> {
+    /// Number of steps.
+    pub count: usize,
+    /// Time-averaged value.
+    pub average: R,
+    /// Acceptance interval of the time-averaged value.
+    pub band: (R, R),
+    /// Number of steps whose value is outside the per-step acceptance
+    /// interval.
+    pub outside: usize,
+}
+
+impl<R: RealField> ChiSquareTest<R> {
+    /// Check if the time-averaged value is within its acceptance interval.
+    pub fn passed(&self) -> bool {
+        self.band.0 <= self.average && self.average <= self.band.1
+    }
+}
+
+/// Sample autocorrelation of the whitened innovations
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Autocorrelation<R: RealField, const LAGS: usize> {
+    /// Autocorrelation at lags `1..=LAGS`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    pub values: [R; LAGS],
+    /// Bound on the magnitude of each value for white innovations, at the
+    /// report confidence.
+    pub bound: R,
+}
+
+impl<R: RealField, const LAGS: usize> Autocorrelation<R, LAGS> {
+    /// Check if all values are within the bound.
+    pub fn passed(&self) -> bool {
+        self.values.iter().all(|v| v.clone().abs() <= self.bound)
+    }
+}
+
+/// Summary of the consistency of a filter
+///
+/// Made by [`ConsistencyEvaluator::report`] or [`evaluate_consistency`]. For
+/// a consistent filter, the normalized estimation error squared (NEES)
+/// `e^T P^-1 e` and the normalized innovation squared (NIS) `y^T S^-1 y` are
+/// chi-square distributed, and the innovations are white.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsistencyReport<R: RealField, const LAGS: usize> {
+    /// Probability of the acceptance intervals.
+    pub confidence: R,
+    /// NEES test, if any estimates were evaluated.
+    pub nees: Option<ChiSquareTest<R>>,
+    /// NIS test, if any innovations were evaluated.
+    pub nis: Option<ChiSquareTest<R>>,
+    /// Whiteness test, if more than `LAGS` innovations were evaluated.
+    pub autocorrelation: Option<Autocorrelation<R, LAGS>>,
+}
+
+impl<R: RealField, const LAGS: usize> ConsistencyReport<R, LAGS> {
+    /// Check if all available tests passed.
+    pub fn is_consistent(&self) -> bool {
+        self.nees.as_ref().is_none_or(ChiSquareTest::passed)
+            && self.nis.as_ref().is_none_or(ChiSquareTest::passed)
+            && self
+                .autocorrelation
+                .as_ref()
+                .is_none_or(Autocorrelation::passed)
+    }
+}
+
+impl<R: RealField + fmt::Display, const LAGS: usize> fmt::Display for ConsistencyReport<R, LAGS> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tests = [("NEES", &self.nees), ("NIS", &self.nis)];
+        for (name, test) in tests {
+            if let Some(t) = test {
+                writeln!(
+                    f,
+                    "{}: average {} in [{}, {}]: {}, {} of {} steps outside",
+                    name,
+                    t.average,
+                    t.band.0,
+                    t.band.1,
+                    if t.passed() { "pass" } else { "FAIL" },
+                    t.outside,
+                    t.count
+                )?;
+            }
+        }
+        if let Some(a) = &self.autocorrelation {
+            write!(f, "innovation autocorrelation (bound {}):", a.bound)?;
+            for v in a.values.iter() {
+                write!(f, " {}", v)?;
+            }
+            writeln!(f, ": {}", if a.passed() { "pass" } else { "FAIL" })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Accumulator<R> {
+    count: usize,
+    dof: usize,
+    sum: R,
+    outside: usize,
+}
+
+impl<R: RealField> Accumulator<R> {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            dof: 0,
+            sum: R::zero(),
+            outside: 0,
+        }
+    }
+
+    fn add(&mut self, value: R, dof: usize, confidence: R) {
+        let (low, high) = chi_square_interval(confidence, dof);
+        if value < low || value > high {
+            self.outside += 1;
+        }
+        self.count += 1;
+        self.dof += dof;
+        self.sum += value;
+    }
+
+    fn test(&self, confidence: R) -> Option<ChiSquareTest<R>> {
+        if self.count == 0 {
+            return None;
+        }
+        let count: R = na::convert(self.count as f64);
+        let (low, high) = chi_square_interval(confidence, self.dof);
+        Some(ChiSquareTest {
+            count: self.count,
+            average: self.sum.clone() / count.clone(),
+            band: (low / count.clone(), high / count),
+            outside: self.outside,
+        })
+    }
+}
+
+/// Accumulates NEES, NIS and innovation autocorrelation over a run
+///
+/// Add the estimate and the innovation of each step as the filter runs; the
+/// per-step values are returned, and [`report`](#method.report) summarizes
+/// them. Nothing is allocated, so this works in `no_std`. The
+/// autocorrelation of the whitened innovations is computed for lags
+/// `1..=LAGS`.
+#[derive(Debug, Clone)]
+pub struct ConsistencyEvaluator<R, OS, const LAGS: usize>
+where
+    R: RealField,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    confidence: R,
+    nees: Accumulator<R>,
+    nis: Accumulator<R>,
+    /// Whitened innovations, most recent first.
+    history: [OVector<R, OS>; LAGS],
+    lag_sums: [R; LAGS],
+    energy: R,
+}
+
+impl<R, OS, const LAGS: usize> ConsistencyEvaluator<R, OS, LAGS>
+where
+    R: RealField,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    /// Create a new `ConsistencyEvaluator`.
+    ///
+    /// `confidence` is the probability of the acceptance intervals, for
+    /// example `0.95`.
+    pub fn new(confidence: R) -> Self {
+        Self {
+            confidence,
+            nees: Accumulator::new(),
+            nis: Accumulator::new(),
+            history: core::array::from_fn(|_| OVector::<R, OS>::zeros()),
+            lag_sums: core::array::from_fn(|_| R::zero()),
+            energy: R::zero(),
+        }
+    }
+
+    /// Add an estimate and the true state, returning the NEES.
+    pub fn add_estimate<SS>(
+        &mut self,
+        truth: &OVector<R, SS>,
+        estimate: &StateAndCovariance<R, SS>,
+    ) -> Result<R, Error>
+    where
+        SS: DimName,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+    {
+        let error = truth - estimate.state();
+        let whitened = whiten(estimate.covariance(), error)
+            .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?;
+        let nees = whitened.norm_squared();
+        self.nees
+            .add(nees.clone(), SS::dim(), self.confidence.clone());
+        Ok(nees)
+    }
+
+    /// Add the innovation of an update, returning the NIS.
+    ///
+    /// Innovations must be added in time order.
+    pub fn add_innovation(&mut self, innovation: &Innovation<R, OS>) -> Result<R, Error>
+    where
+        DefaultAllocator: Allocator<R, OS, OS>,
+    {
+        let whitened = whiten(innovation.covariance(), innovation.residual().clone())
+            .ok_or(ErrorKind::SingularInnovationCovariance)?;
+        let nis = whitened.norm_squared();
+        let previous = self.nis.count;
+        self.nis
+            .add(nis.clone(), OS::dim(), self.confidence.clone());
+
+        for (lag, (sum, past)) in self
+            .lag_sums
+            .iter_mut()
+            .zip(self.history.iter())
+            .enumerate()
+        {
+            if lag < previous {
+                *sum += whitened.dot(past);
+            }
+        }
+        self.energy += nis.clone();
+        self.history.rotate_right(1);
+        if let Some(latest) = self.history.first_mut() {
+            *latest = whitened;
+        }
+        Ok(nis)
+    }
+
+    /// Summarize the estimates and innovations added so far.
+    pub fn report(&self) -> ConsistencyReport<R, LAGS> {
+        let autocorrelation = if self.nis.count > LAGS && self.energy > R::zero() {
+            let samples: R = na::convert((self.nis.count * OS::dim()) as f64);
+            let half: R = na::convert(0.5);
+            let z = normal_quantile(half.clone() + half * self.confidence.clone());
+            Some(Autocorrelation {
+                values: core::array::from_fn(|lag| {
+                    self.lag_sums
+                        .get(lag)
+                        .map(|sum| sum.clone() / self.energy.clone())
+                        .unwrap_or_else(R::zero)
+                }),
+                bound: z / samples.sqrt(),
+            })
+        } else {
+            None
+        };
+        ConsistencyReport {
+            confidence: self.confidence.clone(),
+            nees: self.nees.test(self.confidence.clone()),
+            nis: self.nis.test(self.confidence.clone()),
+            autocorrelation,
+        }
+    }
+}
+
+/// Evaluate the consistency of a filter run
+///
+/// `truth` and `estimates` are the true states and the estimates at each
+/// step and `innovations` the innovations of the updates, in time order.
+/// Returns `ErrorKind::DimensionMismatch` if `truth` and `estimates` differ
+/// in length.
+pub fn evaluate_consistency<R, SS, OS, const LAGS: usize>(
+    truth: &[OVector<R, SS>],
+    estimates: &[StateAndCovariance<R, SS>],
+    innovations: &[Innovation<R, OS>],
+    confidence: R,
+) -> Result<ConsistencyReport<R, LAGS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    if truth.len() != estimates.len() {
+        return Err(ErrorKind::DimensionMismatch {
+            expected: truth.len(),
+            actual: estimates.len(),
+        }
+        .into());
+    }
+    let mut evaluator = ConsistencyEvaluator::<R, OS, LAGS>::new(confidence);
+    for (i, (t, e)) in truth.iter().zip(estimates).enumerate() {
+        evaluator.add_estimate(t, e).map_err(|e| e.at_step(i))?;
+    }
+    for (i, innovation) in innovations.iter().enumerate() {
+        evaluator
+            .add_innovation(innovation)
+            .map_err(|e| e.at_step(i))?;
+    }
+    Ok(evaluator.report())
+}
+
+/// Compute `L^-1 v`, where `L` is the Cholesky factor of `covariance`.
+fn whiten<R, D>(covariance: &OMatrix<R, D, D>, v: OVector<R, D>) -> Option<OVector<R, D>>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+    DefaultAllocator: Allocator<R, D>,
+{
+    na::linalg::Cholesky::new(covariance.clone())?
+        .l()
+        .solve_lower_triangular(&v)
+}
+
+#[test]
+fn test_consistency() {
+    use na::{Matrix1, Matrix2, Vector1, Vector2, U1};
+
+    // Tabulated quantiles of chi-square with 10 degrees of freedom.
+    approx::assert_relative_eq!(chi_square_quantile(0.5, 10), 9.342, max_relative = 1e-2);
+    approx::assert_relative_eq!(chi_square_quantile(0.975, 10), 20.483, max_relative = 1e-2);
+    approx::assert_relative_eq!(chi_square_quantile(0.025, 10), 3.247, max_relative = 1e-2);
+
+    // Errors of one standard deviation in each of two components: NEES is 2,
+    // its expected value.
+    let truth = [Vector2::new(1.0, -1.0), Vector2::new(-1.0, 1.0)];
+    let estimates = [
+        StateAndCovariance::new(Vector2::zeros(), Matrix2::identity()),
+        StateAndCovariance::new(Vector2::zeros(), Matrix2::identity()),
+    ];
+    // Innovations alternating in sign are strongly anti-correlated.
+    let innovations: [Innovation<f64, U1>; 20] = core::array::from_fn(|i| {
+        let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+        Innovation::new(Vector1::new(sign), Matrix1::new(1.0))
+    });
+
+    let report: ConsistencyReport<f64, 2> =
+        evaluate_consistency(&truth, &estimates, &innovations, 0.95).unwrap();
+    let nees = report.nees.as_ref().unwrap();
+    assert_eq!((nees.count, nees.outside), (2, 0));
+    approx::assert_relative_eq!(nees.average, 2.0);
+    assert!(nees.passed());
+    assert!(report.nis.as_ref().unwrap().passed());
+    let autocorrelation = report.autocorrelation.as_ref().unwrap();
+    approx::assert_relative_eq!(autocorrelation.values[0], -19.0 / 20.0);
+    approx::assert_relative_eq!(autocorrelation.values[1], 18.0 / 20.0);
+    assert!(!autocorrelation.passed());
+    assert!(!report.is_consistent());
+
+    // An overconfident covariance fails the NEES test.
+    let overconfident = [
+        StateAndCovariance::new(Vector2::zeros(), Matrix2::identity() * 0.01),
+        StateAndCovariance::new(Vector2::zeros(), Matrix2::identity() * 0.01),
+    ];
+    let report: ConsistencyReport<f64, 1> =
+        evaluate_consistency(&truth, &overconfident, &innovations[..0], 0.95).unwrap();
+    assert!(!report.nees.unwrap().passed());
+    assert!(report.nis.is_none());
+}
@@ -0,0 +1,416 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    CovarianceUpdateMethod, Error, ErrorKind, Innovation, ObservationModel, StateAndCovariance,
+    UpdateWorkspace,
+};
+
+/// Linear inequality constraints `D x <= d` on the state
+///
+/// Each of the `NC` rows of `D` and `d` is one constraint. A lower bound
+/// `x_i >= l` is the row `-x_i <= -l`.
+#[derive(Debug, Clone)]
+pub struct LinearConstraints<R, SS, NC>
+where
+    R: RealField,
+    SS: DimName,
+    NC: DimName,
+    DefaultAllocator: Allocator<R, NC, SS>,
+    DefaultAllocator: Allocator<R, NC>,
+{
+    matrix: OMatrix<R, NC, SS>,
+    bound: OVector<R, NC>,
+}
+
+impl<R, SS, NC> LinearConstraints<R, SS, NC>
+where
+    R: RealField,
+    SS: DimName,
+    NC: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, NC, SS>,
+    DefaultAllocator: Allocator<R, SS, NC>,
+    DefaultAllocator: Allocator<R, NC, NC>,
+    DefaultAllocator: Allocator<R, NC>,
+{
+    /// Create new `LinearConstraints` `matrix * x <= bound`.
+    pub fn new(matrix: OMatrix<R, NC, SS>, bound: OVector<R, NC>) -> Self {
+        Self { matrix, bound }
+    }
+
+    /// Get the constraint matrix, `D`.
+    pub fn matrix(&self) -> &OMatrix<R, NC, SS> {
+        &self.matrix
+    }
+
+    /// Get the constraint bound, `d`.
+    pub fn bound(&self) -> &OVector<R, NC> {
+        &self.bound
+    }
+
+    /// Check if `state` satisfies all constraints.
+    pub fn is_satisfied(&self, state: &OVector<R, SS>) -> bool {
+        (&self.matrix * state)
+            .iter()
+            .zip(self.bound.iter())
+            .all(|(v, b)| v <= b)
+    }
+
+    /// Project an estimate onto the constraints, weighted by its covariance.
+    ///
+    /// The projected state minimizes `(x - x^)^T P^-1 (x - x^)` subject to
+    /// `D x <= d`. With `D_a` the active constraints, the covariance becomes
+    /// `P - P D_a^T (D_a P D_a^T)^-1 D_a P`, the covariance of the estimate
+    /// conditioned on the active constraints holding with equality. An
+    /// estimate which satisfies the constraints is returned unchanged.
+    ///
+    /// Returns `ErrorKind::CovarianceNotPositiveSemiDefinite` if the active
+    /// constraints are linearly dependent under `P` and
+    /// `ErrorKind::ConstraintsNotSatisfied` if the projected state still
+    /// violates a constraint, as when the constraints are infeasible or the
+    /// search for the active set did not converge.
+    pub fn project(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        if self.is_satisfied(estimate.state()) {
+            return Ok(estimate.clone());
+        }
+        let p = estimate.covariance();
+        let pdt: OMatrix<R, SS, NC> = p * self.matrix.transpose();
+        let dpdt: OMatrix<R, NC, NC> = &self.matrix * &pdt;
+        let violation: OVector<R, NC> = &self.matrix * estimate.state() - &self.bound;
+
+        // Find the active set by coordinate ascent on the dual problem
+        // (Hildreth's method): maximize `-l^T M l / 2 + l^T b` for `l >= 0`,
+        // with `M = D P D^T` and `b = D x^ - d`.
+        let mut multipliers = OVector::<R, NC>::zeros();
+        let tolerance = R::default_epsilon().sqrt();
+        for _ in 0..MAX_SWEEPS {
+            let mut largest_change = R::zero();
+            for (i, (m_col, b)) in dpdt.column_iter().zip(violation.iter()).enumerate() {
+                let m_ii = m_col.get(i).cloned().unwrap_or_else(R::zero);
+                if m_ii <= R::zero() {
+                    continue;
+                }
+                let gradient = b.clone() - m_col.dot(&multipliers);
+                if let Some(l) = multipliers.get_mut(i) {
+                    let updated = (l.clone() + gradient / m_ii).max(R::zero());
+                    largest_change = largest_change.max((updated.clone() - l.clone()).abs());
+                    *l = updated;
+                }
+            }
+            if largest_change <= tolerance.clone() * (R::one() + multipliers.amax()) {
+                break;
+            }
+        }
+
+        // Project exactly onto the active set, with the inactive rows of `D`
+        // zeroed and the inactive block of `D P D^T` replaced by the identity.
+        let mut d_active = self.matrix.clone();
+        let mut m_active = dpdt;
+        for (i, l) in multipliers.iter().enumerate() {
+            if *l > R::zero() {
+                continue;
+            }
+            d_active.row_mut(i).fill(R::zero());
+            m_active.row_mut(i).fill(R::zero());
+            m_active.column_mut(i).fill(R::zero());
+            if let Some(m_ii) = m_active.get_mut((i, i)) {
+                *m_ii = R::one();
+            }
+        }
+        let m_chol = na::linalg::Cholesky::new(m_active)
+            .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?;
+        let pdt_active: OMatrix<R, SS, NC> = p * d_active.transpose();
+        let active_violation = &d_active * estimate.state()
+            - self.bound.zip_map(
+                &multipliers,
+                |b, l| {
+                    if l > R::zero() {
+                        b
+                    } else {
+                        R::zero()
+                    }
+                },
+            );
+        let state = estimate.state() - &pdt_active * m_chol.solve(&active_violation);
+        let covariance = p - &pdt_active * m_chol.solve(&pdt_active.transpose());
+
+        // Allow for the rounding of the projection, relative to the size of
+        // each term of the constraint.
+        let value: OVector<R, NC> = &self.matrix * &state;
+        let scale: OVector<R, NC> = self.matrix.abs() * state.abs();
+        let satisfied = value
+            .iter()
+            .zip(scale.iter())
+            .zip(self.bound.iter())
+            .all(|((v, s), b)| {
+                let slack = tolerance.clone() * (s.clone() + b.clone().abs() + R::one());
+                v.clone() <= b.clone() + slack
+            });
+        if !satisfied {
+            return Err(ErrorKind::ConstraintsNotSatisfied.into());
+        }
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+}
+
+/// Maximum number of sweeps of the dual coordinate ascent in
+/// [`LinearConstraints::project`].
+const MAX_SWEEPS: usize = 100;
+
+/// An observation model whose update result is projected onto linear
+/// constraints
+///
+/// Wrap any observation model to keep the estimates of the filters in this
+/// crate within physical bounds. See [`LinearConstraints::project`].
+#[derive(Debug, Clone)]
+pub struct ConstrainedObservationModel<R, SS, NC, M>
+where
+    R: RealField,
+    SS: DimName,
+    NC: DimName,
+    DefaultAllocator: Allocator<R, NC, SS>,
+    DefaultAllocator: Allocator<R, NC>,
+{
+    model: M,
+    constraints: LinearConstraints<R, SS, NC>,
+}
+
+impl<R, SS, NC, M> ConstrainedObservationModel<R, SS, NC, M>
+where
+    R: RealField,
+    SS: DimName,
+    NC: DimName,
+    DefaultAllocator: Allocator<R, NC, SS>,
+    DefaultAllocator: Allocator<R, NC>,
+{
+    /// Create a new `ConstrainedObservationModel`.
+    pub fn new(model: M, constraints: LinearConstraints<R, SS, NC>) -> Self {
+        Self { model, constraints }
+    }
+
+    /// Get the wrapped observation model.
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// Get the constraints.
+    pub fn constraints(&self) -> &LinearConstraints<R, SS, NC> {
+        &self.constraints
+    }
+}
+
+impl<R, SS, OS, NC, M> ObservationModel<R, SS, OS> for ConstrainedObservationModel<R, SS, NC, M>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    NC: DimName,
+    M: ObservationModel<R, SS, OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+    DefaultAllocator: Allocator<R, NC, SS>,
+    DefaultAllocator: Allocator<R, SS, NC>,
+    DefaultAllocator: Allocator<R, NC, NC>,
+    DefaultAllocator: Allocator<R, NC>,
+{
+    #[inline]
+    fn predict_observation(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        self.model.predict_observation(state)
+    }
+    #[inline]
+    fn H(&self) -> &OMatrix<R, OS, SS> {
+        self.model.H()
+    }
+    #[inline]
+    fn HT(&self) -> &OMatrix<R, SS, OS> {
+        self.model.HT()
+    }
+    #[inline]
+    fn R(&self) -> &OMatrix<R, OS, OS> {
+        self.model.R()
+    }
+    #[inline]
+    fn innovation(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Innovation<R, OS> {
+        self.model.innovation(prior, observation)
+    }
+
+    /// Update with the wrapped model and project the result onto the
+    /// constraints.
+    fn update(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let posterior = self.model.update(prior, observation, covariance_method)?;
+        self.constraints.project(&posterior)
+    }
+
+    /// Update in place with the wrapped model and project the result onto
+    /// the constraints.
+    ///
+    /// The wrapped model updates a copy, so that `estimate` is unchanged if
+    /// the projection fails.
+    fn update_in_place(
+        &self,
+        estimate: &mut StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_method: CovarianceUpdateMethod,
+        workspace: &mut UpdateWorkspace<R, SS, OS>,
+    ) -> Result<(), Error> {
+        let mut posterior = estimate.clone();
+        self.model
+            .update_in_place(&mut posterior, observation, covariance_method, workspace)?;
+        *estimate = self.constraints.project(&posterior)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn evaluate(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        self.model.evaluate(state)
+    }
+}
+
+#[test]
+fn test_project() {
+    use na::{Matrix1x2, Matrix2, Matrix2x3, Vector1, Vector2, Vector3};
+
+    // x0 + x1 <= 1.
+    let sum = LinearConstraints::new(Matrix1x2::new(1.0, 1.0), Vector1::new(1.0));
+    let estimate = StateAndCovariance::new(Vector2::new(1.0, 1.0), Matrix2::identity());
+    let projected = sum.project(&estimate).unwrap();
+    approx::assert_relative_eq!(projected.state(), &Vector2::new(0.5, 0.5), epsilon = 1e-12);
+    #[rustfmt::skip]
+    let expected = Matrix2::new(
+        0.5, -0.5,
+        -0.5, 0.5,
+    );
+    approx::assert_relative_eq!(projected.covariance(), &expected, epsilon = 1e-12);
+
+    // The less certain component moves more.
+    let weighted =
+        StateAndCovariance::new(Vector2::new(1.0, 1.0), Matrix2::new(1.0, 0.0, 0.0, 3.0));
+    let projected = sum.project(&weighted).unwrap();
+    approx::assert_relative_eq!(
+        projected.state(),
+        &Vector2::new(0.75, 0.25),
+        epsilon = 1e-12
+    );
+
+    // Box 0 <= x0 <= 2 and x1 <= 0.5: only x1 <= 0.5 is active.
+    #[rustfmt::skip]
+    let boxed = LinearConstraints::new(
+        Matrix2x3::new(
+            -1.0, 1.0, 0.0,
+            0.0, 0.0, 1.0,
+        )
+        .transpose(),
+        Vector3::new(0.0, 2.0, 0.5),
+    );
+    let estimate = StateAndCovariance::new(Vector2::new(0.2, 0.9), Matrix2::identity());
+    let projected = boxed.project(&estimate).unwrap();
+    approx::assert_relative_eq!(projected.state(), &Vector2::new(0.2, 0.5), epsilon = 1e-12);
+    assert!(boxed.is_satisfied(projected.state()));
+    assert_eq!(
+        boxed.project(&projected).unwrap().state(),
+        projected.state()
+    );
+
+    // x0 <= 0, x1 <= 0 and x0 + 0.1 x1 >= 1 cannot all hold, so the
+    // projection ends outside of the constraints.
+    #[rustfmt::skip]
+    let infeasible = LinearConstraints::new(
+        Matrix2x3::new(
+            1.0, 0.0, -1.0,
+            0.0, 1.0, -0.1,
+        )
+        .transpose(),
+        Vector3::new(0.0, 0.0, -1.0),
+    );
+    let err = infeasible.project(&estimate).unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::ConstraintsNotSatisfied);
+}
+
+#[test]
+fn test_constrained_update_in_place() {
+    use na::{
+        Matrix1, Matrix1x2, Matrix2, Matrix2x1, Matrix2x3, Vector1, Vector2, Vector3, U1, U2,
+    };
+
+    struct Position {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for Position {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+    let h = Matrix1x2::new(1.0, 0.0);
+    let position = Position {
+        h,
+        ht: h.transpose(),
+        r: Matrix1::new(0.1),
+    };
+    let method = CovarianceUpdateMethod::JosephForm;
+    let prior = StateAndCovariance::new(Vector2::new(0.2, 0.9), Matrix2::identity());
+    let observation = Vector1::new(0.5);
+
+    // x1 <= 0.5: both updates agree.
+    let upper = LinearConstraints::new(Matrix1x2::new(0.0, 1.0), Vector1::new(0.5));
+    let constrained = ConstrainedObservationModel::new(&position, upper);
+    let expected = constrained.update(&prior, &observation, method).unwrap();
+    let mut estimate = prior.clone();
+    let mut workspace = UpdateWorkspace::new(U2, U1);
+    constrained
+        .update_in_place(&mut estimate, &observation, method, &mut workspace)
+        .unwrap();
+    approx::assert_relative_eq!(estimate.state(), expected.state(), epsilon = 1e-12);
+    approx::assert_relative_eq!(
+        estimate.covariance(),
+        expected.covariance(),
+        epsilon = 1e-12
+    );
+
+    // A failed projection leaves the estimate unchanged.
+    #[rustfmt::skip]
+    let infeasible = LinearConstraints::new(
+        Matrix2x3::new(
+            1.0, 0.0, -1.0,
+            0.0, 1.0, -0.1,
+        )
+        .transpose(),
+        Vector3::new(0.0, 0.0, -1.0),
+    );
+    let constrained = ConstrainedObservationModel::new(&position, infeasible);
+    let mut estimate = prior.clone();
+    let err = constrained
+        .update_in_place(&mut estimate, &observation, method, &mut workspace)
+        .unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::ConstraintsNotSatisfied);
+    assert_eq!(estimate.state(), prior.state());
+    assert_eq!(estimate.covariance(), prior.covariance());
+}
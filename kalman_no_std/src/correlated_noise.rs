@@ -0,0 +1,285 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, Dim, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    shape, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Update with measurement noise correlated with the process noise
+///
+/// `cross_covariance` is `M = E[w v^T]` between the process noise `w` of the
+/// prediction that led to `prior` and the measurement noise `v`. The gain is
+/// `K = (P H^T + M) (H P H^T + H M + M^T H^T + R)^-1`.
+pub(crate) fn update_correlated<R, SS, OS>(
+    observation_model: &(impl ObservationModel<R, SS, OS> + ?Sized),
+    prior: &StateAndCovariance<R, SS>,
+    observation: &OVector<R, OS>,
+    cross_covariance: &OMatrix<R, SS, OS>,
+    covariance_method: CovarianceUpdateMethod,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    shape::check_observation(observation_model, prior, observation)?;
+    shape::check_shape(observation_model.HT().shape(), cross_covariance.shape())?;
+    let h = observation_model.H();
+    let p = prior.covariance();
+    let m = cross_covariance;
+
+    let hm: OMatrix<R, OS, OS> = h * m;
+    let s = h * p * observation_model.HT() + observation_model.R() + &hm + hm.transpose();
+    let covariance_gain: OMatrix<R, SS, OS> = p * observation_model.HT() + m;
+    let k_gain = gain(covariance_gain.clone(), s)?;
+
+    let innovation = observation - observation_model.predict_observation(prior.state());
+    let state = prior.state() + &k_gain * innovation;
+
+    let covariance = match covariance_method {
+        CovarianceUpdateMethod::JosephForm => {
+            // Valid for any gain: the error is `(I - K H) e - K v`.
+            let kh: OMatrix<R, SS, SS> = &k_gain * h;
+            let (rows, columns) = kh.shape_generic();
+            let one_minus_kh = OMatrix::<R, SS, SS>::identity_generic(rows, columns) - kh;
+            let cross: OMatrix<R, SS, SS> = &one_minus_kh * m * k_gain.transpose();
+            &one_minus_kh * p * one_minus_kh.transpose()
+                + &k_gain * observation_model.R() * k_gain.transpose()
+                - &cross
+                - cross.transpose()
+        }
+        CovarianceUpdateMethod::OptimalKalman => p - &k_gain * covariance_gain.transpose(),
+        CovarianceUpdateMethod::OptimalKalmanForcedSymmetric => {
+            (p - &k_gain * covariance_gain.transpose()).symmetric_part()
+        }
+    };
+    finish(state, covariance)
+}
+
+/// Predict and update with colored measurement noise by measurement
+/// differencing
+///
+/// The measurement noise follows `v[k+1] = Psi v[k] + xi[k]`, where the white
+/// noise `xi` has the covariance `R` of `observation_model`. The derived
+/// measurement `z[k] - Psi z[k-1] = (H F - Psi H) x[k-1] + H w + xi` has white
+/// noise, correlated with the process noise `w`, and is used in a one-step
+/// predictor (Bryson and Henrikson, 1968).
+pub(crate) fn step_colored<R, SS, OS>(
+    transition_model: &(impl TransitionModelLinearNoControl<R, SS> + ?Sized),
+    observation_model: &(impl ObservationModel<R, SS, OS> + ?Sized),
+    previous_estimate: &StateAndCovariance<R, SS>,
+    previous_observation: &OVector<R, OS>,
+    observation: &OVector<R, OS>,
+    noise_transition: &OMatrix<R, OS, OS>,
+    covariance_method: CovarianceUpdateMethod,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    shape::check_observation(observation_model, previous_estimate, observation)?;
+    shape::check_shape(observation.shape(), previous_observation.shape())?;
+    shape::check_shape(observation_model.R().shape(), noise_transition.shape())?;
+    let f = transition_model.F();
+    let q = transition_model.Q();
+    let h = observation_model.H();
+    let ht = observation_model.HT();
+    let p = previous_estimate.covariance();
+    let x = previous_estimate.state();
+
+    // The derived measurement `z' = H' x + v'` with `Cov(v') = R'` and
+    // `E[w v'^T] = C`.
+    let h_derived: OMatrix<R, OS, SS> = h * f - noise_transition * h;
+    let c: OMatrix<R, SS, OS> = q * ht;
+    let r_derived = h * &c + observation_model.R();
+
+    let s = &h_derived * p * h_derived.transpose() + &r_derived;
+    let covariance_gain: OMatrix<R, SS, OS> = f * p * h_derived.transpose() + &c;
+    let k_gain = gain(covariance_gain.clone(), s)?;
+
+    let predicted = f * x;
+    let derived_observation = observation - noise_transition * previous_observation;
+    let derived_prediction = observation_model.predict_observation(&predicted)
+        - noise_transition * observation_model.predict_observation(x);
+    let state = predicted + &k_gain * (derived_observation - derived_prediction);
+
+    let covariance = match covariance_method {
+        CovarianceUpdateMethod::JosephForm => {
+            // Valid for any gain: the error is `(F - K H') e + w - K v'`.
+            let a = f - &k_gain * &h_derived;
+            let cross: OMatrix<R, SS, SS> = &c * k_gain.transpose();
+            &a * p * a.transpose() + q + &k_gain * r_derived * k_gain.transpose()
+                - &cross
+                - cross.transpose()
+        }
+        CovarianceUpdateMethod::OptimalKalman => {
+            f * p * transition_model.FT() + q - &k_gain * covariance_gain.transpose()
+        }
+        CovarianceUpdateMethod::OptimalKalmanForcedSymmetric => {
+            let covariance =
+                f * p * transition_model.FT() + q - &k_gain * covariance_gain.transpose();
+            covariance.symmetric_part()
+        }
+    };
+    finish(state, covariance)
+}
+
+/// Compute `covariance_gain * s^-1` for symmetric positive definite `s`.
+fn gain<R, SS, OS>(
+    covariance_gain: OMatrix<R, SS, OS>,
+    s: OMatrix<R, OS, OS>,
+) -> Result<OMatrix<R, SS, OS>, Error>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    let s_chol = na::linalg::Cholesky::new(s).ok_or(ErrorKind::SingularInnovationCovariance)?;
+    Ok(s_chol.solve(&covariance_gain.transpose()).transpose())
+}
+
+fn finish<R, SS>(
+    state: OVector<R, SS>,
+    covariance: OMatrix<R, SS, SS>,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    if !state.iter().all(|x| x.is_finite()) {
+        return Err(ErrorKind::NonFiniteState.into());
+    }
+    #[cfg(debug_assertions)]
+    {
+        if approx::relative_ne!(
+            covariance,
+            &covariance.transpose(),
+            max_relative = na::convert(1e-5)
+        ) {
+            return Err(ErrorKind::AsymmetricCovariance.into());
+        }
+    }
+    Ok(StateAndCovariance::new(state, covariance))
+}
+
+#[test]
+fn test_correlated_noise() {
+    use crate::KalmanFilterNoControl;
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    struct ConstantVelocity {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for ConstantVelocity {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    struct Position {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for Position {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let f = Matrix2::new(1.0, 0.5, 0.0, 1.0);
+    let motion = ConstantVelocity {
+        f,
+        ft: f.transpose(),
+        q: Matrix2::new(0.1, 0.05, 0.05, 0.2),
+    };
+    let position = Position {
+        h: Matrix1x2::new(1.0, 0.0),
+        ht: Matrix2x1::new(1.0, 0.0),
+        r: Matrix1::new(1.0),
+    };
+    let kf = KalmanFilterNoControl::new(&motion, &position);
+    let previous = StateAndCovariance::new(Vector2::new(1.0, -1.0), Matrix2::identity());
+    let methods = [
+        CovarianceUpdateMethod::JosephForm,
+        CovarianceUpdateMethod::OptimalKalman,
+        CovarianceUpdateMethod::OptimalKalmanForcedSymmetric,
+    ];
+
+    // Without correlation or color, both reduce to the usual step.
+    let expected = kf.step(&previous, &Vector1::new(2.0)).unwrap();
+    for method in methods {
+        let correlated = kf
+            .step_correlated(&previous, &Vector1::new(2.0), &Matrix2x1::zeros(), method)
+            .unwrap();
+        let colored = kf
+            .step_colored(
+                &previous,
+                &Vector1::new(7.0),
+                &Vector1::new(2.0),
+                &Matrix1::zeros(),
+                method,
+            )
+            .unwrap();
+        for estimate in [&correlated, &colored] {
+            approx::assert_relative_eq!(estimate.state(), expected.state(), epsilon = 1e-12);
+            approx::assert_relative_eq!(
+                estimate.covariance(),
+                expected.covariance(),
+                epsilon = 1e-12
+            );
+        }
+    }
+
+    // Scalar position with unit prior variance and R = 1, M = 0.5: S = 3,
+    // K = 0.5 and the posterior variance is 0.25 for any method.
+    let prior = StateAndCovariance::new(Vector2::new(0.0, 0.0), Matrix2::identity());
+    for method in methods {
+        let posterior = update_correlated(
+            &position,
+            &prior,
+            &Vector1::new(2.0),
+            &Matrix2x1::new(0.5, 0.0),
+            method,
+        )
+        .unwrap();
+        approx::assert_relative_eq!(posterior.state()[0], 1.0, epsilon = 1e-12);
+        approx::assert_relative_eq!(posterior.covariance()[(0, 0)], 0.25, epsilon = 1e-12);
+    }
+}
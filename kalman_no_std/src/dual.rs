@@ -0,0 +1,740 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::{
+    Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
+};
+
+use approx::{AbsDiffEq, RelativeEq, UlpsEq};
+use na::allocator::Allocator;
+use na::{ComplexField, DefaultAllocator, DimName, Field, OMatrix, OVector, RealField, SimdValue};
+use nalgebra as na;
+use num_traits::{FromPrimitive, Num, One, Signed, Zero};
+use simba::scalar::SubsetOf;
+
+/// A dual number `value + derivative ε` with `ε² = 0`, for forward-mode
+/// automatic differentiation
+///
+/// `Dual<T>` implements `RealField`, so a function written generically over
+/// `R: RealField` can be evaluated with `Dual<T>` arguments. The derivative
+/// part of the result is then the exact derivative with respect to the
+/// argument whose derivative part was seeded with one. See
+/// [`dual_jacobian`].
+///
+/// Comparisons consider only the value part.
+#[derive(Debug, Clone, Copy)]
+pub struct Dual<T> {
+    value: T,
+    derivative: T,
+}
+
+impl<T: RealField> Dual<T> {
+    /// Create a new `Dual` number.
+    #[inline]
+    pub fn new(value: T, derivative: T) -> Self {
+        Self { value, derivative }
+    }
+
+    /// Create a `Dual` number with zero derivative.
+    #[inline]
+    pub fn constant(value: T) -> Self {
+        Self::new(value, T::zero())
+    }
+
+    /// Create a `Dual` number with unit derivative.
+    #[inline]
+    pub fn variable(value: T) -> Self {
+        Self::new(value, T::one())
+    }
+
+    /// Get the value part.
+    #[inline]
+    pub fn value(&self) -> T {
+        self.value.clone()
+    }
+
+    /// Get the derivative part.
+    #[inline]
+    pub fn derivative(&self) -> T {
+        self.derivative.clone()
+    }
+
+    /// Apply a function with value `value` and derivative `slope` at
+    /// `self.value`, using the chain rule.
+    #[inline]
+    fn chain(self, value: T, slope: T) -> Self {
+        Self::new(value, self.derivative * slope)
+    }
+}
+
+/// Compute the value and the exact Jacobian of `f` at `x`
+///
+/// `f` is evaluated once per component of `x` with dual-number arguments.
+/// With `f` the evaluation function of an observation model, written
+/// generically over `R: RealField`, the Jacobian is the observation matrix
+/// `H` linearized at `x`.
+pub fn dual_jacobian<R, SS, OS, F>(f: F, x: &OVector<R, SS>) -> (OVector<R, OS>, OMatrix<R, OS, SS>)
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    F: Fn(&OVector<Dual<R>, SS>) -> OVector<Dual<R>, OS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<Dual<R>, SS>,
+    DefaultAllocator: Allocator<Dual<R>, OS>,
+{
+    let mut value = OVector::<R, OS>::zeros();
+    let mut jacobian = OMatrix::<R, OS, SS>::zeros();
+    for (j, mut column) in jacobian.column_iter_mut().enumerate() {
+        let seeded = OVector::<Dual<R>, SS>::from_fn(|i, _| {
+            let xi = x.get(i).cloned().unwrap_or_else(R::zero);
+            if i == j {
+                Dual::variable(xi)
+            } else {
+                Dual::constant(xi)
+            }
+        });
+        let y = f(&seeded);
+        column.copy_from(&y.map(|yi| yi.derivative));
+        value = y.map(|yi| yi.value);
+    }
+    if SS::dim() == 0 {
+        value = f(&x.map(Dual::constant)).map(|yi| yi.value);
+    }
+    (value, jacobian)
+}
+
+impl<T: RealField> PartialEq for Dual<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: RealField> PartialOrd for Dual<T> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl<T: RealField + fmt::Display> fmt::Display for Dual<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} + {}ε", self.value, self.derivative)
+    }
+}
+
+impl<T: RealField> Add for Dual<T> {
+    type Output = Self;
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.value + rhs.value, self.derivative + rhs.derivative)
+    }
+}
+
+impl<T: RealField> Sub for Dual<T> {
+    type Output = Self;
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.value - rhs.value, self.derivative - rhs.derivative)
+    }
+}
+
+impl<T: RealField> Mul for Dual<T> {
+    type Output = Self;
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        let derivative = self.derivative * rhs.value.clone() + self.value.clone() * rhs.derivative;
+        Self::new(self.value * rhs.value, derivative)
+    }
+}
+
+impl<T: RealField> Div for Dual<T> {
+    type Output = Self;
+    #[inline]
+    fn div(self, rhs: Self) -> Self {
+        let value = self.value / rhs.value.clone();
+        let derivative = (self.derivative - value.clone() * rhs.derivative) / rhs.value;
+        Self::new(value, derivative)
+    }
+}
+
+impl<T: RealField> Rem for Dual<T> {
+    type Output = Self;
+    #[inline]
+    fn rem(self, rhs: Self) -> Self {
+        // a % b = a - b * trunc(a / b)
+        let quotient = (self.value.clone() / rhs.value.clone()).trunc();
+        Self::new(
+            self.value % rhs.value,
+            self.derivative - rhs.derivative * quotient,
+        )
+    }
+}
+
+impl<T: RealField> Neg for Dual<T> {
+    type Output = Self;
+    #[inline]
+    fn neg(self) -> Self {
+        Self::new(-self.value, -self.derivative)
+    }
+}
+
+macro_rules! impl_assign_op {
+    ($Trait:ident, $method:ident, $op:tt) => {
+        impl<T: RealField> $Trait for Dual<T> {
+            #[inline]
+            fn $method(&mut self, rhs: Self) {
+                *self = self.clone() $op rhs;
+            }
+        }
+    };
+}
+
+impl_assign_op!(AddAssign, add_assign, +);
+impl_assign_op!(SubAssign, sub_assign, -);
+impl_assign_op!(MulAssign, mul_assign, *);
+impl_assign_op!(DivAssign, div_assign, /);
+impl_assign_op!(RemAssign, rem_assign, %);
+
+impl<T: RealField> Zero for Dual<T> {
+    #[inline]
+    fn zero() -> Self {
+        Self::constant(T::zero())
+    }
+    #[inline]
+    fn is_zero(&self) -> bool {
+        self.value.is_zero()
+    }
+}
+
+impl<T: RealField> One for Dual<T> {
+    #[inline]
+    fn one() -> Self {
+        Self::constant(T::one())
+    }
+}
+
+impl<T: RealField> Num for Dual<T> {
+    type FromStrRadixErr = T::FromStrRadixErr;
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        T::from_str_radix(s, radix).map(Self::constant)
+    }
+}
+
+impl<T: RealField> Signed for Dual<T> {
+    #[inline]
+    fn abs(&self) -> Self {
+        if self.value < T::zero() {
+            -self.clone()
+        } else {
+            self.clone()
+        }
+    }
+    #[inline]
+    fn abs_sub(&self, other: &Self) -> Self {
+        if self.value <= other.value {
+            Self::zero()
+        } else {
+            self.clone() - other.clone()
+        }
+    }
+    #[inline]
+    fn signum(&self) -> Self {
+        Self::constant(Signed::signum(&self.value))
+    }
+    #[inline]
+    fn is_positive(&self) -> bool {
+        self.value.is_positive()
+    }
+    #[inline]
+    fn is_negative(&self) -> bool {
+        self.value.is_negative()
+    }
+}
+
+impl<T: RealField> FromPrimitive for Dual<T> {
+    #[inline]
+    fn from_i64(n: i64) -> Option<Self> {
+        T::from_i64(n).map(Self::constant)
+    }
+    #[inline]
+    fn from_u64(n: u64) -> Option<Self> {
+        T::from_u64(n).map(Self::constant)
+    }
+    #[inline]
+    fn from_f64(n: f64) -> Option<Self> {
+        T::from_f64(n).map(Self::constant)
+    }
+}
+
+impl<T: RealField> AbsDiffEq for Dual<T> {
+    type Epsilon = Self;
+    #[inline]
+    fn default_epsilon() -> Self {
+        Self::constant(T::default_epsilon())
+    }
+    #[inline]
+    fn abs_diff_eq(&self, other: &Self, epsilon: Self) -> bool {
+        self.value.abs_diff_eq(&other.value, epsilon.value)
+    }
+}
+
+impl<T: RealField> RelativeEq for Dual<T> {
+    #[inline]
+    fn default_max_relative() -> Self {
+        Self::constant(T::default_max_relative())
+    }
+    #[inline]
+    fn relative_eq(&self, other: &Self, epsilon: Self, max_relative: Self) -> bool {
+        self.value
+            .relative_eq(&other.value, epsilon.value, max_relative.value)
+    }
+}
+
+impl<T: RealField> UlpsEq for Dual<T> {
+    #[inline]
+    fn default_max_ulps() -> u32 {
+        T::default_max_ulps()
+    }
+    #[inline]
+    fn ulps_eq(&self, other: &Self, epsilon: Self, max_ulps: u32) -> bool {
+        self.value.ulps_eq(&other.value, epsilon.value, max_ulps)
+    }
+}
+
+impl<T: RealField> SimdValue for Dual<T> {
+    type Element = Self;
+    type SimdBool = bool;
+
+    #[inline(always)]
+    fn lanes() -> usize {
+        1
+    }
+    #[inline(always)]
+    fn splat(val: Self) -> Self {
+        val
+    }
+    #[inline(always)]
+    fn extract(&self, _: usize) -> Self {
+        self.clone()
+    }
+    #[inline(always)]
+    unsafe fn extract_unchecked(&self, _: usize) -> Self {
+        self.clone()
+    }
+    #[inline(always)]
+    fn replace(&mut self, _: usize, val: Self) {
+        *self = val
+    }
+    #[inline(always)]
+    unsafe fn replace_unchecked(&mut self, _: usize, val: Self) {
+        *self = val
+    }
+    #[inline(always)]
+    fn select(self, cond: bool, other: Self) -> Self {
+        if cond {
+            self
+        } else {
+            other
+        }
+    }
+}
+
+impl<T: RealField> Field for Dual<T> {}
+
+impl<T: RealField> SubsetOf<Dual<T>> for Dual<T> {
+    #[inline]
+    fn to_superset(&self) -> Self {
+        self.clone()
+    }
+    #[inline]
+    fn from_superset_unchecked(element: &Self) -> Self {
+        element.clone()
+    }
+    #[inline]
+    fn is_in_subset(_: &Self) -> bool {
+        true
+    }
+}
+
+impl<T: RealField> SubsetOf<Dual<T>> for f64 {
+    #[inline]
+    fn to_superset(&self) -> Dual<T> {
+        Dual::constant(na::convert(*self))
+    }
+    #[inline]
+    fn from_superset_unchecked(element: &Dual<T>) -> f64 {
+        na::convert_ref_unchecked(&element.value)
+    }
+    #[inline]
+    fn is_in_subset(element: &Dual<T>) -> bool {
+        element.derivative.is_zero() && na::is_convertible::<T, f64>(&element.value)
+    }
+}
+
+impl<T: RealField> ComplexField for Dual<T> {
+    type RealField = Self;
+
+    #[inline]
+    fn from_real(re: Self) -> Self {
+        re
+    }
+    #[inline]
+    fn real(self) -> Self {
+        self
+    }
+    #[inline]
+    fn imaginary(self) -> Self {
+        Self::zero()
+    }
+    #[inline]
+    fn modulus(self) -> Self {
+        Signed::abs(&self)
+    }
+    #[inline]
+    fn modulus_squared(self) -> Self {
+        self.clone() * self
+    }
+    #[inline]
+    fn argument(self) -> Self {
+        if self.value >= T::zero() {
+            Self::zero()
+        } else {
+            Self::constant(T::pi())
+        }
+    }
+    #[inline]
+    fn norm1(self) -> Self {
+        Signed::abs(&self)
+    }
+    #[inline]
+    fn scale(self, factor: Self) -> Self {
+        self * factor
+    }
+    #[inline]
+    fn unscale(self, factor: Self) -> Self {
+        self / factor
+    }
+    #[inline]
+    fn floor(self) -> Self {
+        Self::constant(self.value.floor())
+    }
+    #[inline]
+    fn ceil(self) -> Self {
+        Self::constant(self.value.ceil())
+    }
+    #[inline]
+    fn round(self) -> Self {
+        Self::constant(self.value.round())
+    }
+    #[inline]
+    fn trunc(self) -> Self {
+        Self::constant(self.value.trunc())
+    }
+    #[inline]
+    fn fract(self) -> Self {
+        Self::new(self.value.fract(), self.derivative)
+    }
+    #[inline]
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        self * a + b
+    }
+    #[inline]
+    fn abs(self) -> Self {
+        Signed::abs(&self)
+    }
+    #[inline]
+    fn hypot(self, other: Self) -> Self {
+        let value = self.value.clone().hypot(other.value.clone());
+        if value.is_zero() {
+            return Self::constant(value);
+        }
+        let derivative =
+            (self.value * self.derivative + other.value * other.derivative) / value.clone();
+        Self::new(value, derivative)
+    }
+    #[inline]
+    fn recip(self) -> Self {
+        Self::one() / self
+    }
+    #[inline]
+    fn conjugate(self) -> Self {
+        self
+    }
+    #[inline]
+    fn sin(self) -> Self {
+        let (sin, cos) = self.value.clone().sin_cos();
+        self.chain(sin, cos)
+    }
+    #[inline]
+    fn cos(self) -> Self {
+        let (sin, cos) = self.value.clone().sin_cos();
+        self.chain(cos, -sin)
+    }
+    #[inline]
+    fn sin_cos(self) -> (Self, Self) {
+        let (sin, cos) = self.value.clone().sin_cos();
+        (
+            Self::new(sin.clone(), self.derivative.clone() * cos.clone()),
+            Self::new(cos, -self.derivative * sin),
+        )
+    }
+    #[inline]
+    fn tan(self) -> Self {
+        let tan = self.value.clone().tan();
+        self.chain(tan.clone(), T::one() + tan.clone() * tan)
+    }
+    #[inline]
+    fn asin(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().asin(), (T::one() - x.clone() * x).sqrt().recip())
+    }
+    #[inline]
+    fn acos(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().acos(), -(T::one() - x.clone() * x).sqrt().recip())
+    }
+    #[inline]
+    fn atan(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().atan(), (T::one() + x.clone() * x).recip())
+    }
+    #[inline]
+    fn sinh(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().sinh(), x.cosh())
+    }
+    #[inline]
+    fn cosh(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().cosh(), x.sinh())
+    }
+    #[inline]
+    fn tanh(self) -> Self {
+        let tanh = self.value.clone().tanh();
+        self.chain(tanh.clone(), T::one() - tanh.clone() * tanh)
+    }
+    #[inline]
+    fn asinh(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().asinh(), (x.clone() * x + T::one()).sqrt().recip())
+    }
+    #[inline]
+    fn acosh(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().acosh(), (x.clone() * x - T::one()).sqrt().recip())
+    }
+    #[inline]
+    fn atanh(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().atanh(), (T::one() - x.clone() * x).recip())
+    }
+    #[inline]
+    fn log(self, base: Self) -> Self {
+        self.ln() / base.ln()
+    }
+    #[inline]
+    fn log2(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().log2(), (x * T::ln_2()).recip())
+    }
+    #[inline]
+    fn log10(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().log10(), (x * T::ln_10()).recip())
+    }
+    #[inline]
+    fn ln(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().ln(), x.recip())
+    }
+    #[inline]
+    fn ln_1p(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().ln_1p(), (T::one() + x).recip())
+    }
+    #[inline]
+    fn sqrt(self) -> Self {
+        let sqrt = self.value.clone().sqrt();
+        let two: T = na::convert(2.0);
+        self.chain(sqrt.clone(), (two * sqrt).recip())
+    }
+    #[inline]
+    fn exp(self) -> Self {
+        let exp = self.value.clone().exp();
+        self.chain(exp.clone(), exp)
+    }
+    #[inline]
+    fn exp2(self) -> Self {
+        let exp2 = self.value.clone().exp2();
+        self.chain(exp2.clone(), exp2 * T::ln_2())
+    }
+    #[inline]
+    fn exp_m1(self) -> Self {
+        let x = self.value.clone();
+        self.chain(x.clone().exp_m1(), x.exp())
+    }
+    #[inline]
+    fn powi(self, n: i32) -> Self {
+        let x = self.value.clone();
+        let slope = if n == 0 {
+            T::zero()
+        } else {
+            x.clone().powi(n - 1) * na::convert::<f64, T>(f64::from(n))
+        };
+        self.chain(x.powi(n), slope)
+    }
+    #[inline]
+    fn powf(self, n: Self) -> Self {
+        let x = self.value.clone();
+        let value = x.clone().powf(n.value.clone());
+        // d(x^n) = n x^(n-1) dx + x^n ln(x) dn
+        let mut derivative = if n.value.is_zero() {
+            T::zero()
+        } else {
+            n.value.clone() * x.clone().powf(n.value - T::one()) * self.derivative
+        };
+        if !n.derivative.is_zero() {
+            derivative += value.clone() * x.ln() * n.derivative;
+        }
+        Self::new(value, derivative)
+    }
+    #[inline]
+    fn powc(self, n: Self) -> Self {
+        self.powf(n)
+    }
+    #[inline]
+    fn cbrt(self) -> Self {
+        let cbrt = self.value.clone().cbrt();
+        let three: T = na::convert(3.0);
+        self.chain(cbrt.clone(), (three * cbrt.clone() * cbrt).recip())
+    }
+    #[inline]
+    fn is_finite(&self) -> bool {
+        self.value.is_finite() && self.derivative.is_finite()
+    }
+    #[inline]
+    fn try_sqrt(self) -> Option<Self> {
+        if self.value >= T::zero() {
+            Some(ComplexField::sqrt(self))
+        } else {
+            None
+        }
+    }
+}
+
+macro_rules! impl_constants {
+    ($($name:ident),*) => {$(
+        #[inline]
+        fn $name() -> Self {
+            Self::constant(T::$name())
+        }
+    )*};
+}
+
+impl<T: RealField> RealField for Dual<T> {
+    #[inline]
+    fn is_sign_positive(&self) -> bool {
+        self.value.is_sign_positive()
+    }
+    #[inline]
+    fn is_sign_negative(&self) -> bool {
+        self.value.is_sign_negative()
+    }
+    #[inline]
+    fn copysign(self, sign: Self) -> Self {
+        if self.value.is_sign_negative() == sign.value.is_sign_negative() {
+            self
+        } else {
+            -self
+        }
+    }
+    #[inline]
+    fn max(self, other: Self) -> Self {
+        if other.value > self.value {
+            other
+        } else {
+            self
+        }
+    }
+    #[inline]
+    fn min(self, other: Self) -> Self {
+        if other.value < self.value {
+            other
+        } else {
+            self
+        }
+    }
+    #[inline]
+    fn clamp(self, min: Self, max: Self) -> Self {
+        RealField::min(RealField::max(self, min), max)
+    }
+    #[inline]
+    fn atan2(self, other: Self) -> Self {
+        let (y, x) = (self.value, other.value);
+        let r2 = x.clone() * x.clone() + y.clone() * y.clone();
+        let derivative = if r2.is_zero() {
+            T::zero()
+        } else {
+            (x.clone() * self.derivative - y.clone() * other.derivative) / r2
+        };
+        Self::new(y.atan2(x), derivative)
+    }
+    #[inline]
+    fn min_value() -> Option<Self> {
+        T::min_value().map(Self::constant)
+    }
+    #[inline]
+    fn max_value() -> Option<Self> {
+        T::max_value().map(Self::constant)
+    }
+    impl_constants!(
+        pi,
+        two_pi,
+        frac_pi_2,
+        frac_pi_3,
+        frac_pi_4,
+        frac_pi_6,
+        frac_pi_8,
+        frac_1_pi,
+        frac_2_pi,
+        frac_2_sqrt_pi,
+        e,
+        log2_e,
+        log10_e,
+        ln_2,
+        ln_10
+    );
+}
+
+#[test]
+fn test_dual_jacobian() {
+    use na::{Matrix2x4, Vector2, Vector4};
+
+    // The observation [x^3, xy] of a 2D constant velocity state, written
+    // once for any scalar type.
+    fn observe<R: RealField + Copy>(s: &Vector4<R>) -> Vector2<R> {
+        Vector2::new(s.x * s.x * s.x, s.x * s.y)
+    }
+    let state = Vector4::new(1.5, -2.0, 10.0, -5.0);
+    let (value, h) = dual_jacobian(observe, &state);
+    #[rustfmt::skip]
+    let expected = Matrix2x4::new(
+        3.0 * state.x * state.x, 0.0, 0.0, 0.0,
+        state.y, state.x, 0.0, 0.0,
+    );
+    assert_eq!(value, observe(&state));
+    assert_eq!(h, expected);
+
+    // Transcendental functions against their analytic derivatives.
+    let x = Dual::variable(0.3);
+    let y = (x.sin() * x.exp()).atan2(x.sqrt()) + x.powf(Dual::constant(2.5));
+    let (s, c, e, r) = (0.3f64.sin(), 0.3f64.cos(), 0.3f64.exp(), 0.3f64.sqrt());
+    let (u, v) = (s * e, r);
+    let du = (c + s) * e;
+    let dv = 0.5 / r;
+    let expected = (v * du - u * dv) / (u * u + v * v) + 2.5 * 0.3f64.powf(1.5);
+    approx::assert_relative_eq!(y.derivative(), expected, max_relative = 1e-12);
+}
@@ -1,40 +1,273 @@
+use core::fmt;
+
 /// An error
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Error {
     kind: ErrorKind,
+    step: Option<usize>,
+}
+
+impl Error {
+    /// Get the kind of error.
+    #[inline]
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Get the index of the time step at which the error occurred, if known.
+    #[inline]
+    pub fn step(&self) -> Option<usize> {
+        self.step
+    }
+
+    /// Record the index of the time step at which the error occurred.
+    pub(crate) fn at_step(self, step: usize) -> Self {
+        Self {
+            step: Some(step),
+            ..self
+        }
+    }
 }
 
 /// The kinds of errors
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ErrorKind {
-    /// The covariance matrix is not positive semi-definite (or is not symmetric).
+    /// The covariance matrix is not positive semi-definite.
     CovarianceNotPositiveSemiDefinite,
+    /// The innovation covariance `S = H P H^T + R` could not be factorized.
+    SingularInnovationCovariance,
+    /// The state estimate contains a NaN or infinite component.
+    NonFiniteState,
+    /// The covariance matrix is not symmetric.
+    AsymmetricCovariance,
+    /// A buffer or matrix does not have the expected length.
+    DimensionMismatch {
+        /// The expected (minimum) length.
+        expected: usize,
+        /// The actual length.
+        actual: usize,
+    },
+    /// The Cholesky factorization of the predicted covariance failed in the
+    /// smoother.
+    SmootherCholeskyFailure,
+    /// A timestamp is earlier than the time of the current estimate.
+    NonMonotonicTime,
+    /// The observations do not determine the state.
+    InsufficientObservations,
+    /// The projected state does not satisfy the linear constraints.
+    ConstraintsNotSatisfied,
+    /// A time step or sample interval is zero, negative or NaN.
+    NonPositiveTimeStep,
+    /// A fusion weight is not between zero and one.
+    WeightOutOfRange,
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for ErrorKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use ErrorKind::*;
-        let s = match self {
+        match self {
             CovarianceNotPositiveSemiDefinite => {
-                "The covariance matrix is not positive semi-definite (or is not symmetric)"
+                f.write_str("The covariance matrix is not positive semi-definite")
             }
-        };
-        f.write_str(s)
+            SingularInnovationCovariance => {
+                f.write_str("The innovation covariance matrix is singular")
+            }
+            NonFiniteState => f.write_str("The state estimate is not finite"),
+            AsymmetricCovariance => f.write_str("The covariance matrix is not symmetric"),
+            DimensionMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Dimension mismatch: expected {}, got {}",
+                    expected, actual
+                )
+            }
+            SmootherCholeskyFailure => {
+                f.write_str("The Cholesky factorization in the smoother failed")
+            }
+            NonMonotonicTime => f.write_str("The timestamp is earlier than the current estimate"),
+            InsufficientObservations => f.write_str("The observations do not determine the state"),
+            ConstraintsNotSatisfied => {
+                f.write_str("The projected state does not satisfy the constraints")
+            }
+            NonPositiveTimeStep => f.write_str("The time step is not positive"),
+            WeightOutOfRange => f.write_str("The weight is not between zero and one"),
+        }
     }
 }
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
-        Error { kind }
+        Error { kind, step: None }
     }
 }
 
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
-#[cfg(feature = "std")]
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Kalman Filter Error: {}", self.kind)
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Kalman Filter Error: {}", self.kind)?;
+        if let Some(step) = self.step {
+            write!(f, " at step {}", step)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_error() {
+    use crate::TransitionModelLinearNoControl;
+    use crate::{KalmanFilterNoControl, ObservationModel, StateAndCovariance};
+    use na::{Matrix1, Vector1};
+    use nalgebra as na;
+
+    // Format into a fixed buffer, so that `Display` is checked without std.
+    struct Buffer {
+        bytes: [u8; 128],
+        len: usize,
+    }
+    impl fmt::Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+    fn check(value: impl fmt::Display, expected: &str) {
+        let mut buffer = Buffer {
+            bytes: [0; 128],
+            len: 0,
+        };
+        fmt::write(&mut buffer, format_args!("{}", value)).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buffer.bytes[..buffer.len]),
+            Ok(expected)
+        );
+    }
+    check(
+        ErrorKind::CovarianceNotPositiveSemiDefinite,
+        "The covariance matrix is not positive semi-definite",
+    );
+    check(
+        ErrorKind::SingularInnovationCovariance,
+        "The innovation covariance matrix is singular",
+    );
+    check(
+        ErrorKind::NonFiniteState,
+        "The state estimate is not finite",
+    );
+    check(
+        ErrorKind::AsymmetricCovariance,
+        "The covariance matrix is not symmetric",
+    );
+    check(
+        ErrorKind::DimensionMismatch {
+            expected: 3,
+            actual: 2,
+        },
+        "Dimension mismatch: expected 3, got 2",
+    );
+    check(
+        ErrorKind::SmootherCholeskyFailure,
+        "The Cholesky factorization in the smoother failed",
+    );
+    check(
+        ErrorKind::NonMonotonicTime,
+        "The timestamp is earlier than the current estimate",
+    );
+    check(
+        ErrorKind::InsufficientObservations,
+        "The observations do not determine the state",
+    );
+    check(
+        ErrorKind::ConstraintsNotSatisfied,
+        "The projected state does not satisfy the constraints",
+    );
+    check(
+        ErrorKind::NonPositiveTimeStep,
+        "The time step is not positive",
+    );
+    check(
+        ErrorKind::WeightOutOfRange,
+        "The weight is not between zero and one",
+    );
+    check(
+        Error::from(ErrorKind::NonFiniteState).at_step(2),
+        "Kalman Filter Error: The state estimate is not finite at step 2",
+    );
+
+    // A random walk observed directly.
+    struct RandomWalk {
+        q: Matrix1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, na::U1> for RandomWalk {
+        fn F(&self) -> &Matrix1<f64> {
+            const ONE: &Matrix1<f64> = &Matrix1::new(1.0);
+            ONE
+        }
+        fn FT(&self) -> &Matrix1<f64> {
+            self.F()
+        }
+        fn Q(&self) -> &Matrix1<f64> {
+            &self.q
+        }
+    }
+    impl ObservationModel<f64, na::U1, na::U1> for RandomWalk {
+        fn H(&self) -> &Matrix1<f64> {
+            self.F()
+        }
+        fn HT(&self) -> &Matrix1<f64> {
+            self.F()
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let model = RandomWalk {
+        q: Matrix1::new(0.1),
+        r: Matrix1::new(1.0),
+    };
+    let kf = KalmanFilterNoControl::new(&model, &model);
+    let initial = StateAndCovariance::new(Vector1::new(0.0), Matrix1::new(1.0));
+    // The infinite observation makes the state of step 2 infinite.
+    let observations = [1.0, 2.0, f64::INFINITY, 3.0].map(Vector1::new);
+    let mut estimates = [(); 4].map(|_| initial.clone());
+    let err = kf
+        .filter_inplace(&initial, &observations, &mut estimates)
+        .unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::NonFiniteState);
+    assert_eq!(err.step(), Some(2));
+
+    #[cfg(feature = "std")]
+    {
+        let err = kf.filter(&initial, &observations).unwrap_err();
+        assert_eq!(err.step(), Some(2));
+        let err = kf.smooth(&initial, &observations).unwrap_err();
+        assert_eq!(err.step(), Some(2));
+
+        // Without process noise, the prediction of a certain estimate has a
+        // singular covariance.
+        let model = RandomWalk {
+            q: Matrix1::new(0.0),
+            r: Matrix1::new(1.0),
+        };
+        let kf = KalmanFilterNoControl::new(&model, &model);
+        let mut filtered = kf.filter(&initial, &observations[..2]).unwrap();
+        filtered.insert(
+            1,
+            StateAndCovariance::new(Vector1::new(1.5), Matrix1::new(0.0)),
+        );
+        let err = kf.smooth_from_filtered(filtered.clone()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::SmootherCholeskyFailure);
+        assert_eq!(err.step(), Some(1));
+        let err = kf.smooth_full_from_filtered(filtered).unwrap_err();
+        assert_eq!(err.step(), Some(1));
     }
 }
@@ -0,0 +1,439 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, Dim, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, KalmanFilterNoControl, ObservationModel,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// Counts of steps and transmitted observations of event-triggered estimation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommunicationStatistics {
+    /// Number of steps.
+    pub steps: u32,
+    /// Number of steps with a transmitted (or, at the remote side, received)
+    /// observation.
+    pub transmissions: u32,
+}
+
+impl CommunicationStatistics {
+    /// Fraction of steps with a transmission, zero before the first step.
+    pub fn rate<R: RealField>(&self) -> R {
+        if self.steps == 0 {
+            return R::zero();
+        }
+        na::convert::<f64, R>(f64::from(self.transmissions))
+            / na::convert::<f64, R>(f64::from(self.steps))
+    }
+}
+
+/// The observation model with the noise of a set-valued "no news" update
+struct NoNewsObservationModel<'b, R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    model: &'b dyn ObservationModel<R, SS, OS>,
+    covariance: &'b OMatrix<R, OS, OS>,
+}
+
+impl<'b, R, SS, OS> ObservationModel<R, SS, OS> for NoNewsObservationModel<'b, R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    fn predict_observation(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        self.model.predict_observation(state)
+    }
+    fn H(&self) -> &OMatrix<R, OS, SS> {
+        self.model.H()
+    }
+    fn HT(&self) -> &OMatrix<R, SS, OS> {
+        self.model.HT()
+    }
+    fn R(&self) -> &OMatrix<R, OS, OS> {
+        self.covariance
+    }
+}
+
+/// The remote side of event-triggered (send-on-delta) estimation
+///
+/// The sensor side, [`EventTriggeredSensor`], transmits an observation only
+/// when a component of its innovation exceeds the threshold. At every step
+/// without a transmission, the estimator knows that the innovation was
+/// within `[-threshold, threshold]`. This "no news" is used as an
+/// observation of the predicted value with the noise covariance
+/// `R + diag(threshold^2 / 3)`, the variance of a uniform distribution over
+/// the interval, after Sijs and Lazar. Without news, the covariance thus
+/// grows more slowly than with prediction alone.
+///
+/// Both sides must start from the same estimate. If a transmission is lost,
+/// the remote estimate no longer matches the one the sensor assumes; send
+/// [`EventTriggeredSensor::estimate`] now and then and pass it to
+/// [`reset`](#method.reset) to resynchronize.
+pub struct EventTriggeredEstimator<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    filter: KalmanFilterNoControl<'a, R, SS, OS>,
+    threshold: OVector<R, OS>,
+    no_news_covariance: OMatrix<R, OS, OS>,
+    estimate: StateAndCovariance<R, SS>,
+    statistics: CommunicationStatistics,
+}
+
+impl<'a, R, SS, OS> EventTriggeredEstimator<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Create a new `EventTriggeredEstimator`.
+    ///
+    /// `threshold` holds the non-negative trigger level of each observation
+    /// component and must be the same as that of the sensor.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModel<R, SS, OS>,
+        threshold: OVector<R, OS>,
+        initial_estimate: StateAndCovariance<R, SS>,
+    ) -> Self {
+        let mut no_news_covariance = observation_model.R().clone();
+        let three: R = na::convert(3.0);
+        for (i, delta) in threshold.iter().enumerate() {
+            if let Some(variance) = no_news_covariance.get_mut((i, i)) {
+                *variance += delta.clone() * delta.clone() / three.clone();
+            }
+        }
+        Self {
+            filter: KalmanFilterNoControl::new(transition_model, observation_model),
+            threshold,
+            no_news_covariance,
+            estimate: initial_estimate,
+            statistics: CommunicationStatistics::default(),
+        }
+    }
+
+    /// Get the filter.
+    pub fn filter(&self) -> &KalmanFilterNoControl<'a, R, SS, OS> {
+        &self.filter
+    }
+
+    /// Get the trigger level of each observation component.
+    #[inline]
+    pub fn threshold(&self) -> &OVector<R, OS> {
+        &self.threshold
+    }
+
+    /// Get the current state estimate.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+
+    /// Get the counts of steps and received observations.
+    #[inline]
+    pub fn statistics(&self) -> &CommunicationStatistics {
+        &self.statistics
+    }
+
+    /// Fraction of steps with a received observation.
+    pub fn communication_rate(&self) -> R {
+        self.statistics.rate()
+    }
+
+    /// Replace the estimate, for example to resynchronize with the sensor.
+    ///
+    /// The statistics are kept.
+    pub fn reset(&mut self, estimate: StateAndCovariance<R, SS>) {
+        self.estimate = estimate;
+    }
+
+    /// Predict and update with the received observation, if any.
+    ///
+    /// `None` means that nothing was received and is used as the set-valued
+    /// "no news" observation. A missing (NaN) observation leaves the
+    /// prediction as the estimate. If the update fails, the estimate is left
+    /// unchanged.
+    pub fn step(&mut self, observation: Option<&OVector<R, OS>>) -> Result<(), Error> {
+        let prior = self.filter.transition_model().predict(&self.estimate);
+        self.update(prior, observation)
+    }
+
+    fn update(
+        &mut self,
+        prior: StateAndCovariance<R, SS>,
+        observation: Option<&OVector<R, OS>>,
+    ) -> Result<(), Error> {
+        let observation_model = self.filter.observation_model();
+        let method = CovarianceUpdateMethod::JosephForm;
+        self.estimate = match observation {
+            Some(z) if z.iter().any(|x| is_nan(x.clone())) => prior,
+            Some(z) => observation_model.update(&prior, z, method)?,
+            None => {
+                let no_news = NoNewsObservationModel {
+                    model: observation_model,
+                    covariance: &self.no_news_covariance,
+                };
+                let predicted = observation_model.predict_observation(prior.state());
+                no_news.update(&prior, &predicted, method)?
+            }
+        };
+        self.statistics.steps = self.statistics.steps.saturating_add(1);
+        if observation.is_some() {
+            self.statistics.transmissions = self.statistics.transmissions.saturating_add(1);
+        }
+        Ok(())
+    }
+}
+
+/// The sensor side of event-triggered (send-on-delta) estimation
+///
+/// The sensor runs a copy of the remote [`EventTriggeredEstimator`]. At each
+/// step, it compares the observation with the observation predicted by that
+/// copy and returns the observation for transmission only if a component of
+/// the innovation exceeds its threshold. Missing (NaN) observations are
+/// always transmitted, as the remote would otherwise take their absence as
+/// news.
+pub struct EventTriggeredSensor<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    remote: EventTriggeredEstimator<'a, R, SS, OS>,
+}
+
+impl<'a, R, SS, OS> EventTriggeredSensor<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Create a new `EventTriggeredSensor`.
+    ///
+    /// The arguments must be the same as those of the remote
+    /// [`EventTriggeredEstimator`].
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModel<R, SS, OS>,
+        threshold: OVector<R, OS>,
+        initial_estimate: StateAndCovariance<R, SS>,
+    ) -> Self {
+        Self {
+            remote: EventTriggeredEstimator::new(
+                transition_model,
+                observation_model,
+                threshold,
+                initial_estimate,
+            ),
+        }
+    }
+
+    /// Get the trigger level of each observation component.
+    #[inline]
+    pub fn threshold(&self) -> &OVector<R, OS> {
+        self.remote.threshold()
+    }
+
+    /// Get the estimate of the remote side, assuming every transmission
+    /// arrived.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        self.remote.estimate()
+    }
+
+    /// Get the counts of steps and transmitted observations.
+    #[inline]
+    pub fn statistics(&self) -> &CommunicationStatistics {
+        self.remote.statistics()
+    }
+
+    /// Fraction of steps with a transmitted observation.
+    pub fn communication_rate(&self) -> R {
+        self.remote.communication_rate()
+    }
+
+    /// Replace the estimate of the remote side.
+    ///
+    /// The statistics are kept.
+    pub fn reset(&mut self, estimate: StateAndCovariance<R, SS>) {
+        self.remote.reset(estimate);
+    }
+
+    /// Process an observation and return it if it is to be transmitted.
+    ///
+    /// Pass the result to [`EventTriggeredEstimator::step`]. If the update
+    /// fails, the estimate is left unchanged and nothing should be
+    /// transmitted.
+    pub fn step(&mut self, observation: &OVector<R, OS>) -> Result<Option<OVector<R, OS>>, Error> {
+        let prior = self
+            .remote
+            .filter
+            .transition_model()
+            .predict(&self.remote.estimate);
+        let predicted = self
+            .remote
+            .filter
+            .observation_model()
+            .predict_observation(prior.state());
+        let innovation = observation - predicted;
+        // NaN components are transmitted.
+        let transmit = innovation
+            .iter()
+            .zip(self.remote.threshold.iter())
+            .any(|(y, delta)| is_nan(y.clone()) || y.clone().abs() > delta.clone());
+        let sent = if transmit { Some(observation) } else { None };
+        self.remote.update(prior, sent)?;
+        Ok(sent.cloned())
+    }
+}
+
+#[test]
+fn test_event_triggered() {
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    struct ConstantVelocity {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for ConstantVelocity {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    struct Position {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for Position {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let dt = 0.1;
+    let f = Matrix2::new(1.0, dt, 0.0, 1.0);
+    let transition = ConstantVelocity {
+        f,
+        ft: f.transpose(),
+        q: Matrix2::new(dt * dt * dt / 3.0, dt * dt / 2.0, dt * dt / 2.0, dt) * 0.1,
+    };
+    let position = Position {
+        h: Matrix1x2::new(1.0, 0.0),
+        ht: Matrix2x1::new(1.0, 0.0),
+        r: Matrix1::new(1e-2),
+    };
+    let initial = StateAndCovariance::new(Vector2::new(0.0, 1.0), Matrix2::identity());
+    // A smoothly accelerating target with deterministic measurement noise.
+    let observations: [Vector1<f64>; 200] = core::array::from_fn(|i| {
+        let t = i as f64 * dt;
+        Vector1::new(t + (0.5 * t).sin() + 0.1 * (i as f64 * 2.7).sin())
+    });
+
+    let threshold = Vector1::new(0.3);
+    let mut sensor = EventTriggeredSensor::new(&transition, &position, threshold, initial.clone());
+    let mut remote =
+        EventTriggeredEstimator::new(&transition, &position, threshold, initial.clone());
+    for (i, z) in observations.iter().enumerate() {
+        let prior_variance = transition.predict(remote.estimate()).covariance()[(0, 0)];
+        let sent = sensor.step(z).unwrap();
+        remote.step(sent.as_ref()).unwrap();
+        assert_eq!(remote.estimate().state(), sensor.estimate().state());
+        assert_eq!(
+            remote.estimate().covariance(),
+            sensor.estimate().covariance()
+        );
+        // No news reduces the uncertainty.
+        assert!(remote.estimate().covariance()[(0, 0)] < prior_variance);
+        if i > 20 {
+            assert!((remote.estimate().state()[0] - z[0]).abs() < 0.5);
+        }
+    }
+    assert_eq!(sensor.statistics(), remote.statistics());
+    assert_eq!(remote.statistics().steps, 200);
+    let rate = remote.communication_rate();
+    assert!(0.05 < rate && rate < 0.5, "rate {}", rate);
+
+    // A missing observation is transmitted and leaves the prediction.
+    let prior = transition.predict(remote.estimate());
+    let missing = Vector1::new(f64::NAN);
+    let sent = sensor.step(&missing).unwrap();
+    assert!(sent.is_some());
+    remote.step(sent.as_ref()).unwrap();
+    assert_eq!(remote.estimate().state(), prior.state());
+
+    // With a zero threshold, every observation is sent and the estimate is
+    // that of the Kalman filter.
+    let kf = KalmanFilterNoControl::new(&transition, &position);
+    let mut sensor =
+        EventTriggeredSensor::new(&transition, &position, Vector1::zeros(), initial.clone());
+    let mut estimate = initial;
+    for z in observations.iter() {
+        assert!(sensor.step(z).unwrap().is_some());
+        estimate = kf.step(&estimate, z).unwrap();
+    }
+    assert_eq!(sensor.communication_rate(), 1.0);
+    approx::assert_relative_eq!(sensor.estimate().state(), estimate.state());
+    approx::assert_relative_eq!(sensor.estimate().covariance(), estimate.covariance());
+}
@@ -0,0 +1,107 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, Dim, RealField};
+use nalgebra as na;
+
+use crate::{StateAndCovariance, TransitionModelLinearNoControl};
+
+/// An iterator over predictions of successive intervals
+///
+/// Each item is the state and covariance one interval after the previous
+/// item, so the uncertainty grows with the horizon. Created by
+/// [`KalmanFilterNoControl::forecast`](struct.KalmanFilterNoControl.html#method.forecast)
+/// or [`Forecast::new`].
+pub struct Forecast<'a, R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    current: StateAndCovariance<R, SS>,
+}
+
+impl<'a, R, SS> Forecast<'a, R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `Forecast` starting from `estimate`.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        estimate: StateAndCovariance<R, SS>,
+    ) -> Self {
+        Self {
+            transition_model,
+            current: estimate,
+        }
+    }
+}
+
+impl<R, SS> Iterator for Forecast<'_, R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    type Item = StateAndCovariance<R, SS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.current = self.transition_model.predict(&self.current);
+        Some(self.current.clone())
+    }
+}
+
+#[test]
+fn test_forecast() {
+    use na::{Matrix2, Vector2, U2};
+
+    struct Model {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for Model {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+
+    let f = Matrix2::new(1.0, 0.1, 0.0, 1.0);
+    let model = Model {
+        f,
+        ft: f.transpose(),
+        q: Matrix2::new(1e-3, 5e-3, 5e-3, 0.1),
+    };
+    let estimate = StateAndCovariance::new(Vector2::new(1.0, 2.0), Matrix2::identity());
+
+    let mut forecast: [StateAndCovariance<f64, U2>; 13] =
+        core::array::from_fn(|_| estimate.clone());
+    model.forecast_into(&estimate, &mut forecast);
+
+    for (i, prediction) in Forecast::new(&model, estimate.clone()).take(13).enumerate() {
+        let closed_form = model.predict_n(&estimate, i + 1);
+        approx::assert_relative_eq!(prediction.state(), closed_form.state(), epsilon = 1e-12);
+        approx::assert_relative_eq!(
+            prediction.covariance(),
+            closed_form.covariance(),
+            max_relative = 1e-12
+        );
+        approx::assert_relative_eq!(prediction.covariance(), forecast[i].covariance());
+    }
+    approx::assert_relative_eq!(model.predict_n(&estimate, 0).state(), estimate.state());
+    approx::assert_relative_eq!(
+        forecast[12].state(),
+        &Vector2::new(3.6, 2.0),
+        epsilon = 1e-12
+    );
+}
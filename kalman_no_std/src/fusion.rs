@@ -0,0 +1,189 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, DimName, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::optimize::golden_section_minimize;
+use crate::{Error, ErrorKind, StateAndCovariance};
+
+/// Specifies the quantity minimized when choosing the covariance intersection
+/// weight
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FusionCriterion {
+    /// Minimize the determinant of the fused covariance (the volume of the
+    /// uncertainty ellipsoid).
+    Determinant,
+    /// Minimize the trace of the fused covariance (the sum of the variances).
+    Trace,
+}
+
+/// Fuse two estimates with unknown cross-correlation by covariance
+/// intersection
+///
+/// The fused information matrix is `w Pa^-1 + (1 - w) Pb^-1`, where the weight
+/// `w` in `[0, 1]` is chosen to minimize `criterion`. The result is consistent
+/// for any cross-correlation between the errors of `a` and `b`.
+pub fn covariance_intersection<R, SS>(
+    a: &StateAndCovariance<R, SS>,
+    b: &StateAndCovariance<R, SS>,
+    criterion: FusionCriterion,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let info_a = inverse(a.covariance())?;
+    let info_b = inverse(b.covariance())?;
+
+    let omega = golden_section_minimize(R::zero(), R::one(), |omega: R| -> Result<R, Error> {
+        let info = intersect(&info_a, &info_b, omega);
+        let info_chol = cholesky(info)?;
+        Ok(match criterion {
+            // det(P) = 1 / det(P^-1)
+            FusionCriterion::Determinant => -info_chol.ln_determinant(),
+            FusionCriterion::Trace => info_chol.inverse().trace(),
+        })
+    })?;
+    fuse_information(a, b, &info_a, &info_b, omega)
+}
+
+/// Fuse two estimates by covariance intersection with a given weight
+///
+/// The fused information matrix is `omega Pa^-1 + (1 - omega) Pb^-1`.
+///
+/// Returns `ErrorKind::WeightOutOfRange` if `omega` is not in `[0, 1]`, as
+/// the fused estimate is then not consistent.
+pub fn covariance_intersection_with_weight<R, SS>(
+    a: &StateAndCovariance<R, SS>,
+    b: &StateAndCovariance<R, SS>,
+    omega: R,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    if !(R::zero()..=R::one()).contains(&omega) {
+        return Err(ErrorKind::WeightOutOfRange.into());
+    }
+    let info_a = inverse(a.covariance())?;
+    let info_b = inverse(b.covariance())?;
+    fuse_information(a, b, &info_a, &info_b, omega)
+}
+
+/// Fuse two estimates with known cross-covariance (Bar-Shalom-Campo)
+///
+/// `cross_covariance` is `Cov(ea, eb)`, the covariance between the errors of
+/// `a` and `b`. With a zero cross-covariance this is the usual
+/// information-weighted fusion of independent estimates.
+pub fn bar_shalom_campo<R, SS>(
+    a: &StateAndCovariance<R, SS>,
+    b: &StateAndCovariance<R, SS>,
+    cross_covariance: &OMatrix<R, SS, SS>,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let pa = a.covariance();
+    let pb = b.covariance();
+
+    // U = Pa + Pb - Pab - Pba
+    let u = pa + pb - cross_covariance - cross_covariance.transpose();
+    let u_chol = cholesky(u)?;
+
+    // K = (Pa - Pab) U^-1, computed as K^T = U^-1 (Pa - Pab)^T
+    let pa_minus_pab = pa - cross_covariance;
+    let k_gain: OMatrix<R, SS, SS> = u_chol.solve(&pa_minus_pab.transpose()).transpose();
+
+    let state: OVector<R, SS> = a.state() + &k_gain * (b.state() - a.state());
+    let covariance: OMatrix<R, SS, SS> = pa - &k_gain * pa_minus_pab.transpose();
+    Ok(StateAndCovariance::new(state, covariance.symmetric_part()))
+}
+
+fn fuse_information<R, SS>(
+    a: &StateAndCovariance<R, SS>,
+    b: &StateAndCovariance<R, SS>,
+    info_a: &OMatrix<R, SS, SS>,
+    info_b: &OMatrix<R, SS, SS>,
+    omega: R,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let info = intersect(info_a, info_b, omega.clone());
+    let info_chol = cholesky(info)?;
+    let info_state: OVector<R, SS> =
+        info_a * a.state() * omega.clone() + info_b * b.state() * (R::one() - omega);
+    let state = info_chol.solve(&info_state);
+    Ok(StateAndCovariance::new(state, info_chol.inverse()))
+}
+
+fn intersect<R, SS>(
+    info_a: &OMatrix<R, SS, SS>,
+    info_b: &OMatrix<R, SS, SS>,
+    omega: R,
+) -> OMatrix<R, SS, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+{
+    info_a * omega.clone() + info_b * (R::one() - omega)
+}
+
+fn cholesky<R, SS>(m: OMatrix<R, SS, SS>) -> Result<na::linalg::Cholesky<R, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+{
+    match na::linalg::Cholesky::new(m) {
+        Some(v) => Ok(v),
+        None => Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into()),
+    }
+}
+
+fn inverse<R, SS>(m: &OMatrix<R, SS, SS>) -> Result<OMatrix<R, SS, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+{
+    Ok(cholesky(m.clone())?.inverse())
+}
+
+#[test]
+fn test_fusion() {
+    use na::{Matrix2, Vector2};
+
+    let a = StateAndCovariance::new(Vector2::new(0.0, 0.0), Matrix2::new(1.0, 0.0, 0.0, 4.0));
+    let b = StateAndCovariance::new(Vector2::new(1.0, 1.0), Matrix2::new(4.0, 0.0, 0.0, 1.0));
+
+    // Independent estimates: information-weighted average.
+    let fused = bar_shalom_campo(&a, &b, &Matrix2::zeros()).unwrap();
+    approx::assert_relative_eq!(fused.state(), &Vector2::new(0.2, 0.8), epsilon = 1e-12);
+    approx::assert_relative_eq!(fused.covariance()[(0, 0)], 0.8, epsilon = 1e-12);
+
+    // The problem is symmetric so the optimal weight is one half.
+    for criterion in [FusionCriterion::Determinant, FusionCriterion::Trace] {
+        let fused = covariance_intersection(&a, &b, criterion).unwrap();
+        let expected = covariance_intersection_with_weight(&a, &b, 0.5).unwrap();
+        approx::assert_relative_eq!(fused.state(), expected.state(), epsilon = 1e-6);
+        approx::assert_relative_eq!(fused.covariance(), expected.covariance(), epsilon = 1e-6);
+    }
+
+    for omega in [-0.1, 1.1, f64::NAN] {
+        let err = covariance_intersection_with_weight(&a, &b, omega).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::WeightOutOfRange);
+    }
+    assert!(covariance_intersection_with_weight(&a, &b, 1.0).is_ok());
+}
@@ -0,0 +1,689 @@
+use core::array;
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A single weighted component of a [`GaussianMixture`]
+#[derive(Debug, Clone)]
+pub struct WeightedComponent<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    weight: R,
+    estimate: StateAndCovariance<R, SS>,
+}
+
+impl<R, SS> WeightedComponent<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `WeightedComponent`.
+    pub fn new(weight: R, estimate: StateAndCovariance<R, SS>) -> Self {
+        Self { weight, estimate }
+    }
+    /// Get the weight of this component.
+    #[inline]
+    pub fn weight(&self) -> R {
+        self.weight.clone()
+    }
+    /// Get a reference to the state and covariance of this component.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+    /// Get the weight and the state and covariance.
+    #[inline]
+    pub fn inner(self) -> (R, StateAndCovariance<R, SS>) {
+        (self.weight, self.estimate)
+    }
+
+    /// Return the log-likelihood of the observation and the updated
+    /// estimate.
+    fn updated<OS>(
+        &self,
+        observation_model: &dyn ObservationModel<R, SS, OS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<(R, StateAndCovariance<R, SS>), Error>
+    where
+        OS: DimName + DimMin<OS, Output = OS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        let innovation = observation_model.innovation(&self.estimate, observation);
+        let log_likelihood = innovation.log_likelihood()?;
+        let estimate =
+            observation_model.update(&self.estimate, observation, covariance_update_method)?;
+        Ok((log_likelihood, estimate))
+    }
+
+    /// Merge two components into one by matching the first two moments.
+    fn merge(self, other: Self) -> Self {
+        let weight = self.weight.clone() + other.weight.clone();
+        if weight <= R::zero() {
+            return self;
+        }
+        let (xa, mut pa) = self.estimate.inner();
+        let (xb, mut pb) = other.estimate.inner();
+        let wa = self.weight / weight.clone();
+        let wb = other.weight / weight.clone();
+
+        let state: OVector<R, SS> = &xa * wa.clone() + &xb * wb.clone();
+        let da = xa - &state;
+        let db = xb - &state;
+        pa.ger(R::one(), &da, &da, R::one());
+        pb.ger(R::one(), &db, &db, R::one());
+        let covariance: OMatrix<R, SS, SS> = pa * wa + pb * wb;
+        Self::new(weight, StateAndCovariance::new(state, covariance))
+    }
+}
+
+/// A Gaussian mixture with at most `N` weighted components
+///
+/// Each component is a [`StateAndCovariance`] with a weight. Unlike a single
+/// Gaussian, a mixture can represent multimodal posteriors such as the sign
+/// ambiguity of an `x**3` observation.
+#[derive(Debug, Clone)]
+pub struct GaussianMixture<R, SS, const N: usize>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    components: [Option<WeightedComponent<R, SS>>; N],
+}
+
+impl<R, SS, const N: usize> GaussianMixture<R, SS, N>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new, empty `GaussianMixture`.
+    pub fn new() -> Self {
+        Self {
+            components: array::from_fn(|_| None),
+        }
+    }
+
+    /// Add a component to the mixture.
+    ///
+    /// If the mixture already holds `N` components, the component is returned
+    /// as the error value. Weights are not renormalized.
+    pub fn push(
+        &mut self,
+        weight: R,
+        estimate: StateAndCovariance<R, SS>,
+    ) -> Result<(), WeightedComponent<R, SS>> {
+        let component = WeightedComponent::new(weight, estimate);
+        match self.components.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(component);
+                Ok(())
+            }
+            None => Err(component),
+        }
+    }
+
+    /// Number of components in the mixture.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the mixture has no components.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the components of the mixture.
+    pub fn iter(&self) -> impl Iterator<Item = &WeightedComponent<R, SS>> {
+        self.components.iter().flatten()
+    }
+
+    /// Remove all components from the mixture.
+    pub fn clear(&mut self) {
+        self.components.iter_mut().for_each(|slot| *slot = None);
+    }
+
+    /// Scale the weights so that they sum to one.
+    ///
+    /// Does nothing if the total weight is not positive.
+    pub fn normalize(&mut self) {
+        let total = self
+            .iter()
+            .fold(R::zero(), |acc, component| acc + component.weight());
+        if total <= R::zero() {
+            return;
+        }
+        for component in self.components.iter_mut().flatten() {
+            component.weight /= total.clone();
+        }
+    }
+
+    /// Get the component with the largest weight.
+    pub fn most_likely(&self) -> Option<&WeightedComponent<R, SS>> {
+        self.heaviest_index()
+            .and_then(|idx| self.components.get(idx))
+            .and_then(Option::as_ref)
+    }
+
+    /// Collapse the mixture into a single Gaussian by moment matching.
+    ///
+    /// Returns `None` if the mixture is empty.
+    pub fn collapse(&self) -> Option<StateAndCovariance<R, SS>> {
+        self.iter()
+            .cloned()
+            .reduce(WeightedComponent::merge)
+            .map(|component| component.estimate)
+    }
+
+    /// Replace each estimate by its update, multiply each weight by the
+    /// likelihood of the observation and renormalize.
+    fn apply_updates(&mut self, updates: [Option<(R, StateAndCovariance<R, SS>)>; N]) {
+        // Scale by the largest likelihood to avoid underflow.
+        let max_log_likelihood = updates
+            .iter()
+            .flatten()
+            .map(|(log_likelihood, _)| log_likelihood.clone())
+            .reduce(|a, b| if b > a { b } else { a });
+        let max_log_likelihood = match max_log_likelihood {
+            Some(v) => v,
+            None => return,
+        };
+        for (slot, update) in self.components.iter_mut().zip(updates) {
+            if let (Some(component), Some((log_likelihood, estimate))) = (slot, update) {
+                component.weight *= (log_likelihood - max_log_likelihood.clone()).exp();
+                component.estimate = estimate;
+            }
+        }
+        self.normalize();
+    }
+
+    fn heaviest_index(&self) -> Option<usize> {
+        let mut best: Option<(usize, R)> = None;
+        for (idx, slot) in self.components.iter().enumerate() {
+            if let Some(component) = slot {
+                let is_heavier = match &best {
+                    Some((_, weight)) => component.weight > *weight,
+                    None => true,
+                };
+                if is_heavier {
+                    best = Some((idx, component.weight()));
+                }
+            }
+        }
+        best.map(|(idx, _)| idx)
+    }
+}
+
+impl<R, SS, const N: usize> Default for GaussianMixture<R, SS, N>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parameters controlling how a [`GaussianMixture`] is kept small
+#[derive(Debug, Clone)]
+pub struct MixtureReduction<R: RealField> {
+    prune_weight: R,
+    merge_distance: R,
+}
+
+impl<R: RealField> MixtureReduction<R> {
+    /// Create a new `MixtureReduction`.
+    ///
+    /// Components with a normalized weight below `prune_weight` are removed
+    /// (the heaviest component is always kept). Components whose squared
+    /// Mahalanobis distance to a heavier component is below `merge_distance`
+    /// are merged into it by moment matching.
+    pub fn new(prune_weight: R, merge_distance: R) -> Self {
+        Self {
+            prune_weight,
+            merge_distance,
+        }
+    }
+}
+
+/// A Gaussian-sum filter with no control inputs
+///
+/// Each component of a [`GaussianMixture`] is predicted and updated by the
+/// same linear Kalman machinery as
+/// [`KalmanFilterNoControl`](struct.KalmanFilterNoControl.html). Component
+/// weights are updated by the likelihood of the observation and the mixture
+/// is pruned and merged after every step to bound its size.
+pub struct GaussianSumFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModel<R, SS, OS>,
+    reduction: MixtureReduction<R>,
+}
+
+impl<'a, R, SS, OS> GaussianSumFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `GaussianSumFilter` struct.
+    ///
+    /// The models are the same as for
+    /// [`KalmanFilterNoControl::new`](struct.KalmanFilterNoControl.html#method.new).
+    /// The `reduction` parameter controls pruning and merging of components.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModel<R, SS, OS>,
+        reduction: MixtureReduction<R>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            reduction,
+        }
+    }
+
+    /// Perform prediction, update and reduction steps with default values
+    ///
+    /// If any component of the observation is NaN (not a number), the update
+    /// step is skipped. The update uses `CovarianceUpdateMethod::JosephForm`.
+    pub fn step<const N: usize>(
+        &self,
+        mixture: &mut GaussianMixture<R, SS, N>,
+        observation: &OVector<R, OS>,
+    ) -> Result<(), Error> {
+        self.step_with_options(mixture, observation, CovarianceUpdateMethod::JosephForm)
+    }
+
+    /// Perform prediction, update and reduction steps
+    ///
+    /// If any component of the observation is NaN (not a number), the update
+    /// step is skipped.
+    pub fn step_with_options<const N: usize>(
+        &self,
+        mixture: &mut GaussianMixture<R, SS, N>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<(), Error> {
+        self.predict(mixture);
+        if !observation.iter().any(|x| is_nan(x.clone())) {
+            self.update(mixture, observation, covariance_update_method)?;
+        }
+        self.reduce(mixture)
+    }
+
+    /// Predict every component of the mixture with the transition model.
+    pub fn predict<const N: usize>(&self, mixture: &mut GaussianMixture<R, SS, N>) {
+        for component in mixture.components.iter_mut().flatten() {
+            component.estimate = self.transition_model.predict(&component.estimate);
+        }
+    }
+
+    /// Update every component of the mixture with the observation model.
+    ///
+    /// Component weights are multiplied by the likelihood of the observation
+    /// and renormalized. If the update of any component fails, the error is
+    /// returned and the mixture is left unchanged.
+    pub fn update<const N: usize>(
+        &self,
+        mixture: &mut GaussianMixture<R, SS, N>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<(), Error> {
+        let mut updates: [Option<(R, StateAndCovariance<R, SS>)>; N] = array::from_fn(|_| None);
+        for (slot, update) in mixture.components.iter().zip(updates.iter_mut()) {
+            if let Some(component) = slot {
+                *update = Some(component.updated(
+                    self.observation_model,
+                    observation,
+                    covariance_update_method,
+                )?);
+            }
+        }
+        mixture.apply_updates(updates);
+        Ok(())
+    }
+
+    /// Update every component with an observation model linearized for it.
+    ///
+    /// For a non-linear observation, such as `[x**3, xy]`, each component
+    /// must be linearized around its own state. `linearize` is called once
+    /// per component and the returned model is used for that component only.
+    /// If the update of any component fails, the error is returned and the
+    /// mixture is left unchanged.
+    pub fn update_linearized<const N: usize, M, F>(
+        &self,
+        mixture: &mut GaussianMixture<R, SS, N>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+        linearize: F,
+    ) -> Result<(), Error>
+    where
+        M: ObservationModel<R, SS, OS>,
+        F: Fn(&StateAndCovariance<R, SS>) -> M,
+    {
+        let mut updates: [Option<(R, StateAndCovariance<R, SS>)>; N] = array::from_fn(|_| None);
+        for (slot, update) in mixture.components.iter().zip(updates.iter_mut()) {
+            if let Some(component) = slot {
+                let model = linearize(&component.estimate);
+                *update = Some(component.updated(&model, observation, covariance_update_method)?);
+            }
+        }
+        mixture.apply_updates(updates);
+        Ok(())
+    }
+
+    /// Prune light components and merge nearby components.
+    pub fn reduce<const N: usize>(
+        &self,
+        mixture: &mut GaussianMixture<R, SS, N>,
+    ) -> Result<(), Error> {
+        mixture.normalize();
+
+        // Prune, but never remove the heaviest component.
+        let heaviest = mixture.heaviest_index();
+        for (idx, slot) in mixture.components.iter_mut().enumerate() {
+            let is_light = match slot {
+                Some(component) => component.weight < self.reduction.prune_weight,
+                None => false,
+            };
+            if is_light && Some(idx) != heaviest {
+                *slot = None;
+            }
+        }
+
+        // Merge, starting from the heaviest remaining component.
+        let mut done = [false; N];
+        loop {
+            let mut center: Option<(usize, R)> = None;
+            for (idx, (slot, is_done)) in mixture.components.iter().zip(done.iter()).enumerate() {
+                if let (Some(component), false) = (slot, *is_done) {
+                    let is_heavier = match &center {
+                        Some((_, weight)) => component.weight > *weight,
+                        None => true,
+                    };
+                    if is_heavier {
+                        center = Some((idx, component.weight()));
+                    }
+                }
+            }
+            let i = match center {
+                Some((idx, _)) => idx,
+                None => break,
+            };
+            if let Some(is_done) = done.get_mut(i) {
+                *is_done = true;
+            }
+
+            // Factorize before taking the component out of its slot, so that
+            // an error leaves it in the mixture.
+            let covariance = match mixture.components.get(i).and_then(Option::as_ref) {
+                Some(component) => component.estimate.covariance().clone(),
+                None => break,
+            };
+            let p_chol = match na::linalg::Cholesky::new(covariance) {
+                Some(v) => v,
+                None => {
+                    return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+                }
+            };
+            let mut merged = match mixture.components.get_mut(i).and_then(Option::take) {
+                Some(component) => component,
+                None => break,
+            };
+            let center_state = merged.estimate.state().clone();
+            for (slot, is_done) in mixture.components.iter_mut().zip(done.iter()) {
+                if *is_done {
+                    continue;
+                }
+                let is_near = match slot {
+                    Some(component) => {
+                        let d = component.estimate.state() - &center_state;
+                        d.dot(&p_chol.solve(&d)) < self.reduction.merge_distance
+                    }
+                    None => false,
+                };
+                if is_near {
+                    if let Some(component) = slot.take() {
+                        merged = merged.merge(component);
+                    }
+                }
+            }
+            if let Some(slot) = mixture.components.get_mut(i) {
+                *slot = Some(merged);
+            }
+        }
+
+        mixture.normalize();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_gaussian_sum_resolves_ambiguity() {
+    use na::{Matrix1, Vector1, U1};
+
+    struct Model {
+        one: Matrix1<f64>,
+        noise: Matrix1<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U1> for Model {
+        fn F(&self) -> &Matrix1<f64> {
+            &self.one
+        }
+        fn FT(&self) -> &Matrix1<f64> {
+            &self.one
+        }
+        fn Q(&self) -> &Matrix1<f64> {
+            &self.noise
+        }
+    }
+    impl ObservationModel<f64, U1, U1> for Model {
+        fn H(&self) -> &Matrix1<f64> {
+            &self.one
+        }
+        fn HT(&self) -> &Matrix1<f64> {
+            &self.one
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.noise
+        }
+    }
+
+    let model = Model {
+        one: Matrix1::new(1.0),
+        noise: Matrix1::new(0.01),
+    };
+    let filter = GaussianSumFilter::new(&model, &model, MixtureReduction::new(1e-3, 1.0));
+
+    let mut mixture = GaussianMixture::<f64, U1, 4>::new();
+    for x in [-1.0, 1.0, 1.01] {
+        let estimate = StateAndCovariance::new(Vector1::new(x), Matrix1::new(0.1));
+        mixture.push(1.0, estimate).unwrap();
+    }
+    mixture
+        .push(
+            1.0,
+            StateAndCovariance::new(Vector1::new(5.0), Matrix1::new(0.1)),
+        )
+        .unwrap();
+    assert!(mixture
+        .push(
+            1.0,
+            StateAndCovariance::new(Vector1::new(0.0), Matrix1::new(0.1))
+        )
+        .is_err());
+
+    filter.step(&mut mixture, &Vector1::new(1.0)).unwrap();
+
+    // The components near +1 are merged and the others are pruned.
+    assert_eq!(mixture.len(), 1);
+    let best = mixture.most_likely().unwrap();
+    approx::assert_relative_eq!(best.weight(), 1.0);
+    approx::assert_relative_eq!(best.estimate().state()[0], 1.0, epsilon = 1e-2);
+}
+
+#[test]
+fn test_gaussian_sum_linearized() {
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    struct Static {
+        f: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for Static {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    // A scalar observation `observe(x)` linearized with the Jacobian `h`.
+    struct Linearized {
+        observe: fn(&Vector2<f64>) -> f64,
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl Linearized {
+        fn new(observe: fn(&Vector2<f64>) -> f64, h: Matrix1x2<f64>, r: f64) -> Self {
+            Self {
+                observe,
+                h,
+                ht: h.transpose(),
+                r: Matrix1::new(r),
+            }
+        }
+    }
+    impl ObservationModel<f64, U2, U1> for Linearized {
+        fn predict_observation(&self, state: &Vector2<f64>) -> Vector1<f64> {
+            Vector1::new((self.observe)(state))
+        }
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+    // `x y` cannot tell `(x, y)` from `(-x, -y)`; `x**3` can.
+    let product = |e: &StateAndCovariance<f64, U2>| {
+        let s = e.state();
+        Linearized::new(|s| s.x * s.y, Matrix1x2::new(s.y, s.x), 0.01)
+    };
+    let cubic = |e: &StateAndCovariance<f64, U2>| {
+        let s = e.state();
+        Linearized::new(|s| s.x.powi(3), Matrix1x2::new(3.0 * s.x * s.x, 0.0), 0.01)
+    };
+
+    let motion = Static {
+        f: Matrix2::identity(),
+        q: Matrix2::identity() * 1e-4,
+    };
+    let unused = product(&StateAndCovariance::new(
+        Vector2::zeros(),
+        Matrix2::identity(),
+    ));
+    let filter = GaussianSumFilter::new(&motion, &unused, MixtureReduction::new(1e-3, 1.0));
+    let method = CovarianceUpdateMethod::JosephForm;
+
+    let mut mixture = GaussianMixture::<f64, U2, 4>::new();
+    for sign in [1.0, -1.0] {
+        let estimate =
+            StateAndCovariance::new(Vector2::new(0.8, 2.3) * sign, Matrix2::identity() * 0.1);
+        mixture.push(1.0, estimate).unwrap();
+    }
+
+    // The truth is (1, 2). Both hypotheses explain every product.
+    for _ in 0..5 {
+        filter.predict(&mut mixture);
+        filter
+            .update_linearized(&mut mixture, &Vector1::new(2.0), method, product)
+            .unwrap();
+        filter.reduce(&mut mixture).unwrap();
+        assert_eq!(mixture.len(), 2);
+        for component in mixture.iter() {
+            approx::assert_relative_eq!(component.weight(), 0.5, epsilon = 1e-9);
+            let s = component.estimate().state();
+            approx::assert_relative_eq!(s.x * s.y, 2.0, epsilon = 0.1);
+        }
+    }
+
+    // The cubic observation keeps only the positive hypothesis.
+    filter.predict(&mut mixture);
+    filter
+        .update_linearized(&mut mixture, &Vector1::new(1.0), method, cubic)
+        .unwrap();
+    filter.reduce(&mut mixture).unwrap();
+    assert_eq!(mixture.len(), 1);
+    let best = mixture.most_likely().unwrap();
+    assert!(best.estimate().state().x > 0.0);
+    assert!(best.estimate().state().y > 0.0);
+
+    // A failing update leaves the weights and estimates unchanged.
+    let mut mixture = GaussianMixture::<f64, U2, 4>::new();
+    let good = StateAndCovariance::new(Vector2::new(1.0, 2.0), Matrix2::identity() * 0.1);
+    let singular = StateAndCovariance::new(Vector2::new(-1.0, -2.0), Matrix2::zeros());
+    mixture.push(0.25, good.clone()).unwrap();
+    mixture.push(0.75, singular).unwrap();
+    let zero_noise = |e: &StateAndCovariance<f64, U2>| {
+        let s = e.state();
+        Linearized::new(|s| s.x.powi(3), Matrix1x2::new(3.0 * s.x * s.x, 0.0), 0.0)
+    };
+    assert!(filter
+        .update_linearized(&mut mixture, &Vector1::new(1.0), method, zero_noise)
+        .is_err());
+    let weights: [f64; 2] = [0.25, 0.75];
+    for (component, weight) in mixture.iter().zip(weights) {
+        assert_eq!(component.weight(), weight);
+    }
+    assert_eq!(
+        mixture.iter().next().unwrap().estimate().state(),
+        good.state()
+    );
+
+    // A component which cannot be factorized stays in the mixture.
+    assert!(filter.reduce(&mut mixture).is_err());
+    assert_eq!(mixture.len(), 2);
+}
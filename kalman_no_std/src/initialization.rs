@@ -0,0 +1,154 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, Error, ErrorKind, ObservationModel, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// Initialize an estimate from the first observations by weighted least
+/// squares
+///
+/// Finds the state `x0` at the time of the first observation which best
+/// explains `observations[k] = H F^k x0 + v_k`, weighting each residual by
+/// `R^-1`, and returns it predicted forward to the time of the last
+/// observation. Filtering can then continue with the following observations.
+/// Missing (NaN) observations are skipped.
+///
+/// Process noise between the observations is neglected in the fit, so use a
+/// short batch. It is included when predicting to the last observation.
+///
+/// If `prior` is given, it is an estimate at the time of the first
+/// observation which is combined with the observations. A diffuse prior, as
+/// made by [`StateAndCovariance::diffuse`](struct.StateAndCovariance.html#method.diffuse),
+/// fixes the state components the observations do not determine while
+/// barely affecting the others.
+///
+/// Returns `ErrorKind::InsufficientObservations` if the state is not
+/// determined by the observations and the prior.
+pub fn batch_initialize<R, SS, OS>(
+    transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &dyn ObservationModel<R, SS, OS>,
+    observations: &[OVector<R, OS>],
+    prior: Option<&StateAndCovariance<R, SS>>,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    let r_chol = na::linalg::Cholesky::new(observation_model.R().clone())
+        .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?;
+
+    // Normal equations `information * x0 = information_state`.
+    let (mut information, mut information_state) = match prior {
+        Some(prior) => {
+            let prior_chol = na::linalg::Cholesky::new(prior.covariance().clone())
+                .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?;
+            (prior_chol.inverse(), prior_chol.solve(prior.state()))
+        }
+        None => (OMatrix::<R, SS, SS>::zeros(), OVector::<R, SS>::zeros()),
+    };
+
+    // `transition` is `F^k` for observation `k`.
+    let mut transition = OMatrix::<R, SS, SS>::identity();
+    for (k, observation) in observations.iter().enumerate() {
+        if k > 0 {
+            transition = transition_model.F() * transition;
+        }
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            continue;
+        }
+        let h: OMatrix<R, OS, SS> = observation_model.H() * &transition;
+        let weighted_h = r_chol.solve(&h);
+        information += h.transpose() * &weighted_h;
+        information_state += weighted_h.transpose() * observation;
+    }
+
+    let information_chol =
+        na::linalg::Cholesky::new(information).ok_or(ErrorKind::InsufficientObservations)?;
+    let first = StateAndCovariance::new(
+        information_chol.solve(&information_state),
+        information_chol.inverse(),
+    );
+    Ok(transition_model.predict_n(&first, observations.len().saturating_sub(1)))
+}
+
+#[test]
+fn test_batch_initialize() {
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    struct ConstantVelocity {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for ConstantVelocity {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    struct Position {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for Position {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let f = Matrix2::new(1.0, 1.0, 0.0, 1.0);
+    let motion = ConstantVelocity {
+        f,
+        ft: f.transpose(),
+        q: Matrix2::zeros(),
+    };
+    let position = Position {
+        h: Matrix1x2::new(1.0, 0.0),
+        ht: Matrix2x1::new(1.0, 0.0),
+        r: Matrix1::new(0.5),
+    };
+
+    // Two positions determine position and velocity exactly, as with
+    // two-point differencing.
+    let observations = [Vector1::new(1.0), Vector1::new(f64::NAN), Vector1::new(5.0)];
+    let estimate = batch_initialize(&motion, &position, &observations, None).unwrap();
+    approx::assert_relative_eq!(estimate.state(), &Vector2::new(5.0, 2.0), epsilon = 1e-12);
+    #[rustfmt::skip]
+    let expected = Matrix2::new(
+        0.5, 0.25,
+        0.25, 0.25,
+    );
+    approx::assert_relative_eq!(estimate.covariance(), &expected, epsilon = 1e-12);
+
+    // One position does not determine the velocity, unless with a prior.
+    let err = batch_initialize(&motion, &position, &observations[..1], None).unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::InsufficientObservations);
+    let diffuse = StateAndCovariance::diffuse(1e6);
+    let estimate =
+        batch_initialize(&motion, &position, &observations[..1], Some(&diffuse)).unwrap();
+    approx::assert_relative_eq!(estimate.state()[0], 1.0, epsilon = 1e-5);
+    approx::assert_relative_eq!(estimate.covariance()[(1, 1)], 1e6);
+}
@@ -0,0 +1,87 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, Dim, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::{Error, ErrorKind};
+
+/// The innovation (measurement residual) of an observation and its covariance
+///
+/// The innovation is the difference between an observation and the
+/// observation predicted from the prior state. Its covariance is
+/// `S = H P H^T + R`.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "OVector<R, OS>: serde::Serialize, OMatrix<R, OS, OS>: serde::Serialize",
+        deserialize = "OVector<R, OS>: serde::Deserialize<'de>, \
+                       OMatrix<R, OS, OS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct Innovation<R, OS>
+where
+    R: RealField,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    residual: OVector<R, OS>,
+    covariance: OMatrix<R, OS, OS>,
+}
+
+impl<R, OS> Innovation<R, OS>
+where
+    R: RealField,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    /// Create a new `Innovation` from the residual and its covariance.
+    pub fn new(residual: OVector<R, OS>, covariance: OMatrix<R, OS, OS>) -> Self {
+        Self {
+            residual,
+            covariance,
+        }
+    }
+    /// Get a reference to the residual, `z - h(x)`.
+    #[inline]
+    pub fn residual(&self) -> &OVector<R, OS> {
+        &self.residual
+    }
+    /// Get a reference to the innovation covariance, `S`.
+    #[inline]
+    pub fn covariance(&self) -> &OMatrix<R, OS, OS> {
+        &self.covariance
+    }
+
+    /// Normalized innovation squared (NIS), `y^T S^-1 y`.
+    ///
+    /// This is the squared Mahalanobis distance of the residual and is
+    /// chi-square distributed with `OS` degrees of freedom for a consistent
+    /// filter.
+    pub fn nis(&self) -> Result<R, Error> {
+        let (nis, _) = self.nis_and_ln_det()?;
+        Ok(nis)
+    }
+
+    /// Log-likelihood of the residual under the zero-mean Gaussian `N(0, S)`.
+    pub fn log_likelihood(&self) -> Result<R, Error> {
+        let (nis, ln_det) = self.nis_and_ln_det()?;
+        let dim: R = na::convert(self.residual.len() as f64);
+        let half: R = na::convert(0.5);
+        Ok(-half * (nis + ln_det + dim * R::two_pi().ln()))
+    }
+
+    fn nis_and_ln_det(&self) -> Result<(R, R), Error> {
+        let s_chol = match na::linalg::Cholesky::new(self.covariance.clone()) {
+            Some(v) => v,
+            None => {
+                return Err(ErrorKind::SingularInnovationCovariance.into());
+            }
+        };
+        let whitened = s_chol.solve(&self.residual);
+        Ok((self.residual.dot(&whitened), s_chol.ln_determinant()))
+    }
+}
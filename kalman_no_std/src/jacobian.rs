@@ -0,0 +1,164 @@
+use core::fmt;
+
+use na::allocator::Allocator;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+/// Compute the Jacobian of `f` at `x` by central finite differences
+///
+/// The step for component `j` is `eps^(1/3) * max(|x_j|, 1)`, which balances
+/// truncation and rounding errors for central differences. Use
+/// [`numerical_jacobian_with_steps`] to choose the steps.
+///
+/// With `f` the evaluation function of an observation model, this is the
+/// observation matrix `H` linearized at `x`. With `f` a transition function,
+/// it is `F`.
+pub fn numerical_jacobian<R, SS, OS, F>(f: F, x: &OVector<R, SS>) -> OMatrix<R, OS, SS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    F: Fn(&OVector<R, SS>) -> OVector<R, OS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+{
+    let scale = R::default_epsilon().cbrt();
+    let steps = x.map(|xj| scale.clone() * xj.abs().max(R::one()));
+    numerical_jacobian_with_steps(f, x, &steps)
+}
+
+/// Compute the Jacobian of `f` at `x` by central finite differences
+///
+/// `steps[j]` is the step used for component `j` of `x`.
+pub fn numerical_jacobian_with_steps<R, SS, OS, F>(
+    f: F,
+    x: &OVector<R, SS>,
+    steps: &OVector<R, SS>,
+) -> OMatrix<R, OS, SS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    F: Fn(&OVector<R, SS>) -> OVector<R, OS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+{
+    let mut jacobian = OMatrix::<R, OS, SS>::zeros();
+    let two: R = na::convert(2.0);
+    for (j, (mut column, step)) in jacobian.column_iter_mut().zip(steps.iter()).enumerate() {
+        let mut forward = x.clone();
+        let mut backward = x.clone();
+        if let (Some(xf), Some(xb)) = (forward.get_mut(j), backward.get_mut(j)) {
+            *xf += step.clone();
+            *xb -= step.clone();
+        }
+        column.copy_from(&((f(&forward) - f(&backward)) / (two.clone() * step.clone())));
+    }
+    jacobian
+}
+
+/// The largest disagreement found by [`check_jacobian`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JacobianMismatch<R: RealField> {
+    /// Row of the worst entry.
+    pub row: usize,
+    /// Column of the worst entry.
+    pub column: usize,
+    /// Value of the worst entry in the Jacobian being checked.
+    pub analytic: R,
+    /// Value of the worst entry in the numerical Jacobian.
+    pub numerical: R,
+    /// Number of entries outside the tolerance.
+    pub count: usize,
+}
+
+impl<R: RealField + fmt::Display> fmt::Display for JacobianMismatch<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} Jacobian entries mismatch, worst at ({}, {}): analytic {}, numerical {}",
+            self.count, self.row, self.column, self.analytic, self.numerical
+        )
+    }
+}
+
+/// Compare a hand-written Jacobian of `f` at `x` with the numerical one
+///
+/// An entry mismatches if it differs from the numerical value by more than
+/// `tolerance * max(|numerical|, 1)`. Returns the worst mismatch, if any.
+/// This is intended as a debug check for analytic derivations, for example
+/// of the observation matrix `H`.
+pub fn check_jacobian<R, SS, OS, F>(
+    f: F,
+    x: &OVector<R, SS>,
+    analytic: &OMatrix<R, OS, SS>,
+    tolerance: R,
+) -> Result<(), JacobianMismatch<R>>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    F: Fn(&OVector<R, SS>) -> OVector<R, OS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+{
+    let numerical = numerical_jacobian(f, x);
+    let mut worst: Option<(R, JacobianMismatch<R>)> = None;
+    let mut count = 0;
+    for (column, (a_col, n_col)) in analytic
+        .column_iter()
+        .zip(numerical.column_iter())
+        .enumerate()
+    {
+        for (row, (a, n)) in a_col.iter().zip(n_col.iter()).enumerate() {
+            let excess = (a.clone() - n.clone()).abs() / n.clone().abs().max(R::one());
+            // A NaN entry is always a mismatch.
+            if excess <= tolerance {
+                continue;
+            }
+            count += 1;
+            if worst.as_ref().is_none_or(|(w, _)| excess > *w) {
+                let mismatch = JacobianMismatch {
+                    row,
+                    column,
+                    analytic: a.clone(),
+                    numerical: n.clone(),
+                    count: 0,
+                };
+                worst = Some((excess, mismatch));
+            }
+        }
+    }
+    match worst {
+        Some((_, mismatch)) => Err(JacobianMismatch { count, ..mismatch }),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_numerical_jacobian() {
+    use na::{Matrix2x4, Vector2, Vector4};
+
+    // The observation [x^3, xy] of a 2D constant velocity state.
+    let f = |s: &Vector4<f64>| Vector2::new(s.x * s.x * s.x, s.x * s.y);
+    let state = Vector4::new(1.5, -2.0, 10.0, -5.0);
+    #[rustfmt::skip]
+    let expected = Matrix2x4::new(
+        3.0 * state.x * state.x, 0.0, 0.0, 0.0,
+        state.y, state.x, 0.0, 0.0,
+    );
+
+    let h: Matrix2x4<f64> = numerical_jacobian(f, &state);
+    approx::assert_relative_eq!(h, expected, epsilon = 1e-8);
+    assert_eq!(check_jacobian(f, &state, &expected, 1e-6), Ok(()));
+
+    let mut wrong = expected;
+    wrong[(0, 0)] = state.x * state.x;
+    wrong[(1, 1)] = f64::NAN;
+    let mismatch = check_jacobian(f, &state, &wrong, 1e-6).unwrap_err();
+    assert_eq!((mismatch.row, mismatch.column, mismatch.count), (0, 0, 2));
+}
@@ -1,25 +1,118 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(non_snake_case)]
+// Public entry points report failures as `Result` rather than panicking.
+// `cargo clippy` enforces this for everything but tests.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::indexing_slicing,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
 #[cfg(feature = "std")]
+#[macro_use]
+extern crate log;
 
-extern crate nalgebra;
-
-use nalgebra::{ Matrix2, Matrix3, Matrix3x2, Vector1, Vector2, Vector3, Matrix2x3 };
 use nalgebra::allocator::Allocator;
-use nalgebra::{DefaultAllocator, DimName, RealField};
+use nalgebra::{DefaultAllocator, Dim, RealField};
 use nalgebra::{OMatrix, OVector};
-use nalgebra::base::DimRange;
 use nalgebra as na;
 
 
 mod state_cov;
 pub use state_cov::StateAndCovariance;
 
+#[cfg(feature = "serde")]
+mod serde_array;
+
 mod error;
 pub use error::{Error, ErrorKind};
 
+mod innovation;
+pub use innovation::Innovation;
+
+mod gaussian_sum;
+pub use gaussian_sum::{GaussianMixture, GaussianSumFilter, MixtureReduction, WeightedComponent};
+
+mod fusion;
+pub use fusion::{
+    bar_shalom_campo, covariance_intersection, covariance_intersection_with_weight,
+    FusionCriterion,
+};
+
+mod optimize;
+
+mod forecast;
+pub use forecast::Forecast;
+
+mod multi_sensor;
+pub use multi_sensor::{MultiSensorKalmanFilter, Sensor, SensorDiagnostics};
+
+mod alpha_beta;
+pub use alpha_beta::{
+    AlphaBetaFilter, AlphaBetaGains, AlphaBetaGammaFilter, AlphaBetaGammaGains,
+};
+
+mod tracking;
+pub use tracking::{assign, MultiTargetTracker, Track, TrackManagement, TrackStatus};
+
+mod conditioning;
+pub use conditioning::{CovarianceConditioning, CovarianceHealth};
+
+mod owned_filter;
+pub use owned_filter::KalmanFilter;
+
+mod online;
+pub use online::{OnlineTracker, TrackerSnapshot};
+
+mod jacobian;
+pub use jacobian::{
+    check_jacobian, numerical_jacobian, numerical_jacobian_with_steps, JacobianMismatch,
+};
+
+mod dual;
+pub use dual::{dual_jacobian, Dual};
+
+mod initialization;
+pub use initialization::batch_initialize;
+
+mod correlated_noise;
+
+mod consistency;
+pub use consistency::{
+    chi_square_quantile, evaluate_consistency, Autocorrelation, ChiSquareTest,
+    ConsistencyEvaluator, ConsistencyReport,
+};
+
+mod schmidt;
+pub use schmidt::ConsiderObservationModel;
+
+mod cloning;
+pub use cloning::{AugmentedTransitionModel, RelativeObservationModel};
+
+mod constraints;
+pub use constraints::{ConstrainedObservationModel, LinearConstraints};
+
+mod moving_horizon;
+pub use moving_horizon::MovingHorizonEstimator;
+
+mod workspace;
+pub use workspace::UpdateWorkspace;
+
+mod shape;
+
+mod smoother;
+pub use smoother::SmoothedEstimate;
+
+mod event_triggered;
+pub use event_triggered::{CommunicationStatistics, EventTriggeredEstimator, EventTriggeredSensor};
+
 use nalgebra::base::dimension::DimMin;
-use num_traits::identities::One;
 
 #[cfg(not(feature = "std"))]
 macro_rules! trace {
@@ -32,12 +125,12 @@ macro_rules! trace {
 macro_rules! pretty_print {
     ($arr:expr) => {{
         let indent = 4;
-        let prefix = String::from_utf8(vec![b' '; indent]).unwrap();
+        let prefix = " ".repeat(indent);
         let mut result_els = vec!["".to_string()];
-        for i in 0..$arr.nrows() {
+        for row in $arr.row_iter() {
             let mut row_els = vec![];
-            for j in 0..$arr.ncols() {
-                row_els.push(format!("{:12.3}", $arr[(i, j)]));
+            for el in row.iter() {
+                row_els.push(format!("{:12.3}", el));
             }
             let row_str = row_els.into_iter().collect::<Vec<_>>().join(" ");
             let row_str = format!("{}{}", prefix, row_str);
@@ -52,7 +145,7 @@ macro_rules! debug_assert_symmetric {
         #[cfg(debug_assertions)]
         {
             if approx::relative_ne!($mat, &$mat.transpose(), max_relative = na::convert(1e-5)) {
-                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+                return Err(ErrorKind::AsymmetricCovariance.into());
             }
         }
     };
@@ -62,7 +155,7 @@ macro_rules! debug_assert_symmetric {
 pub trait TransitionModelLinearNoControl<R, SS>
 where
     R: RealField,
-    SS: DimName,
+    SS: Dim,
     DefaultAllocator: Allocator<R, SS, SS>,
     DefaultAllocator: Allocator<R, SS>,
 {
@@ -84,14 +177,81 @@ where
         let covariance = ((F * previous_estimate.covariance()) * self.FT()) + self.Q();
         StateAndCovariance::new(state, covariance)
     }
+
+    /// Predict successive states, writing one prediction per interval.
+    ///
+    /// `forecast[i]` is the prediction `i + 1` intervals after `estimate`. The
+    /// number of intervals for a horizon of `T` seconds is `T / dt`, where
+    /// `dt` is the interval of the transition model.
+    fn forecast_into(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+        forecast: &mut [StateAndCovariance<R, SS>],
+    ) {
+        let mut current = estimate.clone();
+        for prediction in forecast.iter_mut() {
+            current = self.predict(&current);
+            *prediction = current.clone();
+        }
+    }
+
+    /// Predict `n` intervals ahead.
+    ///
+    /// For a time-invariant model, this is equivalent to calling
+    /// [`predict`](#method.predict) `n` times, but the transition
+    /// `(F^n, Q_n)` is formed by repeated squaring in `O(log n)` matrix
+    /// products, where `Q_n = sum_k F^k Q F^k^T` for `k` in `0..n`.
+    fn predict_n(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+        n: usize,
+    ) -> StateAndCovariance<R, SS> {
+        let (rows, columns) = self.F().shape_generic();
+        let mut f_n = OMatrix::<R, SS, SS>::identity_generic(rows, columns);
+        let mut q_n = OMatrix::<R, SS, SS>::zeros_generic(rows, columns);
+        let mut f_pow = self.F().clone();
+        let mut q_pow = self.Q().clone();
+        let mut remaining = n;
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                q_n = &f_pow * q_n * f_pow.transpose() + &q_pow;
+                f_n = &f_pow * f_n;
+            }
+            remaining >>= 1;
+            if remaining > 0 {
+                q_pow = &f_pow * &q_pow * f_pow.transpose() + &q_pow;
+                f_pow = &f_pow * &f_pow;
+            }
+        }
+        let state = &f_n * estimate.state();
+        let covariance = &f_n * estimate.covariance() * f_n.transpose() + q_n;
+        StateAndCovariance::new(state, covariance)
+    }
+}
+
+/// A linear model of process dynamics which can be discretized for any interval
+///
+/// Used by [`OnlineTracker`] to predict to arbitrary timestamps.
+pub trait ContinuousTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// The discrete-time model for a fixed interval.
+    type Discrete: TransitionModelLinearNoControl<R, SS>;
+
+    /// Get the discrete-time model for an interval of `dt`.
+    fn discretize(&self, dt: R) -> Self::Discrete;
 }
 
 /// An observation model, potentially non-linear.
 pub trait ObservationModel<R, SS, OS>
 where
     R: RealField,
-    SS: DimName,
-    OS: DimName + DimMin<OS, Output = OS>,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
     DefaultAllocator: Allocator<R, SS, SS>,
     DefaultAllocator: Allocator<R, SS>,
     DefaultAllocator: Allocator<R, OS, SS>,
@@ -113,15 +273,37 @@ where
     /// Get the observation noise covariance, `R`.
     fn R(&self) -> &OMatrix<R, OS, OS>;
 
+    /// Given prior state and observation, compute the innovation.
+    ///
+    /// The innovation covariance is `S = H P H^T + R`.
+    fn innovation(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Innovation<R, OS> {
+        let s = (self.H() * prior.covariance() * self.HT()) + self.R();
+        let residual = observation - self.predict_observation(prior.state());
+        Innovation::new(residual, s)
+    }
+
     /// Given prior state and observation, estimate the posterior state.
     ///
-    /// This is the *update* step in the Kalman filter literature.
+    /// This is the *update* step in the Kalman filter literature. The Kalman
+    /// gain is found by a Cholesky solve with the innovation covariance
+    /// rather than by inverting it. See
+    /// [`update_in_place`](#method.update_in_place) for an update which
+    /// keeps its intermediates in a workspace.
+    ///
+    /// Returns `ErrorKind::DimensionMismatch` if the sizes of `prior`,
+    /// `observation` and the model disagree, which can only happen with `Dyn`
+    /// dimensions.
     fn update(
         &self,
         prior: &StateAndCovariance<R, SS>,
         observation: &OVector<R, OS>,
         covariance_method: CovarianceUpdateMethod,
     ) -> Result<StateAndCovariance<R, SS>, Error> {
+        shape::check_observation(self, prior, observation)?;
         let h = self.H();
         trace!("h {}", pretty_print!(h));
 
@@ -141,23 +323,22 @@ where
         // positive definite. If p is positive definite, then (h*p*ht) is at
         // least positive semi-definite. If h is full rank, it is positive
         // definite.
-        let s = (h * p * ht) + r;
+        let hp: OMatrix<R, OS, SS> = h * p;
+        let s = &hp * ht + r;
         trace!("s {}", pretty_print!(s));
 
-        // Calculate kalman gain by inverting.
+        // Calculate kalman gain by solving `s * k_gain^T = h * p`, which
+        // holds because p is symmetric.
         let s_chol = match na::linalg::Cholesky::new(s) {
             Some(v) => v,
             None => {
-                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+                return Err(ErrorKind::SingularInnovationCovariance.into());
             }
         };
-        let s_inv: OMatrix<R, OS, OS> = s_chol.inverse();
-        trace!("s_inv {}", pretty_print!(s_inv));
-
-        let k_gain: OMatrix<R, SS, OS> = p * ht * s_inv;
-        // let k_gain: OMatrix<R,SS,OS> = solve!( (p*ht), s );
+        let mut gain_t = hp;
+        workspace::cholesky_solve_mut(&s_chol.unpack_dirty(), &mut gain_t);
+        let k_gain: OMatrix<R, SS, OS> = gain_t.transpose();
         trace!("k_gain {}", pretty_print!(k_gain));
-
         let predicted: OVector<R, OS> = self.predict_observation(prior.state());
         trace!("predicted {}", pretty_print!(predicted));
         trace!("observation {}", pretty_print!(observation));
@@ -165,11 +346,15 @@ where
         trace!("innovation {}", pretty_print!(innovation));
         let state: OVector<R, SS> = prior.state() + &k_gain * innovation;
         trace!("state {}", pretty_print!(state));
+        if !state.iter().all(|x| x.is_finite()) {
+            return Err(ErrorKind::NonFiniteState.into());
+        }
 
         trace!("self.observation_matrix() {}", pretty_print!(self.H()));
         let kh: OMatrix<R, SS, SS> = &k_gain * self.H();
         trace!("kh {}", pretty_print!(kh));
-        let one_minus_kh = OMatrix::<R, SS, SS>::one() - kh;
+        let (rows, columns) = kh.shape_generic();
+        let one_minus_kh = OMatrix::<R, SS, SS>::identity_generic(rows, columns) - kh;
         trace!("one_minus_kh {}", pretty_print!(one_minus_kh));
 
         let covariance: OMatrix<R, SS, SS> = match covariance_method {
@@ -196,6 +381,126 @@ where
         Ok(StateAndCovariance::new(state, covariance))
     }
 
+    /// Given an estimate and observation, update the estimate in place.
+    ///
+    /// All intermediates are kept in `workspace`. The Kalman gain is found
+    /// by a Cholesky solve with the innovation covariance rather than by
+    /// inverting it. Apart from `predict_observation`, which returns a new
+    /// vector, this does not allocate. If the innovation covariance is
+    /// singular or the posterior state is not finite, `estimate` is left
+    /// unchanged.
+    ///
+    /// The workspace is used only with `Dyn` dimensions, where it saves the
+    /// allocations of [`update`](#method.update). With static dimensions
+    /// nothing is allocated anyway, so this calls `update`, whose
+    /// temporaries the compiler keeps in registers.
+    ///
+    /// A model which overrides [`update`](#method.update) should override
+    /// this method consistently.
+    fn update_in_place(
+        &self,
+        estimate: &mut StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_method: CovarianceUpdateMethod,
+        workspace: &mut UpdateWorkspace<R, SS, OS>,
+    ) -> Result<(), Error> {
+        if workspace::is_static::<SS, OS>() {
+            *estimate = self.update(estimate, observation, covariance_method)?;
+            return Ok(());
+        }
+        shape::check_observation(self, estimate, observation)?;
+        workspace.check_shape(estimate.state().nrows(), self.H().nrows())?;
+        let h = self.H();
+        trace!("h {}", pretty_print!(h));
+
+        let p = estimate.covariance();
+        trace!("p {}", pretty_print!(p));
+        debug_assert_symmetric!(p);
+
+        let r = self.R();
+        trace!("r {}", pretty_print!(r));
+
+        // Calculate innovation covariance
+        //
+        // Math note: if (h*p*ht) and r are positive definite, s is also
+        // positive definite. If p is positive definite, then (h*p*ht) is at
+        // least positive semi-definite. If h is full rank, it is positive
+        // definite.
+        h.mul_to(p, &mut workspace.hp);
+        let mut s = workspace.take_s();
+        s.copy_from(r);
+        s.gemm(R::one(), &workspace.hp, self.HT(), R::one());
+        trace!("s {}", pretty_print!(s));
+
+        // Calculate kalman gain by solving `s * k_gain^T = h * p`, which
+        // holds because p is symmetric.
+        let s_chol = match na::linalg::Cholesky::new(s) {
+            Some(v) => v,
+            None => {
+                return Err(ErrorKind::SingularInnovationCovariance.into());
+            }
+        };
+        let factor = s_chol.unpack_dirty();
+        workspace.gain_t.copy_from(&workspace.hp);
+        workspace::cholesky_solve_mut(&factor, &mut workspace.gain_t);
+        workspace.s = Some(factor);
+        trace!("k_gain^T {}", pretty_print!(workspace.gain_t));
+
+        let predicted: OVector<R, OS> = self.predict_observation(estimate.state());
+        trace!("predicted {}", pretty_print!(predicted));
+        trace!("observation {}", pretty_print!(observation));
+        workspace.innovation.copy_from(observation);
+        workspace.innovation -= predicted;
+        trace!("innovation {}", pretty_print!(workspace.innovation));
+        workspace.state.copy_from(estimate.state());
+        workspace
+            .state
+            .gemv_tr(R::one(), &workspace.gain_t, &workspace.innovation, R::one());
+        trace!("state {}", pretty_print!(workspace.state));
+        if !workspace.state.iter().all(|x| x.is_finite()) {
+            return Err(ErrorKind::NonFiniteState.into());
+        }
+
+        match covariance_method {
+            CovarianceUpdateMethod::JosephForm => {
+                // Joseph form of covariance update keeps covariance matrix symmetric.
+                //
+                // (I - K H) P (I - K H)^T + K R K^T, with
+                // (I - K H)^T = I - H^T K^T.
+                let one_minus_kh_t = &mut workspace.one_minus_kh_t;
+                one_minus_kh_t.fill_with_identity();
+                one_minus_kh_t.gemm_tr(-R::one(), h, &workspace.gain_t, R::one());
+                trace!("one_minus_kh^T {}", pretty_print!(one_minus_kh_t));
+                p.mul_to(one_minus_kh_t, &mut workspace.product);
+                r.mul_to(&workspace.gain_t, &mut workspace.hp);
+                let covariance = estimate.covariance_mut();
+                one_minus_kh_t.tr_mul_to(&workspace.product, covariance);
+                covariance.gemm_tr(R::one(), &workspace.gain_t, &workspace.hp, R::one());
+            }
+            CovarianceUpdateMethod::OptimalKalman => {
+                // P - K H P
+                let covariance = estimate.covariance_mut();
+                covariance.gemm_tr(-R::one(), &workspace.gain_t, &workspace.hp, R::one());
+            }
+            CovarianceUpdateMethod::OptimalKalmanForcedSymmetric => {
+                let covariance = estimate.covariance_mut();
+                covariance.gemm_tr(-R::one(), &workspace.gain_t, &workspace.hp, R::one());
+                trace!("covariance1 {}", pretty_print!(covariance));
+                // Hack to force covariance to be symmetric.
+                // See https://math.stackexchange.com/q/2335831
+                covariance.transpose_to(&mut workspace.product);
+                *covariance += &workspace.product;
+                *covariance *= na::convert::<f64, R>(0.5);
+            }
+        }
+        estimate.state_mut().copy_from(&workspace.state);
+        trace!("covariance {}", pretty_print!(estimate.covariance()));
+
+        debug_assert_symmetric!(estimate.covariance());
+
+        Ok(())
+    }
+
     /// For a given state, predict the observation.
     #[inline]
     fn evaluate(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
@@ -205,6 +510,7 @@ where
 
 /// Specifies the approach used for updating the covariance matrix
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CovarianceUpdateMethod {
     /// Assumes optimal Kalman gain.
     ///
@@ -221,11 +527,14 @@ pub enum CovarianceUpdateMethod {
 
 /// A Kalman filter with no control inputs, a linear process model and linear
 /// observation model
+///
+/// The dimensions may be static or, with the `std` feature, `Dyn`, so that
+/// the state and observation sizes can be configured at runtime.
 pub struct KalmanFilterNoControl<'a, R, SS, OS>
 where
     R: RealField,
-    SS: DimName,
-    OS: DimName,
+    SS: Dim,
+    OS: Dim,
 {
     transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
     observation_matrix: &'a dyn ObservationModel<R, SS, OS>,
@@ -234,8 +543,8 @@ where
 impl<'a, R, SS, OS> KalmanFilterNoControl<'a, R, SS, OS>
 where
     R: RealField,
-    SS: DimName,
-    OS: DimName + DimMin<OS, Output = OS>,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
     DefaultAllocator: Allocator<R, SS, SS>,
     DefaultAllocator: Allocator<R, SS>,
     DefaultAllocator: Allocator<R, OS, SS>,
@@ -261,6 +570,36 @@ where
         }
     }
 
+    /// Get the state transition model.
+    pub fn transition_model(&self) -> &'a dyn TransitionModelLinearNoControl<R, SS> {
+        self.transition_model
+    }
+
+    /// Get the observation model.
+    pub fn observation_model(&self) -> &'a dyn ObservationModel<R, SS, OS> {
+        self.observation_matrix
+    }
+
+    /// The statically dispatched filter of the two trait objects, which
+    /// holds the logic of the steps.
+    #[inline]
+    fn generic(
+        &self,
+    ) -> KalmanFilter<
+        &'a dyn TransitionModelLinearNoControl<R, SS>,
+        &'a dyn ObservationModel<R, SS, OS>,
+    > {
+        KalmanFilter::new(self.transition_model, self.observation_matrix)
+    }
+
+    /// Iterate over predictions of successive intervals after `estimate`.
+    ///
+    /// The iterator is unbounded; use `take(n)` for a horizon of `n`
+    /// intervals.
+    pub fn forecast(&self, estimate: &StateAndCovariance<R, SS>) -> Forecast<'a, R, SS> {
+        Forecast::new(self.transition_model, estimate.clone())
+    }
+
     /// Perform Kalman prediction and update steps with default values
     ///
     /// If any component of the observation is NaN (not a number), the
@@ -295,21 +634,170 @@ where
     /// This calls the prediction step of the transition model and then, if
     /// there is a (non-`nan`) observation, calls the update step of the
     /// observation model using the specified covariance update method.
+    ///
+    /// Returns `ErrorKind::DimensionMismatch` if the sizes of the estimate,
+    /// the observation and the models disagree.
     pub fn step_with_options(
         &self,
         previous_estimate: &StateAndCovariance<R, SS>,
         observation: &OVector<R, OS>,
         covariance_update_method: CovarianceUpdateMethod,
     ) -> Result<StateAndCovariance<R, SS>, Error> {
+        self.generic()
+            .step_with_options(previous_estimate, observation, covariance_update_method)
+    }
+
+    /// Perform Kalman prediction and update steps in place
+    ///
+    /// This is [step_with_options](struct.KalmanFilterNoControl.html#method.step_with_options)
+    /// with all intermediates kept in `workspace`, so that a hot loop can
+    /// reuse them. Missing (NaN) observations are handled as in
+    /// [step](struct.KalmanFilterNoControl.html#method.step). If the update
+    /// fails, `estimate` holds the prediction.
+    ///
+    /// The workspace is used only with `Dyn` dimensions. With static
+    /// dimensions this performs the steps of
+    /// [step_with_options](struct.KalmanFilterNoControl.html#method.step_with_options),
+    /// which allocate nothing and are faster than writing through the
+    /// workspace.
+    pub fn step_in_place(
+        &self,
+        estimate: &mut StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+        workspace: &mut UpdateWorkspace<R, SS, OS>,
+    ) -> Result<(), Error> {
+        self.generic()
+            .step_in_place(estimate, observation, covariance_update_method, workspace)
+    }
+
+    /// Perform Kalman prediction and update steps with measurement noise
+    /// correlated with the process noise
+    ///
+    /// `cross_covariance` is `M = E[w v^T]`, the covariance between the
+    /// process noise `w` of this prediction and the measurement noise `v` of
+    /// `observation`, as arises when a sensor disturbance also drives the
+    /// state. With `M` zero this is
+    /// [step_with_options](struct.KalmanFilterNoControl.html#method.step_with_options).
+    ///
+    /// Missing (NaN) observations are handled as in
+    /// [step](struct.KalmanFilterNoControl.html#method.step).
+    pub fn step_correlated(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        cross_covariance: &OMatrix<R, SS, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        shape::check_transition(self.transition_model, previous_estimate)?;
         let prior = self.transition_model.predict(previous_estimate);
         if observation.iter().any(|x| is_nan(x.clone())) {
             Ok(prior)
         } else {
-            self.observation_matrix
-                .update(&prior, observation, covariance_update_method)
+            correlated_noise::update_correlated(
+                self.observation_matrix,
+                &prior,
+                observation,
+                cross_covariance,
+                covariance_update_method,
+            )
         }
     }
 
+    /// Perform Kalman prediction and update steps with colored measurement
+    /// noise
+    ///
+    /// The measurement noise is modelled as `v[k] = Psi v[k-1] + xi`, with
+    /// `noise_transition` the matrix `Psi` and the observation model's `R`
+    /// the covariance of the white noise `xi`. Each step uses the difference
+    /// `observation - Psi previous_observation`, whose noise is white, so the
+    /// caller passes the observation of the previous step as well. The result
+    /// is the estimate at the time of `observation`, as with
+    /// [step](struct.KalmanFilterNoControl.html#method.step).
+    ///
+    /// The very first observation only enters through its difference with
+    /// the second. If either observation is missing (NaN), only the
+    /// prediction is performed. With `Psi` zero this is
+    /// [step_with_options](struct.KalmanFilterNoControl.html#method.step_with_options).
+    pub fn step_colored(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        previous_observation: &OVector<R, OS>,
+        observation: &OVector<R, OS>,
+        noise_transition: &OMatrix<R, OS, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        shape::check_transition(self.transition_model, previous_estimate)?;
+        if observation
+            .iter()
+            .chain(previous_observation.iter())
+            .any(|x| is_nan(x.clone()))
+        {
+            return Ok(self.transition_model.predict(previous_estimate));
+        }
+        correlated_noise::step_colored(
+            self.transition_model,
+            self.observation_matrix,
+            previous_estimate,
+            previous_observation,
+            observation,
+            noise_transition,
+            covariance_update_method,
+        )
+    }
+
+    /// Perform Kalman prediction and update steps, repairing the covariance
+    ///
+    /// The prior and posterior covariances are conditioned according to
+    /// `conditioning`. If the update fails even with the conditioned prior,
+    /// the prior covariance is reset to `conditioning.reset_covariance` (if
+    /// given) and the update is retried. If that also fails, the observation
+    /// is skipped and the prior is returned. Every repair is counted in
+    /// `health`, so this never returns an error from the update step.
+    ///
+    /// Missing (NaN) observations are handled as in
+    /// [step](struct.KalmanFilterNoControl.html#method.step).
+    pub fn step_conditioned(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        conditioning: &CovarianceConditioning<R, SS>,
+        health: &mut CovarianceHealth,
+    ) -> StateAndCovariance<R, SS>
+    where
+        SS: na::DimSub<na::U1>,
+        DefaultAllocator: Allocator<R, na::DimDiff<SS, na::U1>>,
+    {
+        let mut prior = self.transition_model.predict(previous_estimate);
+        conditioning.condition(prior.covariance_mut(), health);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            return prior;
+        }
+
+        let method = CovarianceUpdateMethod::JosephForm;
+        let mut posterior = match self.observation_matrix.update(&prior, observation, method) {
+            Ok(posterior) => posterior,
+            Err(_) => {
+                let retried = conditioning.reset_covariance.as_ref().and_then(|reset| {
+                    health.resets = health.resets.saturating_add(1);
+                    *prior.covariance_mut() = reset.clone();
+                    self.observation_matrix
+                        .update(&prior, observation, method)
+                        .ok()
+                });
+                match retried {
+                    Some(posterior) => posterior,
+                    None => {
+                        health.skipped_updates = health.skipped_updates.saturating_add(1);
+                        return prior;
+                    }
+                }
+            }
+        };
+        conditioning.condition(posterior.covariance_mut(), health);
+        posterior
+    }
+
     /// Kalman filter (operates on in-place data without allocating)
     ///
     /// Operates on entire time series (by repeatedly calling
@@ -319,23 +807,17 @@ where
     /// `dt` specified in the motion model.
     ///
     /// If any observation has a NaN component, it is treated as missing.
+    ///
+    /// Returns `ErrorKind::DimensionMismatch` if `state_estimates` is shorter
+    /// than `observations`. Errors from a step record the index of that step.
     pub fn filter_inplace(
         &self,
         initial_estimate: &StateAndCovariance<R, SS>,
         observations: &[OVector<R, OS>],
         state_estimates: &mut [StateAndCovariance<R, SS>],
     ) -> Result<(), Error> {
-        let mut previous_estimate = initial_estimate.clone();
-        assert!(state_estimates.len() >= observations.len());
-
-        for (this_observation, state_estimate) in
-            observations.iter().zip(state_estimates.iter_mut())
-        {
-            let this_estimate = self.step(&previous_estimate, this_observation)?;
-            *state_estimate = this_estimate.clone();
-            previous_estimate = this_estimate;
-        }
-        Ok(())
+        self.generic()
+            .filter_inplace(initial_estimate, observations, state_estimates)
     }
 
     /// Kalman filter
@@ -348,9 +830,8 @@ where
         observations: &[OVector<R, OS>],
     ) -> Result<Vec<StateAndCovariance<R, SS>>, Error> {
         let mut state_estimates = Vec::with_capacity(observations.len());
-        let empty = StateAndCovariance::new(na::zero(), na::OMatrix::<R, SS, SS>::identity());
         for _ in 0..observations.len() {
-            state_estimates.push(empty.clone());
+            state_estimates.push(initial_estimate.clone());
         }
         self.filter_inplace(initial_estimate, observations, &mut state_estimates)?;
         Ok(state_estimates)
@@ -364,10 +845,6 @@ where
     /// and returns a vector of state estimates. To be mathematically correct,
     /// the interval between observations must be the `dt` specified in the
     /// motion model.
-
-    /// Operates on entire time series in one shot and returns a vector of state
-    /// estimates. To be mathematically correct, the interval between
-    /// observations must be the `dt` specified in the motion model.
     ///
     /// If any observation has a NaN component, it is treated as missing.
     #[cfg(feature = "std")]
@@ -385,6 +862,8 @@ where
     /// Operates on entire time series in one shot and returns a vector of state
     /// estimates. To be mathematically correct, the interval between
     /// observations must be the `dt` specified in the motion model.
+    ///
+    /// Empty input gives empty output.
     #[cfg(feature = "std")]
     pub fn smooth_from_filtered(
         &self,
@@ -394,29 +873,91 @@ where
 
         let mut smoothed_backwards = Vec::with_capacity(forward_results.len());
 
-        let mut smooth_future = forward_results[0].clone();
+        let mut smooth_future = match forward_results.first() {
+            Some(v) => v.clone(),
+            None => return Ok(smoothed_backwards),
+        };
         smoothed_backwards.push(smooth_future.clone());
-        for filt in forward_results.iter().skip(1) {
-            smooth_future = self.smooth_step(&smooth_future, filt)?;
+        let last = forward_results.len() - 1;
+        for (i, filt) in forward_results.iter().enumerate().skip(1) {
+            smooth_future = self
+                .smooth_step(&smooth_future, filt)
+                .map_err(|e| e.at_step(last - i))?
+                .estimate;
             smoothed_backwards.push(smooth_future.clone());
         }
 
         smoothed_backwards.reverse();
         Ok(smoothed_backwards)
     }
-    
+
+    /// Rauch-Tung-Striebel (RTS) smoother with smoother gains and lag-one
+    /// cross-covariances
+    ///
+    /// This is [`smooth`](struct.KalmanFilterNoControl.html#method.smooth)
+    /// returning a [`SmoothedEstimate`] for each observation.
+    #[cfg(feature = "std")]
+    pub fn smooth_full(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<SmoothedEstimate<R, SS>>, Error> {
+        let forward_results = self.filter(initial_estimate, observations)?;
+        self.smooth_full_from_filtered(forward_results)
+    }
+
+    /// Rauch-Tung-Striebel (RTS) smoother with smoother gains and lag-one
+    /// cross-covariances, using already Kalman filtered estimates
+    ///
+    /// This is
+    /// [`smooth_from_filtered`](struct.KalmanFilterNoControl.html#method.smooth_from_filtered)
+    /// returning a [`SmoothedEstimate`] for each estimate. Empty input gives
+    /// empty output.
+    #[cfg(feature = "std")]
+    pub fn smooth_full_from_filtered(
+        &self,
+        mut forward_results: Vec<StateAndCovariance<R, SS>>,
+    ) -> Result<Vec<SmoothedEstimate<R, SS>>, Error> {
+        let mut smoothed_backwards: Vec<SmoothedEstimate<R, SS>> =
+            Vec::with_capacity(forward_results.len());
+        let Some(last) = forward_results.pop() else {
+            return Ok(smoothed_backwards);
+        };
+        let mut smooth_future = SmoothedEstimate::new(last, None, None);
+        for (i, filt) in forward_results.iter().enumerate().rev() {
+            let smoothed = self
+                .smooth_step(smooth_future.estimate(), filt)
+                .map_err(|e| e.at_step(i))?;
+            // Cov(x_{t+1}, x_t | all data) = P_{t+1} J_t^T
+            smooth_future.lag_one_covariance = smoothed
+                .gain()
+                .map(|gain| smooth_future.estimate().covariance() * gain.transpose());
+            smoothed_backwards.push(smooth_future);
+            smooth_future = smoothed;
+        }
+        smoothed_backwards.push(smooth_future);
+
+        smoothed_backwards.reverse();
+        Ok(smoothed_backwards)
+    }
+
+    /// Smooth one step, returning the estimate and the smoother gain.
     #[cfg(feature = "std")]
     fn smooth_step(
         &self,
         smooth_future: &StateAndCovariance<R, SS>,
         filt: &StateAndCovariance<R, SS>,
-    ) -> Result<StateAndCovariance<R, SS>, Error> {
+    ) -> Result<SmoothedEstimate<R, SS>, Error> {
+        shape::check_transition(self.transition_model, filt)?;
+        let n = filt.state().nrows();
+        shape::check_shape((n, 1), smooth_future.state().shape())?;
+        shape::check_shape((n, n), smooth_future.covariance().shape())?;
         let prior = self.transition_model.predict(filt);
 
         let v_chol = match na::linalg::Cholesky::new(prior.covariance().clone()) {
             Some(v) => v,
             None => {
-                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+                return Err(ErrorKind::SmootherCholeskyFailure.into());
             }
         };
         let inv_prior_covariance: OMatrix<R, SS, SS> = v_chol.inverse();
@@ -436,7 +977,11 @@ where
         let covar_residuals = smooth_future.covariance() - prior.covariance();
         let covariance = filt.covariance() + &j * (covar_residuals * j.transpose());
 
-        Ok(StateAndCovariance::new(state, covariance))
+        Ok(SmoothedEstimate::new(
+            StateAndCovariance::new(state, covariance),
+            Some(j),
+            None,
+        ))
     }
 }
 
@@ -447,20 +992,180 @@ fn is_nan<R: RealField>(x: R) -> bool {
 
 #[test]
 fn test_is_nan() {
-    assert_eq!(is_nan::<f64>(-1.0), false);
-    assert_eq!(is_nan::<f64>(0.0), false);
-    assert_eq!(is_nan::<f64>(1.0), false);
-    assert_eq!(is_nan::<f64>(1.0 / 0.0), false);
-    assert_eq!(is_nan::<f64>(-1.0 / 0.0), false);
-    assert_eq!(is_nan::<f64>(std::f64::NAN), true);
-
-    assert_eq!(is_nan::<f32>(-1.0), false);
-    assert_eq!(is_nan::<f32>(0.0), false);
-    assert_eq!(is_nan::<f32>(1.0), false);
-    assert_eq!(is_nan::<f32>(1.0 / 0.0), false);
-    assert_eq!(is_nan::<f32>(-1.0 / 0.0), false);
-    assert_eq!(is_nan::<f32>(std::f32::NAN), true);
+    assert!(!is_nan::<f64>(-1.0));
+    assert!(!is_nan::<f64>(0.0));
+    assert!(!is_nan::<f64>(1.0));
+    assert!(!is_nan::<f64>(1.0 / 0.0));
+    assert!(!is_nan::<f64>(-1.0 / 0.0));
+    assert!(is_nan::<f64>(f64::NAN));
+
+    assert!(!is_nan::<f32>(-1.0));
+    assert!(!is_nan::<f32>(0.0));
+    assert!(!is_nan::<f32>(1.0));
+    assert!(!is_nan::<f32>(1.0 / 0.0));
+    assert!(!is_nan::<f32>(-1.0 / 0.0));
+    assert!(is_nan::<f32>(f32::NAN));
 }
 
 
 
+
+#[cfg(feature = "std")]
+#[test]
+fn test_dynamic_dimensions() {
+    use na::{DMatrix, DVector, Matrix1, Matrix1x2, Matrix2, Vector1, Vector2, U1, U2};
+
+    // The same model type serves static and runtime dimensions.
+    struct Linear<SS: Dim, OS: Dim>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+        DefaultAllocator: Allocator<f64, OS, SS>,
+        DefaultAllocator: Allocator<f64, SS, OS>,
+        DefaultAllocator: Allocator<f64, OS, OS>,
+    {
+        f: OMatrix<f64, SS, SS>,
+        ft: OMatrix<f64, SS, SS>,
+        q: OMatrix<f64, SS, SS>,
+        h: OMatrix<f64, OS, SS>,
+        ht: OMatrix<f64, SS, OS>,
+        r: OMatrix<f64, OS, OS>,
+    }
+    impl<SS: Dim, OS: Dim> TransitionModelLinearNoControl<f64, SS> for Linear<SS, OS>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+        DefaultAllocator: Allocator<f64, SS>,
+        DefaultAllocator: Allocator<f64, OS, SS>,
+        DefaultAllocator: Allocator<f64, SS, OS>,
+        DefaultAllocator: Allocator<f64, OS, OS>,
+    {
+        fn F(&self) -> &OMatrix<f64, SS, SS> {
+            &self.f
+        }
+        fn FT(&self) -> &OMatrix<f64, SS, SS> {
+            &self.ft
+        }
+        fn Q(&self) -> &OMatrix<f64, SS, SS> {
+            &self.q
+        }
+    }
+    impl<SS: Dim, OS: Dim + DimMin<OS, Output = OS>> ObservationModel<f64, SS, OS> for Linear<SS, OS>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+        DefaultAllocator: Allocator<f64, SS>,
+        DefaultAllocator: Allocator<f64, OS, SS>,
+        DefaultAllocator: Allocator<f64, SS, OS>,
+        DefaultAllocator: Allocator<f64, OS, OS>,
+        DefaultAllocator: Allocator<f64, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        fn H(&self) -> &OMatrix<f64, OS, SS> {
+            &self.h
+        }
+        fn HT(&self) -> &OMatrix<f64, SS, OS> {
+            &self.ht
+        }
+        fn R(&self) -> &OMatrix<f64, OS, OS> {
+            &self.r
+        }
+    }
+
+    let f = Matrix2::new(1.0, 1.0, 0.0, 1.0);
+    let q = Matrix2::new(0.01, 0.0, 0.0, 0.1);
+    let h = Matrix1x2::new(1.0, 0.0);
+    let fixed: Linear<U2, U1> = Linear {
+        f,
+        ft: f.transpose(),
+        q,
+        h,
+        ht: h.transpose(),
+        r: Matrix1::new(0.5),
+    };
+    let initial = StateAndCovariance::new(Vector2::new(0.0, 1.0), Matrix2::identity());
+    let observations = [1.2, 1.9, f64::NAN, 4.1, 4.8].map(Vector1::new);
+
+    // The state size is only known at runtime.
+    let n = 2;
+    let dynamic = Linear {
+        f: DMatrix::from_row_slice(n, n, &[1.0, 1.0, 0.0, 1.0]),
+        ft: DMatrix::from_row_slice(n, n, &[1.0, 0.0, 1.0, 1.0]),
+        q: DMatrix::from_diagonal(&DVector::from_vec(vec![0.01, 0.1])),
+        h: DMatrix::from_row_slice(1, n, &[1.0, 0.0]),
+        ht: DMatrix::from_row_slice(n, 1, &[1.0, 0.0]),
+        r: DMatrix::from_element(1, 1, 0.5),
+    };
+    let dynamic_initial = StateAndCovariance::new(
+        DVector::from_vec(vec![0.0, 1.0]),
+        DMatrix::identity(n, n),
+    );
+    let dynamic_observations: Vec<_> = observations
+        .iter()
+        .map(|z| DVector::from_column_slice(z.as_slice()))
+        .collect();
+
+    let expected = KalmanFilterNoControl::new(&fixed, &fixed)
+        .smooth(&initial, &observations)
+        .unwrap();
+    let actual = KalmanFilterNoControl::new(&dynamic, &dynamic)
+        .smooth(&dynamic_initial, &dynamic_observations)
+        .unwrap();
+    assert_eq!(actual.len(), expected.len());
+    for (actual, expected) in actual.iter().zip(expected.iter()) {
+        approx::assert_relative_eq!(
+            actual.state(),
+            &DVector::from_column_slice(expected.state().as_slice()),
+            epsilon = 1e-12
+        );
+        approx::assert_relative_eq!(
+            actual.covariance(),
+            &DMatrix::from_column_slice(n, n, expected.covariance().as_slice()),
+            epsilon = 1e-12
+        );
+    }
+
+    // Runtime sizes which disagree are reported rather than panicking.
+    let kf = KalmanFilterNoControl::new(&dynamic, &dynamic);
+    let mismatch = ErrorKind::DimensionMismatch {
+        expected: 1,
+        actual: 3,
+    };
+    let mut mismatched = dynamic_observations.clone();
+    mismatched[2] = DVector::from_vec(vec![3.0, 0.0, 0.0]);
+    let err = kf.filter(&dynamic_initial, &mismatched).unwrap_err();
+    assert_eq!(err.kind(), &mismatch);
+    assert_eq!(err.step(), Some(2));
+    let err = kf.smooth(&dynamic_initial, &mismatched).unwrap_err();
+    assert_eq!(err.kind(), &mismatch);
+    let err = dynamic
+        .update(
+            &dynamic_initial,
+            &mismatched[2],
+            CovarianceUpdateMethod::JosephForm,
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), &mismatch);
+    let mut estimate = dynamic_initial.clone();
+    let mut workspace = UpdateWorkspace::new(na::Dyn(n), na::Dyn(1));
+    let err = kf
+        .step_in_place(
+            &mut estimate,
+            &mismatched[2],
+            CovarianceUpdateMethod::JosephForm,
+            &mut workspace,
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), &mismatch);
+
+    let short_state =
+        StateAndCovariance::new(DVector::from_vec(vec![0.0]), DMatrix::identity(1, 1));
+    let err = kf.step(&short_state, &dynamic_observations[0]).unwrap_err();
+    assert_eq!(
+        err.kind(),
+        &ErrorKind::DimensionMismatch {
+            expected: 1,
+            actual: 2
+        }
+    );
+    let mut filtered = kf.filter(&dynamic_initial, &dynamic_observations).unwrap();
+    filtered[1] = short_state;
+    assert!(kf.smooth_from_filtered(filtered).is_err());
+}
@@ -0,0 +1,607 @@
+use core::array;
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::linalg::Cholesky;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, numerical_jacobian, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// Maximum number of Gauss-Newton iterations per step of a
+/// [`MovingHorizonEstimator`].
+const MAX_ITERATIONS: usize = 20;
+
+/// Maximum number of step halvings in the line search of a Gauss-Newton
+/// iteration.
+const MAX_BACKTRACKS: usize = 30;
+
+/// A moving horizon estimator with box constraints on the state
+///
+/// Estimates the states at the times of the last `N` observations jointly by
+/// minimizing
+///
+/// `|x_0 - a|^2_A + sum_k |x_k - F x_{k-1}|^2_Q + sum_k |z_k - h(x_k)|^2_R`
+///
+/// subject to `lower <= x_k <= upper`, where `|v|^2_M` is `v^T M^-1 v` and
+/// the arrival cost `(a, A)` is a prior for the first state of the window.
+/// As observations leave the window, they are folded into the arrival cost
+/// by a Kalman filter update and prediction. Without active bounds and with
+/// a linear observation model, the newest state is therefore the Kalman
+/// filter estimate. Missing (NaN) observations are skipped.
+///
+/// Each step is solved by projected Gauss-Newton. The observation model is
+/// linearized at the current iterate by [`numerical_jacobian`] of its
+/// `evaluate` function, components at a bound whose gradient points outward
+/// are held fixed, and the block-tridiagonal normal equations are solved for
+/// the others. The process covariance `Q` must be positive definite.
+pub struct MovingHorizonEstimator<'a, R, SS, OS, const N: usize>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModel<R, SS, OS>,
+    lower: OVector<R, SS>,
+    upper: OVector<R, SS>,
+    arrival: StateAndCovariance<R, SS>,
+    observations: [OVector<R, OS>; N],
+    states: [OVector<R, SS>; N],
+    len: usize,
+}
+
+impl<'a, R, SS, OS, const N: usize> MovingHorizonEstimator<'a, R, SS, OS, N>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Create a new `MovingHorizonEstimator`.
+    ///
+    /// `initial_estimate` is the estimate one interval before the first
+    /// observation, as for
+    /// [`KalmanFilterNoControl::step`](struct.KalmanFilterNoControl.html#method.step).
+    /// Use infinite bounds for unbounded state components.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModel<R, SS, OS>,
+        lower: OVector<R, SS>,
+        upper: OVector<R, SS>,
+        initial_estimate: &StateAndCovariance<R, SS>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            lower,
+            upper,
+            arrival: transition_model.predict(initial_estimate),
+            observations: array::from_fn(|_| OVector::<R, OS>::zeros()),
+            states: array::from_fn(|_| OVector::<R, SS>::zeros()),
+            len: 0,
+        }
+    }
+
+    /// Get the lower bound of the state.
+    pub fn lower(&self) -> &OVector<R, SS> {
+        &self.lower
+    }
+
+    /// Get the upper bound of the state.
+    pub fn upper(&self) -> &OVector<R, SS> {
+        &self.upper
+    }
+
+    /// Get the arrival cost, the prior for the first state of the window.
+    pub fn arrival(&self) -> &StateAndCovariance<R, SS> {
+        &self.arrival
+    }
+
+    /// Get the estimated states of the window, oldest first.
+    pub fn states(&self) -> &[OVector<R, SS>] {
+        self.states.get(..self.len).unwrap_or(&[])
+    }
+
+    /// Add an observation one interval after the previous one and
+    /// re-estimate the window.
+    ///
+    /// Returns the estimate of the newest state. Its covariance is that of
+    /// the linearized problem at the solution, with zero variance for the
+    /// components held at a bound.
+    ///
+    /// Returns `ErrorKind::CovarianceNotPositiveSemiDefinite` if `Q`, `R` or
+    /// the arrival covariance is not positive definite, and
+    /// `ErrorKind::DimensionMismatch` if the window length `N` is zero. After
+    /// an error the estimator is unchanged, so the observation is not
+    /// counted twice if the step is retried.
+    pub fn step(
+        &mut self,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let arrival = self.arrival.clone();
+        let observations = self.observations.clone();
+        let states = self.states.clone();
+        let len = self.len;
+        let result = self.advance(observation);
+        if result.is_err() {
+            self.arrival = arrival;
+            self.observations = observations;
+            self.states = states;
+            self.len = len;
+        }
+        result
+    }
+
+    /// Slide the window to `observation` and solve it, leaving the
+    /// estimator partially updated on error.
+    fn advance(
+        &mut self,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        if N == 0 {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: 1,
+                actual: 0,
+            }
+            .into());
+        }
+        let guess = match self.states().last() {
+            Some(newest) => self.transition_model.F() * newest,
+            None => self.arrival.state().clone(),
+        };
+        if self.len == N {
+            self.slide()?;
+        } else {
+            self.len += 1;
+        }
+        let newest = self.len - 1;
+        if let (Some(slot), Some(state)) = (
+            self.observations.get_mut(newest),
+            self.states.get_mut(newest),
+        ) {
+            *slot = observation.clone();
+            *state = guess.zip_zip_map(&self.lower, &self.upper, clamp);
+        }
+        let covariance = self.solve()?;
+        let state = self
+            .states()
+            .last()
+            .cloned()
+            .ok_or(ErrorKind::DimensionMismatch {
+                expected: 1,
+                actual: 0,
+            })?;
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+
+    /// Fold the oldest observation into the arrival cost and drop it from
+    /// the window.
+    fn slide(&mut self) -> Result<(), Error> {
+        if let Some(oldest) = self.observations.first() {
+            let posterior = if oldest.iter().any(|x| is_nan(x.clone())) {
+                self.arrival.clone()
+            } else {
+                self.observation_model.update(
+                    &self.arrival,
+                    oldest,
+                    CovarianceUpdateMethod::JosephForm,
+                )?
+            };
+            self.arrival = self.transition_model.predict(&posterior);
+        }
+        self.observations.rotate_left(1);
+        self.states.rotate_left(1);
+        Ok(())
+    }
+
+    /// Run projected Gauss-Newton on the window and return the covariance
+    /// of the newest state.
+    fn solve(&mut self) -> Result<OMatrix<R, SS, SS>, Error> {
+        let n = self.len;
+        let arrival_information = Cholesky::new(self.arrival.covariance().clone())
+            .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?
+            .inverse();
+        let q_information = Cholesky::new(self.transition_model.Q().clone())
+            .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?
+            .inverse();
+        let r_information = Cholesky::new(self.observation_model.R().clone())
+            .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?
+            .inverse();
+        let f = self.transition_model.F();
+        let ft = self.transition_model.FT();
+        let propagated_information: OMatrix<R, SS, SS> = ft * &q_information * f;
+        // The off-diagonal block `A_{k,k+1}` of the Hessian.
+        let coupling: OMatrix<R, SS, SS> = -(ft * &q_information);
+
+        let cost = |states: &[OVector<R, SS>]| -> R {
+            let mut total = R::zero();
+            let mut previous: Option<&OVector<R, SS>> = None;
+            for (x, z) in states.iter().zip(self.observations.iter()) {
+                let (residual, information) = match previous {
+                    Some(previous) => (x - f * previous, &q_information),
+                    None => (x - self.arrival.state(), &arrival_information),
+                };
+                total += residual.dot(&(information * &residual));
+                if !z.iter().any(|v| is_nan(v.clone())) {
+                    let residual = z - self.observation_model.evaluate(x);
+                    total += residual.dot(&(&r_information * &residual));
+                }
+                previous = Some(x);
+            }
+            total
+        };
+
+        let mut covariance = OMatrix::<R, SS, SS>::zeros();
+        let tolerance = R::default_epsilon().sqrt();
+        for _ in 0..MAX_ITERATIONS {
+            // Gradient and diagonal Hessian blocks of half the cost,
+            // linearized at the current states.
+            let mut diagonal: [OMatrix<R, SS, SS>; N] =
+                array::from_fn(|_| OMatrix::<R, SS, SS>::zeros());
+            let mut gradient: [OVector<R, SS>; N] = array::from_fn(|_| OVector::<R, SS>::zeros());
+            let states = self.states();
+            for (k, ((x, z), (d, g))) in states
+                .iter()
+                .zip(self.observations.iter())
+                .zip(diagonal.iter_mut().zip(gradient.iter_mut()))
+                .enumerate()
+            {
+                match k.checked_sub(1).and_then(|j| states.get(j)) {
+                    Some(previous) => {
+                        *d += &q_information;
+                        *g += &q_information * (x - f * previous);
+                    }
+                    None => {
+                        *d += &arrival_information;
+                        *g += &arrival_information * (x - self.arrival.state());
+                    }
+                }
+                if let Some(next) = states.get(k + 1) {
+                    *d += &propagated_information;
+                    *g += &coupling * (next - f * x);
+                }
+                if !z.iter().any(|v| is_nan(v.clone())) {
+                    let h: OMatrix<R, OS, SS> =
+                        numerical_jacobian(|s| self.observation_model.evaluate(s), x);
+                    let ht_r: OMatrix<R, SS, OS> = h.transpose() * &r_information;
+                    *d += &ht_r * &h;
+                    *g -= ht_r * (z - self.observation_model.evaluate(x));
+                }
+            }
+
+            // Hold the components at a bound whose gradient points outward.
+            let mut free: [OVector<R, SS>; N] = array::from_fn(|_| OVector::<R, SS>::zeros());
+            for ((m, x), g) in free.iter_mut().zip(states.iter()).zip(gradient.iter()) {
+                for ((((mi, xi), gi), lo), hi) in m
+                    .iter_mut()
+                    .zip(x.iter())
+                    .zip(g.iter())
+                    .zip(self.lower.iter())
+                    .zip(self.upper.iter())
+                {
+                    let held = (xi <= lo && *gi > R::zero()) || (xi >= hi && *gi < R::zero());
+                    *mi = if held { R::zero() } else { R::one() };
+                }
+            }
+
+            // Reduced normal equations, with held components given an
+            // identity row and column and a zero step.
+            let mut couplings: [OMatrix<R, SS, SS>; N] =
+                array::from_fn(|_| OMatrix::<R, SS, SS>::zeros());
+            let mut rhs: [OVector<R, SS>; N] = array::from_fn(|_| OVector::<R, SS>::zeros());
+            for (k, ((d, e), (b, g))) in diagonal
+                .iter_mut()
+                .zip(couplings.iter_mut())
+                .zip(rhs.iter_mut().zip(gradient.iter()))
+                .enumerate()
+                .take(n)
+            {
+                if let Some(m) = free.get(k) {
+                    let held = m.map(|mi| R::one() - mi);
+                    *d = masked(d, m, m) + OMatrix::<R, SS, SS>::from_diagonal(&held);
+                    *b = -g.component_mul(m);
+                    if let Some(m_next) = free.get(k + 1).filter(|_| k + 1 < n) {
+                        *e = masked(&coupling, m, m_next);
+                    }
+                }
+            }
+            let (step, newest_factor) = solve_block_tridiagonal(&diagonal, &couplings, &rhs, n)?;
+            if let Some(m) = free.get(n - 1) {
+                covariance = masked(&newest_factor.inverse(), m, m);
+            }
+
+            // Backtrack along the projected step until the cost decreases.
+            let current = self.states.clone();
+            let current_cost = cost(self.states());
+            let mut alpha = R::one();
+            let mut accepted = None;
+            for _ in 0..MAX_BACKTRACKS {
+                let mut candidate = current.clone();
+                for (x, dx) in candidate.iter_mut().zip(step.iter()).take(n) {
+                    *x = (&*x + dx * alpha.clone()).zip_zip_map(&self.lower, &self.upper, clamp);
+                }
+                let candidate_states = candidate.get(..n).unwrap_or(&[]);
+                if cost(candidate_states) <= current_cost {
+                    accepted = Some(candidate);
+                    break;
+                }
+                alpha /= na::convert(2.0);
+            }
+            let candidate = match accepted {
+                Some(candidate) => candidate,
+                None => break,
+            };
+            let converged = candidate
+                .iter()
+                .zip(current.iter())
+                .take(n)
+                .all(|(a, b)| (a - b).amax() <= tolerance.clone() * (R::one() + b.amax()));
+            self.states = candidate;
+            if converged {
+                break;
+            }
+        }
+        if !self
+            .states()
+            .iter()
+            .all(|x| x.iter().all(|v| v.is_finite()))
+        {
+            return Err(ErrorKind::NonFiniteState.into());
+        }
+        Ok(covariance)
+    }
+}
+
+/// Clamp `x` to `[lower, upper]`.
+fn clamp<R: RealField>(x: R, lower: R, upper: R) -> R {
+    x.max(lower).min(upper)
+}
+
+/// Zero the rows and columns of `matrix` where `rows` and `columns` are zero.
+fn masked<R, SS>(
+    matrix: &OMatrix<R, SS, SS>,
+    rows: &OVector<R, SS>,
+    columns: &OVector<R, SS>,
+) -> OMatrix<R, SS, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    OMatrix::from_diagonal(rows) * matrix * OMatrix::from_diagonal(columns)
+}
+
+/// Solve the symmetric block-tridiagonal system with diagonal blocks
+/// `diagonal[k]` and off-diagonal blocks `A_{k,k+1} = couplings[k]` for the
+/// first `n` blocks.
+///
+/// Returns the solution and the Cholesky factorization of the last Schur
+/// complement, whose inverse is the last diagonal block of the inverse.
+#[allow(clippy::type_complexity)]
+fn solve_block_tridiagonal<R, SS, const N: usize>(
+    diagonal: &[OMatrix<R, SS, SS>; N],
+    couplings: &[OMatrix<R, SS, SS>; N],
+    rhs: &[OVector<R, SS>; N],
+    n: usize,
+) -> Result<([OVector<R, SS>; N], Cholesky<R, SS>), Error>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    // Forward elimination.
+    let mut factors: [Option<Cholesky<R, SS>>; N] = array::from_fn(|_| None);
+    let mut reduced = rhs.clone();
+    let mut previous: Option<(Cholesky<R, SS>, OVector<R, SS>, &OMatrix<R, SS, SS>)> = None;
+    for ((factor, y), (d, e)) in factors
+        .iter_mut()
+        .zip(reduced.iter_mut())
+        .zip(diagonal.iter().zip(couplings.iter()))
+        .take(n)
+    {
+        let mut schur = d.clone();
+        if let Some((previous_factor, previous_y, previous_e)) = &previous {
+            let et = previous_e.transpose();
+            schur -= &et * previous_factor.solve(*previous_e);
+            *y -= et * previous_factor.solve(previous_y);
+        }
+        let chol = Cholesky::new(schur).ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?;
+        previous = Some((chol.clone(), y.clone(), e));
+        *factor = Some(chol);
+    }
+    let newest = previous
+        .map(|(chol, _, _)| chol)
+        .ok_or(ErrorKind::DimensionMismatch {
+            expected: 1,
+            actual: 0,
+        })?;
+
+    // Back substitution.
+    let mut solution: [OVector<R, SS>; N] = array::from_fn(|_| OVector::<R, SS>::zeros());
+    let mut next: Option<OVector<R, SS>> = None;
+    for ((x, factor), (y, e)) in solution
+        .iter_mut()
+        .zip(factors.iter())
+        .zip(reduced.iter().zip(couplings.iter()))
+        .take(n)
+        .rev()
+    {
+        let mut y = y.clone();
+        if let Some(next) = &next {
+            y -= e * next;
+        }
+        if let Some(factor) = factor {
+            *x = factor.solve(&y);
+        }
+        next = Some(x.clone());
+    }
+    Ok((solution, newest))
+}
+
+#[test]
+fn test_moving_horizon() {
+    use crate::KalmanFilterNoControl;
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    struct ConstantVelocity {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for ConstantVelocity {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    struct Position {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for Position {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let f = Matrix2::new(1.0, 0.1, 0.0, 1.0);
+    let motion = ConstantVelocity {
+        f,
+        ft: f.transpose(),
+        q: Matrix2::new(1e-3, 0.0, 0.0, 1e-2),
+    };
+    let position = Position {
+        h: Matrix1x2::new(1.0, 0.0),
+        ht: Matrix2x1::new(1.0, 0.0),
+        r: Matrix1::new(0.01),
+    };
+    let initial = StateAndCovariance::new(Vector2::new(0.5, 0.0), Matrix2::identity());
+    let levels = [
+        0.52,
+        0.61,
+        f64::NAN,
+        0.78,
+        0.86,
+        0.97,
+        1.04,
+        1.08,
+        1.12,
+        1.1,
+    ];
+
+    // Unbounded, the newest state is the Kalman filter estimate, including
+    // after observations have left the window.
+    let unbounded = Vector2::repeat(f64::INFINITY);
+    let mut mhe = MovingHorizonEstimator::<_, _, _, 3>::new(
+        &motion, &position, -unbounded, unbounded, &initial,
+    );
+    let kf = KalmanFilterNoControl::new(&motion, &position);
+    let mut expected = initial.clone();
+    for level in levels {
+        let observation = Vector1::new(level);
+        expected = kf.step(&expected, &observation).unwrap();
+        let estimate = mhe.step(&observation).unwrap();
+        approx::assert_relative_eq!(estimate.state(), expected.state(), epsilon = 1e-8);
+        approx::assert_relative_eq!(estimate.covariance(), expected.covariance(), epsilon = 1e-8);
+    }
+    assert_eq!(mhe.states().len(), 3);
+    assert!(expected.state()[0] > 1.0);
+
+    // A tank level of at most 1 is held at the bound, with zero variance.
+    let lower = Vector2::new(0.0, f64::NEG_INFINITY);
+    let upper = Vector2::new(1.0, f64::INFINITY);
+    let mut mhe =
+        MovingHorizonEstimator::<_, _, _, 3>::new(&motion, &position, lower, upper, &initial);
+    let mut estimate = initial.clone();
+    for level in levels {
+        estimate = mhe.step(&Vector1::new(level)).unwrap();
+        for state in mhe.states() {
+            assert!(state[0] >= 0.0 && state[0] <= 1.0);
+        }
+    }
+    approx::assert_relative_eq!(estimate.state()[0], 1.0);
+    assert_eq!(estimate.covariance()[(0, 0)], 0.0);
+    assert!(estimate.covariance()[(1, 1)] > 0.0);
+
+    // A failed step leaves the estimator unchanged. A zero `R` still allows
+    // the oldest observation to be folded into the arrival cost, but not the
+    // solve.
+    struct Faulty {
+        position: Position,
+        zero: Matrix1<f64>,
+        failing: core::cell::Cell<bool>,
+    }
+    impl ObservationModel<f64, U2, U1> for Faulty {
+        fn H(&self) -> &Matrix1x2<f64> {
+            self.position.H()
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            self.position.HT()
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            if self.failing.get() {
+                &self.zero
+            } else {
+                self.position.R()
+            }
+        }
+    }
+    let faulty = Faulty {
+        position,
+        zero: Matrix1::zeros(),
+        failing: core::cell::Cell::new(false),
+    };
+    let mut mhe = MovingHorizonEstimator::<_, _, _, 3>::new(
+        &motion, &faulty, -unbounded, unbounded, &initial,
+    );
+    let mut reference = MovingHorizonEstimator::<_, _, _, 3>::new(
+        &motion, &faulty, -unbounded, unbounded, &initial,
+    );
+    for level in levels {
+        let observation = Vector1::new(level);
+        faulty.failing.set(true);
+        let arrival = mhe.arrival().clone();
+        let states: [Vector2<f64>; 3] =
+            core::array::from_fn(|i| mhe.states().get(i).copied().unwrap_or_default());
+        let len = mhe.states().len();
+        let err = mhe.step(&observation).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::CovarianceNotPositiveSemiDefinite);
+        assert_eq!(mhe.arrival().state(), arrival.state());
+        assert_eq!(mhe.arrival().covariance(), arrival.covariance());
+        assert_eq!(mhe.states(), states.get(..len).unwrap());
+
+        // The retry gives the same estimate as a step which never failed.
+        faulty.failing.set(false);
+        let estimate = mhe.step(&observation).unwrap();
+        let expected = reference.step(&observation).unwrap();
+        assert_eq!(estimate.state(), expected.state());
+        assert_eq!(estimate.covariance(), expected.covariance());
+    }
+}
@@ -0,0 +1,378 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Counters and the most recent innovation statistic of a [`Sensor`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorDiagnostics<R: RealField> {
+    updates: u32,
+    rejected: u32,
+    missing: u32,
+    last_nis: Option<R>,
+}
+
+impl<R: RealField> SensorDiagnostics<R> {
+    /// Create a new `SensorDiagnostics` with all counters at zero.
+    pub fn new() -> Self {
+        Self {
+            updates: 0,
+            rejected: 0,
+            missing: 0,
+            last_nis: None,
+        }
+    }
+    /// Number of observations used to update the state.
+    #[inline]
+    pub fn updates(&self) -> u32 {
+        self.updates
+    }
+    /// Number of observations rejected by the gate.
+    #[inline]
+    pub fn rejected(&self) -> u32 {
+        self.rejected
+    }
+    /// Number of missing (NaN) observations.
+    #[inline]
+    pub fn missing(&self) -> u32 {
+        self.missing
+    }
+    /// Normalized innovation squared of the most recent observation.
+    #[inline]
+    pub fn last_nis(&self) -> Option<R> {
+        self.last_nis.clone()
+    }
+
+    pub(crate) fn record_update(&mut self) {
+        self.updates = self.updates.saturating_add(1);
+    }
+
+    pub(crate) fn record_rejected(&mut self) {
+        self.rejected = self.rejected.saturating_add(1);
+    }
+
+    pub(crate) fn record_missing(&mut self) {
+        self.missing = self.missing.saturating_add(1);
+    }
+
+    pub(crate) fn record_nis(&mut self, nis: R) {
+        self.last_nis = Some(nis);
+    }
+}
+
+/// Gate and apply an observation, recording the outcome in `diagnostics`
+///
+/// Missing (NaN) and gated-out observations leave the prior unchanged.
+pub(crate) fn gated_update<R, SS, OS>(
+    observation_model: &(impl ObservationModel<R, SS, OS> + ?Sized),
+    gate: Option<&R>,
+    covariance_update_method: CovarianceUpdateMethod,
+    diagnostics: &mut SensorDiagnostics<R>,
+    prior: &StateAndCovariance<R, SS>,
+    observation: &OVector<R, OS>,
+) -> Result<StateAndCovariance<R, SS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    if observation.iter().any(|x| is_nan(x.clone())) {
+        diagnostics.record_missing();
+        return Ok(prior.clone());
+    }
+
+    let nis = observation_model.innovation(prior, observation).nis()?;
+    diagnostics.record_nis(nis.clone());
+    if let Some(gate) = gate {
+        if nis > *gate {
+            diagnostics.record_rejected();
+            return Ok(prior.clone());
+        }
+    }
+
+    let posterior = observation_model.update(prior, observation, covariance_update_method)?;
+    diagnostics.record_update();
+    Ok(posterior)
+}
+
+impl<R: RealField> Default for SensorDiagnostics<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A sensor contributing observations to a [`MultiSensorKalmanFilter`]
+///
+/// Each sensor has its own observation model, of any dimension `OS`, its own
+/// validation gate and its own diagnostics.
+pub struct Sensor<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+{
+    observation_model: &'a dyn ObservationModel<R, SS, OS>,
+    gate: Option<R>,
+    covariance_update_method: CovarianceUpdateMethod,
+    diagnostics: SensorDiagnostics<R>,
+}
+
+impl<'a, R, SS, OS> Sensor<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Create a new `Sensor`.
+    ///
+    /// If `gate` is given, observations whose normalized innovation squared
+    /// exceeds it are rejected. The update uses
+    /// `CovarianceUpdateMethod::JosephForm`.
+    pub fn new(observation_model: &'a dyn ObservationModel<R, SS, OS>, gate: Option<R>) -> Self {
+        Self::new_with_options(observation_model, gate, CovarianceUpdateMethod::JosephForm)
+    }
+
+    /// Create a new `Sensor` using the specified covariance update method.
+    pub fn new_with_options(
+        observation_model: &'a dyn ObservationModel<R, SS, OS>,
+        gate: Option<R>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Self {
+        Self {
+            observation_model,
+            gate,
+            covariance_update_method,
+            diagnostics: SensorDiagnostics::new(),
+        }
+    }
+
+    /// Get the observation model.
+    pub fn observation_model(&self) -> &'a dyn ObservationModel<R, SS, OS> {
+        self.observation_model
+    }
+
+    /// Get the diagnostics of this sensor.
+    pub fn diagnostics(&self) -> &SensorDiagnostics<R> {
+        &self.diagnostics
+    }
+
+    /// Reset the diagnostics of this sensor.
+    pub fn reset_diagnostics(&mut self) {
+        self.diagnostics = SensorDiagnostics::new();
+    }
+
+    /// Update the prior with an observation from this sensor
+    ///
+    /// If any component of the observation is NaN (not a number), or the
+    /// observation falls outside the gate, the prior is returned unchanged.
+    pub fn update(
+        &mut self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        gated_update(
+            self.observation_model,
+            self.gate.as_ref(),
+            self.covariance_update_method,
+            &mut self.diagnostics,
+            prior,
+            observation,
+        )
+    }
+}
+
+/// A Kalman filter with no control inputs fusing heterogeneous sensors
+///
+/// Unlike [`KalmanFilterNoControl`](struct.KalmanFilterNoControl.html), which
+/// is bound to a single observation model, this filter holds only the
+/// transition model. Each update is performed by a [`Sensor`] whose
+/// observation model may have any dimension. To fuse sensors running at
+/// different rates, call [`predict`](#method.predict) once per interval of
+/// the transition model and then [`update`](#method.update) for each sensor
+/// that produced an observation during that interval.
+pub struct MultiSensorKalmanFilter<'a, R, SS>
+where
+    R: RealField,
+    SS: DimName,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+}
+
+impl<'a, R, SS> MultiSensorKalmanFilter<'a, R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Initialize a new `MultiSensorKalmanFilter` struct.
+    pub fn new(transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>) -> Self {
+        Self { transition_model }
+    }
+
+    /// Get the state transition model.
+    pub fn transition_model(&self) -> &'a dyn TransitionModelLinearNoControl<R, SS> {
+        self.transition_model
+    }
+
+    /// Predict the state one interval ahead.
+    pub fn predict(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+    ) -> StateAndCovariance<R, SS> {
+        self.transition_model.predict(previous_estimate)
+    }
+
+    /// Update the prior with an observation from `sensor`.
+    ///
+    /// This is a convenience method that calls [`Sensor::update`].
+    pub fn update<OS>(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        sensor: &mut Sensor<'_, R, SS, OS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error>
+    where
+        OS: DimName + DimMin<OS, Output = OS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        sensor.update(prior, observation)
+    }
+
+    /// Perform prediction and update steps with an observation from `sensor`.
+    pub fn step<OS>(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        sensor: &mut Sensor<'_, R, SS, OS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error>
+    where
+        OS: DimName + DimMin<OS, Output = OS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        let prior = self.predict(previous_estimate);
+        sensor.update(&prior, observation)
+    }
+}
+
+#[test]
+fn test_multi_sensor_fusion() {
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    struct Static {
+        identity: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for Static {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.identity
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.identity
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    struct Position {
+        h: Matrix2<f64>,
+        r: Matrix2<f64>,
+    }
+    impl ObservationModel<f64, U2, U2> for Position {
+        fn H(&self) -> &Matrix2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2<f64> {
+            &self.h
+        }
+        fn R(&self) -> &Matrix2<f64> {
+            &self.r
+        }
+    }
+    struct XOnly {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for XOnly {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let transition = Static {
+        identity: Matrix2::identity(),
+        q: Matrix2::identity() * 1e-6,
+    };
+    let position = Position {
+        h: Matrix2::identity(),
+        r: Matrix2::identity() * 0.1,
+    };
+    let x_only = XOnly {
+        h: Matrix1x2::new(1.0, 0.0),
+        ht: Matrix2x1::new(1.0, 0.0),
+        r: Matrix1::new(1e-4),
+    };
+
+    let filter = MultiSensorKalmanFilter::new(&transition);
+    let mut position_sensor = Sensor::new(&position, None);
+    let mut x_sensor = Sensor::new(&x_only, Some(9.0));
+
+    let mut estimate = StateAndCovariance::new(Vector2::zeros(), Matrix2::identity());
+    for i in 0..20 {
+        estimate = filter.predict(&estimate);
+        estimate = filter
+            .update(&estimate, &mut position_sensor, &Vector2::new(1.0, 2.0))
+            .unwrap();
+        if i % 2 == 0 {
+            // A slower, more precise sensor of x only. One observation is an
+            // outlier.
+            let x = if i == 10 { 50.0 } else { 1.0 };
+            estimate = filter
+                .update(&estimate, &mut x_sensor, &Vector1::new(x))
+                .unwrap();
+        }
+    }
+
+    approx::assert_relative_eq!(estimate.state(), &Vector2::new(1.0, 2.0), epsilon = 2e-2);
+    // The precise x sensor dominates the x variance.
+    assert!(estimate.covariance()[(0, 0)] < 1e-4);
+    assert!(estimate.covariance()[(1, 1)] > 1e-3);
+    assert_eq!(position_sensor.diagnostics().updates(), 20);
+    assert_eq!(x_sensor.diagnostics().updates(), 9);
+    assert_eq!(x_sensor.diagnostics().rejected(), 1);
+}
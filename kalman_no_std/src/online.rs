@@ -0,0 +1,311 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use crate::multi_sensor::gated_update;
+use crate::{
+    is_nan, ContinuousTransitionModel, CovarianceUpdateMethod, Error, ErrorKind, ObservationModel,
+    SensorDiagnostics, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// The state of an [`OnlineTracker`] at one point in time
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "R: serde::Serialize, StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "R: serde::Deserialize<'de>, \
+                       StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct TrackerSnapshot<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    estimate: StateAndCovariance<R, SS>,
+    time: R,
+    diagnostics: SensorDiagnostics<R>,
+}
+
+impl<R, SS> TrackerSnapshot<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Get the state estimate.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+    /// Get the time of the state estimate.
+    #[inline]
+    pub fn time(&self) -> R {
+        self.time.clone()
+    }
+    /// Get the diagnostics.
+    #[inline]
+    pub fn diagnostics(&self) -> &SensorDiagnostics<R> {
+        &self.diagnostics
+    }
+}
+
+/// A Kalman filter which keeps its own estimate for online use
+///
+/// Instead of the caller passing the previous estimate to every step, the
+/// tracker stores the current estimate, its timestamp and the diagnostics of
+/// the observations it has processed. Prediction is to an arbitrary time
+/// using a [`ContinuousTransitionModel`](trait.ContinuousTransitionModel.html).
+pub struct OnlineTracker<R, SS, T, O>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition_model: T,
+    observation_model: O,
+    gate: Option<R>,
+    estimate: StateAndCovariance<R, SS>,
+    time: R,
+    diagnostics: SensorDiagnostics<R>,
+}
+
+impl<R, SS, T, O> OnlineTracker<R, SS, T, O>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `OnlineTracker` with an initial estimate at `time`.
+    ///
+    /// If `gate` is given, observations whose normalized innovation squared
+    /// exceeds it are rejected.
+    pub fn new(
+        transition_model: T,
+        observation_model: O,
+        gate: Option<R>,
+        estimate: StateAndCovariance<R, SS>,
+        time: R,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            gate,
+            estimate,
+            time,
+            diagnostics: SensorDiagnostics::new(),
+        }
+    }
+
+    /// Get the state transition model.
+    pub fn transition_model(&self) -> &T {
+        &self.transition_model
+    }
+
+    /// Get the observation model.
+    pub fn observation_model(&self) -> &O {
+        &self.observation_model
+    }
+
+    /// Get a mutable reference to the observation model.
+    pub fn observation_model_mut(&mut self) -> &mut O {
+        &mut self.observation_model
+    }
+
+    /// Get the current state estimate.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+
+    /// Get the time of the current state estimate.
+    #[inline]
+    pub fn time(&self) -> R {
+        self.time.clone()
+    }
+
+    /// Get the diagnostics of the observations processed so far.
+    #[inline]
+    pub fn diagnostics(&self) -> &SensorDiagnostics<R> {
+        &self.diagnostics
+    }
+
+    /// Replace the estimate and its time, and reset the diagnostics.
+    pub fn reset(&mut self, estimate: StateAndCovariance<R, SS>, time: R) {
+        self.estimate = estimate;
+        self.time = time;
+        self.diagnostics = SensorDiagnostics::new();
+    }
+
+    /// Copy the estimate, its time and the diagnostics.
+    pub fn snapshot(&self) -> TrackerSnapshot<R, SS> {
+        TrackerSnapshot {
+            estimate: self.estimate.clone(),
+            time: self.time.clone(),
+            diagnostics: self.diagnostics.clone(),
+        }
+    }
+
+    /// Return to the state recorded by [`snapshot`](#method.snapshot).
+    pub fn restore(&mut self, snapshot: TrackerSnapshot<R, SS>) {
+        self.estimate = snapshot.estimate;
+        self.time = snapshot.time;
+        self.diagnostics = snapshot.diagnostics;
+    }
+
+    /// Predict the estimate forward to `time`.
+    ///
+    /// Predicting to the current time does nothing. Returns
+    /// `ErrorKind::NonMonotonicTime` if `time` is earlier than the current
+    /// time or is NaN, leaving the estimate unchanged.
+    pub fn predict_to(&mut self, time: R) -> Result<(), Error>
+    where
+        T: ContinuousTransitionModel<R, SS>,
+    {
+        let dt = time.clone() - self.time.clone();
+        if is_nan(dt.clone()) || dt < R::zero() {
+            return Err(ErrorKind::NonMonotonicTime.into());
+        }
+        if dt > R::zero() {
+            self.estimate = self.transition_model.discretize(dt).predict(&self.estimate);
+            self.time = time;
+        }
+        Ok(())
+    }
+
+    /// Update the estimate with an observation at the current time.
+    ///
+    /// Missing (NaN) observations and observations outside the gate are
+    /// counted in the diagnostics and leave the estimate unchanged.
+    pub fn update<OS>(&mut self, observation: &OVector<R, OS>) -> Result<(), Error>
+    where
+        OS: DimName + DimMin<OS, Output = OS>,
+        O: ObservationModel<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        self.estimate = gated_update(
+            &self.observation_model,
+            self.gate.as_ref(),
+            CovarianceUpdateMethod::JosephForm,
+            &mut self.diagnostics,
+            &self.estimate,
+            observation,
+        )?;
+        Ok(())
+    }
+
+    /// Predict forward to `time` and update with an observation made then.
+    pub fn update_at<OS>(&mut self, time: R, observation: &OVector<R, OS>) -> Result<(), Error>
+    where
+        T: ContinuousTransitionModel<R, SS>,
+        OS: DimName + DimMin<OS, Output = OS>,
+        O: ObservationModel<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        self.predict_to(time)?;
+        self.update(observation)
+    }
+}
+
+#[test]
+fn test_online_tracker() {
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    struct ConstantVelocity;
+    struct Discrete {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for Discrete {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    impl ContinuousTransitionModel<f64, U2> for ConstantVelocity {
+        type Discrete = Discrete;
+        fn discretize(&self, dt: f64) -> Discrete {
+            let f = Matrix2::new(1.0, dt, 0.0, 1.0);
+            Discrete {
+                f,
+                ft: f.transpose(),
+                q: Matrix2::new(dt * dt * dt / 3.0, dt * dt / 2.0, dt * dt / 2.0, dt) * 1e-4,
+            }
+        }
+    }
+    struct Position {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for Position {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let position = Position {
+        h: Matrix1x2::new(1.0, 0.0),
+        ht: Matrix2x1::new(1.0, 0.0),
+        r: Matrix1::new(1e-2),
+    };
+    let initial = StateAndCovariance::new(Vector2::zeros(), Matrix2::identity() * 10.0);
+    let mut tracker = OnlineTracker::new(ConstantVelocity, position, Some(25.0), initial, 0.0);
+
+    // Irregularly sampled positions of a target moving at 2 units/s.
+    let mut time = 0.0;
+    for i in 0..40 {
+        time += if i % 3 == 0 { 0.25 } else { 0.1 };
+        tracker.update_at(time, &Vector1::new(2.0 * time)).unwrap();
+    }
+    approx::assert_relative_eq!(tracker.estimate().state()[1], 2.0, epsilon = 1e-2);
+    assert_eq!(tracker.diagnostics().updates(), 40);
+
+    let snapshot = tracker.snapshot();
+    tracker.update(&Vector1::new(f64::NAN)).unwrap();
+    tracker.update(&Vector1::new(1e3)).unwrap();
+    assert_eq!(tracker.diagnostics().missing(), 1);
+    assert_eq!(tracker.diagnostics().rejected(), 1);
+    assert_eq!(
+        tracker.predict_to(time - 1.0).unwrap_err().kind(),
+        &ErrorKind::NonMonotonicTime
+    );
+
+    tracker.predict_to(time + 1.0).unwrap();
+    approx::assert_relative_eq!(
+        tracker.estimate().state()[0],
+        2.0 * (time + 1.0),
+        epsilon = 1e-2
+    );
+    tracker.restore(snapshot);
+    approx::assert_relative_eq!(tracker.time(), time);
+    assert_eq!(tracker.diagnostics().rejected(), 0);
+}
@@ -0,0 +1,38 @@
+use na::RealField;
+use nalgebra as na;
+
+/// Number of golden-section iterations. The bracket shrinks by a factor of
+/// 0.618 per iteration, so this reduces it by about 1e-9.
+const GOLDEN_SECTION_ITERATIONS: usize = 43;
+
+/// Minimize a unimodal scalar function on `[lower, upper]` by golden-section
+/// search. Returns the abscissa of the minimum.
+pub(crate) fn golden_section_minimize<R, F, E>(mut lower: R, mut upper: R, mut f: F) -> Result<R, E>
+where
+    R: RealField,
+    F: FnMut(R) -> Result<R, E>,
+{
+    let inv_phi: R = (R::one() + na::convert::<f64, R>(5.0).sqrt()) / na::convert(2.0);
+    let inv_phi = R::one() / inv_phi;
+
+    let mut c = upper.clone() - (upper.clone() - lower.clone()) * inv_phi.clone();
+    let mut d = lower.clone() + (upper.clone() - lower.clone()) * inv_phi.clone();
+    let mut fc = f(c.clone())?;
+    let mut fd = f(d.clone())?;
+    for _ in 0..GOLDEN_SECTION_ITERATIONS {
+        if fc < fd {
+            upper = d;
+            d = c;
+            fd = fc;
+            c = upper.clone() - (upper.clone() - lower.clone()) * inv_phi.clone();
+            fc = f(c.clone())?;
+        } else {
+            lower = c;
+            c = d;
+            fc = fd;
+            d = lower.clone() + (upper.clone() - lower.clone()) * inv_phi.clone();
+            fd = f(d.clone())?;
+        }
+    }
+    Ok((lower + upper) / na::convert(2.0))
+}
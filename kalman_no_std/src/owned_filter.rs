@@ -0,0 +1,427 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, Dim, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ErrorKind, Innovation, ObservationModel,
+    StateAndCovariance, TransitionModelLinearNoControl, UpdateWorkspace,
+};
+use crate::{shape, workspace};
+
+/// A Kalman filter with no control inputs, generic over its models
+///
+/// Unlike [`KalmanFilterNoControl`](struct.KalmanFilterNoControl.html), which
+/// borrows trait objects, this filter owns its transition model `T` and
+/// observation model `O`, so calls are statically dispatched and can be
+/// inlined. The models may also be references, as `&M` implements the model
+/// traits whenever `M` does. Because [`new`](#method.new) is a `const fn`,
+/// the filter can be placed in a `static` if the models can be constructed
+/// in a constant expression.
+///
+/// `KalmanFilterNoControl` runs its steps through a `KalmanFilter` of its
+/// two trait objects, so both filters give identical results.
+#[derive(Debug, Clone)]
+pub struct KalmanFilter<T, O> {
+    transition_model: T,
+    observation_model: O,
+}
+
+impl<T, O> KalmanFilter<T, O> {
+    /// Initialize a new `KalmanFilter` struct.
+    pub const fn new(transition_model: T, observation_model: O) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+        }
+    }
+
+    /// Get the state transition model.
+    pub fn transition_model(&self) -> &T {
+        &self.transition_model
+    }
+
+    /// Get the observation model.
+    pub fn observation_model(&self) -> &O {
+        &self.observation_model
+    }
+
+    /// Get a mutable reference to the observation model.
+    ///
+    /// This allows, for example, relinearizing a non-linear observation model
+    /// between steps.
+    pub fn observation_model_mut(&mut self) -> &mut O {
+        &mut self.observation_model
+    }
+
+    /// Return the transition and observation models.
+    pub fn into_inner(self) -> (T, O) {
+        (self.transition_model, self.observation_model)
+    }
+
+    /// Perform Kalman prediction and update steps with default values
+    ///
+    /// Equivalent to
+    /// [`KalmanFilterNoControl::step`](struct.KalmanFilterNoControl.html#method.step).
+    pub fn step<R, SS, OS>(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error>
+    where
+        R: RealField,
+        SS: Dim,
+        OS: Dim + DimMin<OS, Output = OS>,
+        T: TransitionModelLinearNoControl<R, SS>,
+        O: ObservationModel<R, SS, OS>,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        self.step_with_options(
+            previous_estimate,
+            observation,
+            CovarianceUpdateMethod::JosephForm,
+        )
+    }
+
+    /// Perform Kalman prediction and update steps
+    ///
+    /// Equivalent to
+    /// [`KalmanFilterNoControl::step_with_options`](struct.KalmanFilterNoControl.html#method.step_with_options).
+    pub fn step_with_options<R, SS, OS>(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error>
+    where
+        R: RealField,
+        SS: Dim,
+        OS: Dim + DimMin<OS, Output = OS>,
+        T: TransitionModelLinearNoControl<R, SS>,
+        O: ObservationModel<R, SS, OS>,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        shape::check_transition(&self.transition_model, previous_estimate)?;
+        shape::check_observation(&self.observation_model, previous_estimate, observation)?;
+        let prior = self.transition_model.predict(previous_estimate);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(prior)
+        } else {
+            self.observation_model
+                .update(&prior, observation, covariance_update_method)
+        }
+    }
+
+    /// Perform Kalman prediction and update steps in place
+    ///
+    /// Equivalent to
+    /// [`KalmanFilterNoControl::step_in_place`](struct.KalmanFilterNoControl.html#method.step_in_place).
+    pub fn step_in_place<R, SS, OS>(
+        &self,
+        estimate: &mut StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CovarianceUpdateMethod,
+        workspace: &mut UpdateWorkspace<R, SS, OS>,
+    ) -> Result<(), Error>
+    where
+        R: RealField,
+        SS: Dim,
+        OS: Dim + DimMin<OS, Output = OS>,
+        T: TransitionModelLinearNoControl<R, SS>,
+        O: ObservationModel<R, SS, OS>,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        shape::check_transition(&self.transition_model, estimate)?;
+        shape::check_observation(&self.observation_model, estimate, observation)?;
+        workspace.check_shape(estimate.state().nrows(), self.observation_model.H().nrows())?;
+        workspace::predict_in_place(&self.transition_model, estimate, workspace);
+        if observation.iter().any(|x| is_nan(x.clone())) {
+            Ok(())
+        } else {
+            self.observation_model.update_in_place(
+                estimate,
+                observation,
+                covariance_update_method,
+                workspace,
+            )
+        }
+    }
+
+    /// Kalman filter (operates on in-place data without allocating)
+    ///
+    /// Equivalent to
+    /// [`KalmanFilterNoControl::filter_inplace`](struct.KalmanFilterNoControl.html#method.filter_inplace).
+    pub fn filter_inplace<R, SS, OS>(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+        state_estimates: &mut [StateAndCovariance<R, SS>],
+    ) -> Result<(), Error>
+    where
+        R: RealField,
+        SS: Dim,
+        OS: Dim + DimMin<OS, Output = OS>,
+        T: TransitionModelLinearNoControl<R, SS>,
+        O: ObservationModel<R, SS, OS>,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<R, OS, OS>,
+        DefaultAllocator: Allocator<R, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+    {
+        if state_estimates.len() < observations.len() {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: observations.len(),
+                actual: state_estimates.len(),
+            }
+            .into());
+        }
+        let mut previous_estimate = initial_estimate.clone();
+
+        for (i, (this_observation, state_estimate)) in observations
+            .iter()
+            .zip(state_estimates.iter_mut())
+            .enumerate()
+        {
+            let this_estimate = self
+                .step(&previous_estimate, this_observation)
+                .map_err(|e| e.at_step(i))?;
+            *state_estimate = this_estimate.clone();
+            previous_estimate = this_estimate;
+        }
+        Ok(())
+    }
+}
+
+impl<R, SS, M> TransitionModelLinearNoControl<R, SS> for &M
+where
+    R: RealField,
+    SS: Dim,
+    M: TransitionModelLinearNoControl<R, SS> + ?Sized,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    #[inline]
+    fn F(&self) -> &OMatrix<R, SS, SS> {
+        (**self).F()
+    }
+    #[inline]
+    fn FT(&self) -> &OMatrix<R, SS, SS> {
+        (**self).FT()
+    }
+    #[inline]
+    fn Q(&self) -> &OMatrix<R, SS, SS> {
+        (**self).Q()
+    }
+    #[inline]
+    fn predict(&self, previous_estimate: &StateAndCovariance<R, SS>) -> StateAndCovariance<R, SS> {
+        (**self).predict(previous_estimate)
+    }
+    #[inline]
+    fn forecast_into(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+        forecast: &mut [StateAndCovariance<R, SS>],
+    ) {
+        (**self).forecast_into(estimate, forecast)
+    }
+    #[inline]
+    fn predict_n(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+        n: usize,
+    ) -> StateAndCovariance<R, SS> {
+        (**self).predict_n(estimate, n)
+    }
+}
+
+impl<R, SS, OS, M> ObservationModel<R, SS, OS> for &M
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    M: ObservationModel<R, SS, OS> + ?Sized,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    #[inline]
+    fn predict_observation(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        (**self).predict_observation(state)
+    }
+    #[inline]
+    fn H(&self) -> &OMatrix<R, OS, SS> {
+        (**self).H()
+    }
+    #[inline]
+    fn HT(&self) -> &OMatrix<R, SS, OS> {
+        (**self).HT()
+    }
+    #[inline]
+    fn R(&self) -> &OMatrix<R, OS, OS> {
+        (**self).R()
+    }
+    #[inline]
+    fn innovation(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Innovation<R, OS> {
+        (**self).innovation(prior, observation)
+    }
+    #[inline]
+    fn update(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        (**self).update(prior, observation, covariance_method)
+    }
+    #[inline]
+    fn update_in_place(
+        &self,
+        estimate: &mut StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_method: CovarianceUpdateMethod,
+        workspace: &mut UpdateWorkspace<R, SS, OS>,
+    ) -> Result<(), Error> {
+        (**self).update_in_place(estimate, observation, covariance_method, workspace)
+    }
+    #[inline]
+    fn evaluate(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        (**self).evaluate(state)
+    }
+}
+
+#[test]
+fn test_owned_filter() {
+    use crate::KalmanFilterNoControl;
+    use na::{Matrix2, Vector2, U2};
+
+    struct Model {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for Model {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    struct Position {
+        h: Matrix2<f64>,
+        r: Matrix2<f64>,
+    }
+    impl ObservationModel<f64, U2, U2> for Position {
+        fn H(&self) -> &Matrix2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2<f64> {
+            &self.h
+        }
+        fn R(&self) -> &Matrix2<f64> {
+            &self.r
+        }
+    }
+
+    // `new` is a `const fn`, so the filter can be a `static`.
+    static KF: KalmanFilter<Model, Position> = KalmanFilter::new(
+        Model {
+            f: Matrix2::new(1.0, 0.1, 0.0, 1.0),
+            ft: Matrix2::new(1.0, 0.0, 0.1, 1.0),
+            q: Matrix2::new(1e-3, 0.0, 0.0, 1e-3),
+        },
+        Position {
+            h: Matrix2::new(1.0, 0.0, 0.0, 1.0),
+            r: Matrix2::new(0.1, 0.0, 0.0, 0.1),
+        },
+    );
+
+    let observations = [
+        Vector2::new(1.0, 0.5),
+        Vector2::new(f64::NAN, 0.0),
+        Vector2::new(1.2, 0.4),
+    ];
+    let initial = StateAndCovariance::new(Vector2::zeros(), Matrix2::identity());
+
+    // The textbook filter, with an explicit inverse and the Joseph form.
+    let f = Matrix2::new(1.0, 0.1, 0.0, 1.0);
+    let q = Matrix2::identity() * 1e-3;
+    let r = Matrix2::identity() * 0.1;
+    let mut state = Vector2::zeros();
+    let mut covariance = Matrix2::identity();
+    let expected = observations.map(|z| {
+        state = f * state;
+        covariance = f * covariance * f.transpose() + q;
+        if !z.iter().any(|x| x.is_nan()) {
+            let k = covariance * (covariance + r).try_inverse().unwrap();
+            state += k * (z - state);
+            let one_minus_k = Matrix2::identity() - k;
+            covariance = one_minus_k * covariance * one_minus_k.transpose() + k * r * k.transpose();
+        }
+        (state, covariance)
+    });
+    // The missing observation leaves the prediction.
+    approx::assert_relative_eq!(expected[1].0, f * expected[0].0, epsilon = 1e-12);
+
+    let mut owned: [StateAndCovariance<f64, U2>; 3] = core::array::from_fn(|_| initial.clone());
+    KF.filter_inplace(&initial, &observations, &mut owned)
+        .unwrap();
+    let mut by_ref = owned.clone();
+    KalmanFilter::new(KF.transition_model(), KF.observation_model())
+        .filter_inplace(&initial, &observations, &mut by_ref)
+        .unwrap();
+    let mut by_dyn = owned.clone();
+    KalmanFilterNoControl::new(KF.transition_model(), KF.observation_model())
+        .filter_inplace(&initial, &observations, &mut by_dyn)
+        .unwrap();
+    for (i, (state, covariance)) in expected.iter().enumerate() {
+        for estimates in [&owned, &by_ref, &by_dyn] {
+            approx::assert_relative_eq!(estimates[i].state(), state, epsilon = 1e-12);
+            approx::assert_relative_eq!(estimates[i].covariance(), covariance, epsilon = 1e-12);
+        }
+    }
+
+    let mut short: [StateAndCovariance<f64, U2>; 2] = core::array::from_fn(|_| initial.clone());
+    let err = KF
+        .filter_inplace(&initial, &observations, &mut short)
+        .unwrap_err();
+    assert_eq!(
+        err.kind(),
+        &ErrorKind::DimensionMismatch {
+            expected: 3,
+            actual: 2
+        }
+    );
+}
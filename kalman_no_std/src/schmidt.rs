@@ -0,0 +1,231 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+use num_traits::One;
+
+use crate::{
+    CovarianceUpdateMethod, Error, ErrorKind, Innovation, ObservationModel, StateAndCovariance,
+    UpdateWorkspace,
+};
+
+/// An observation model whose update leaves "consider" states unchanged
+///
+/// This is the Schmidt-Kalman filter. Consider states are nuisance
+/// parameters such as sensor biases: their uncertainty and correlations with
+/// the other states are tracked, so the covariance of the estimated states
+/// is realistic, but their estimates are not updated by observations.
+///
+/// The gain rows of the consider states are zeroed, and the covariance is
+/// updated in Joseph form, which is valid for this suboptimal gain. Wrap any
+/// observation model to use it with the filters in this crate.
+#[derive(Debug, Clone)]
+pub struct ConsiderObservationModel<R, SS, M>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    model: M,
+    /// One for estimated states and zero for consider states.
+    estimated: OVector<R, SS>,
+}
+
+impl<R, SS, M> ConsiderObservationModel<R, SS, M>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `ConsiderObservationModel` with the consider states at
+    /// the indices `consider`.
+    ///
+    /// Returns `ErrorKind::DimensionMismatch` if an index is not less than
+    /// the state dimension, with `actual` the state dimension the index would
+    /// need.
+    pub fn new(model: M, consider: &[usize]) -> Result<Self, Error> {
+        let mut estimated = OVector::<R, SS>::repeat(R::one());
+        for &i in consider {
+            match estimated.get_mut(i) {
+                Some(e) => *e = R::zero(),
+                None => {
+                    return Err(ErrorKind::DimensionMismatch {
+                        expected: SS::dim(),
+                        actual: i.saturating_add(1),
+                    }
+                    .into())
+                }
+            }
+        }
+        Ok(Self { model, estimated })
+    }
+
+    /// Get the wrapped observation model.
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// Return the wrapped observation model.
+    pub fn into_inner(self) -> M {
+        self.model
+    }
+
+    /// Check if the state at index `i` is a consider state.
+    pub fn is_consider(&self, i: usize) -> bool {
+        self.estimated.get(i).is_some_and(|e| e.is_zero())
+    }
+}
+
+impl<R, SS, OS, M> ObservationModel<R, SS, OS> for ConsiderObservationModel<R, SS, M>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    M: ObservationModel<R, SS, OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    #[inline]
+    fn predict_observation(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        self.model.predict_observation(state)
+    }
+    #[inline]
+    fn H(&self) -> &OMatrix<R, OS, SS> {
+        self.model.H()
+    }
+    #[inline]
+    fn HT(&self) -> &OMatrix<R, SS, OS> {
+        self.model.HT()
+    }
+    #[inline]
+    fn R(&self) -> &OMatrix<R, OS, OS> {
+        self.model.R()
+    }
+    #[inline]
+    fn innovation(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Innovation<R, OS> {
+        self.model.innovation(prior, observation)
+    }
+
+    /// Given prior state and observation, estimate the posterior state,
+    /// leaving the consider states unchanged.
+    ///
+    /// The covariance is always updated in Joseph form, whatever
+    /// `covariance_method`, as the other forms assume the optimal gain.
+    fn update(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        _covariance_method: CovarianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let innovation = self.model.innovation(prior, observation);
+        let s_chol = na::linalg::Cholesky::new(innovation.covariance().clone())
+            .ok_or(ErrorKind::SingularInnovationCovariance)?;
+        let pht: OMatrix<R, SS, OS> = prior.covariance() * self.model.HT();
+        let mut k_gain = s_chol.solve(&pht.transpose()).transpose();
+        for (mut row, estimated) in k_gain.row_iter_mut().zip(self.estimated.iter()) {
+            row *= estimated.clone();
+        }
+
+        let state = prior.state() + &k_gain * innovation.residual();
+        if !state.iter().all(|x| x.is_finite()) {
+            return Err(ErrorKind::NonFiniteState.into());
+        }
+        let one_minus_kh = OMatrix::<R, SS, SS>::one() - &k_gain * self.model.H();
+        let covariance = &one_minus_kh * prior.covariance() * one_minus_kh.transpose()
+            + &k_gain * self.model.R() * k_gain.transpose();
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+
+    /// Update in place as [`update`](#method.update), which does not use
+    /// `workspace`.
+    fn update_in_place(
+        &self,
+        estimate: &mut StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_method: CovarianceUpdateMethod,
+        _workspace: &mut UpdateWorkspace<R, SS, OS>,
+    ) -> Result<(), Error> {
+        *estimate = self.update(estimate, observation, covariance_method)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn evaluate(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        self.model.evaluate(state)
+    }
+}
+
+#[test]
+fn test_consider_states() {
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    // The position `x` is observed with a bias `b`: z = x + b.
+    struct BiasedPosition {
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl ObservationModel<f64, U2, U1> for BiasedPosition {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+    let biased = BiasedPosition {
+        h: Matrix1x2::new(1.0, 1.0),
+        ht: Matrix2x1::new(1.0, 1.0),
+        r: Matrix1::new(1.0),
+    };
+    let prior = StateAndCovariance::new(Vector2::zeros(), Matrix2::identity());
+    let observation = Vector1::new(2.0);
+    let method = CovarianceUpdateMethod::JosephForm;
+
+    let bias_considered = ConsiderObservationModel::new(&biased, &[1]).unwrap();
+    assert!(bias_considered.is_consider(1) && !bias_considered.is_consider(0));
+    let posterior = bias_considered
+        .update(&prior, &observation, method)
+        .unwrap();
+    let updated = biased.update(&prior, &observation, method).unwrap();
+
+    // S = 3 and K = [1/3, 1/3], of which only the first row is applied.
+    approx::assert_relative_eq!(posterior.state(), &Vector2::new(2.0 / 3.0, 0.0));
+    #[rustfmt::skip]
+    let expected = Matrix2::new(
+        2.0 / 3.0, -1.0 / 3.0,
+        -1.0 / 3.0, 1.0,
+    );
+    approx::assert_relative_eq!(posterior.covariance(), &expected, epsilon = 1e-12);
+    // The estimated state has the same variance as with the full update, but
+    // the bias keeps its uncertainty.
+    approx::assert_relative_eq!(
+        posterior.covariance()[(0, 0)],
+        updated.covariance()[(0, 0)],
+        epsilon = 1e-12
+    );
+    assert!(updated.covariance()[(1, 1)] < 1.0);
+
+    let err = ConsiderObservationModel::<f64, U2, _>::new(&biased, &[2])
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        &ErrorKind::DimensionMismatch {
+            expected: 2,
+            actual: 3
+        }
+    );
+}
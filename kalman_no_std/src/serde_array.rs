@@ -0,0 +1,68 @@
+//! Serialization of arrays of any length as tuples
+//!
+//! serde implements its traits only for arrays of up to 32 elements and not
+//! for const generic lengths. Use with `#[serde(with = "crate::serde_array")]`.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use na::RealField;
+use nalgebra as na;
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+pub(crate) fn serialize<S, R, const N: usize>(
+    array: &[R; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    R: Serialize,
+{
+    let mut tuple = serializer.serialize_tuple(N)?;
+    for element in array {
+        tuple.serialize_element(element)?;
+    }
+    tuple.end()
+}
+
+pub(crate) fn deserialize<'de, D, R, const N: usize>(deserializer: D) -> Result<[R; N], D::Error>
+where
+    D: Deserializer<'de>,
+    R: RealField + Deserialize<'de>,
+{
+    deserializer.deserialize_tuple(N, ArrayVisitor(PhantomData))
+}
+
+struct ArrayVisitor<R, const N: usize>(PhantomData<R>);
+
+impl<'de, R, const N: usize> Visitor<'de> for ArrayVisitor<R, N>
+where
+    R: RealField + Deserialize<'de>,
+{
+    type Value = [R; N];
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an array of length {}", N)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<[R; N], A::Error> {
+        // `core::array::from_fn` cannot fail, so the first error is kept and
+        // the remaining elements are filled with zeros.
+        let mut error = None;
+        let array = core::array::from_fn(|i| {
+            if error.is_none() {
+                match seq.next_element() {
+                    Ok(Some(value)) => return value,
+                    Ok(None) => error = Some(de::Error::invalid_length(i, &self)),
+                    Err(e) => error = Some(e),
+                }
+            }
+            R::zero()
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(array),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, Dim, DimMin, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, ErrorKind, ObservationModel, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// Check that a matrix of shape `actual` has shape `expected`, reporting the
+/// first dimension which differs.
+///
+/// With static dimensions both shapes are constants and the check compiles
+/// away. With `Dyn` dimensions it turns the shape assertions of nalgebra's
+/// operators into errors.
+pub(crate) fn check_shape(expected: (usize, usize), actual: (usize, usize)) -> Result<(), Error> {
+    let (expected, actual) = if expected.0 != actual.0 {
+        (expected.0, actual.0)
+    } else if expected.1 != actual.1 {
+        (expected.1, actual.1)
+    } else {
+        return Ok(());
+    };
+    Err(ErrorKind::DimensionMismatch { expected, actual }.into())
+}
+
+/// Check that the covariance of `estimate` and the matrices of
+/// `transition_model` match the length of the state.
+pub(crate) fn check_transition<R, SS>(
+    transition_model: &(impl TransitionModelLinearNoControl<R, SS> + ?Sized),
+    estimate: &StateAndCovariance<R, SS>,
+) -> Result<(), Error>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let n = estimate.state().nrows();
+    check_shape((n, n), estimate.covariance().shape())?;
+    check_shape((n, n), transition_model.F().shape())?;
+    check_shape((n, n), transition_model.FT().shape())?;
+    check_shape((n, n), transition_model.Q().shape())
+}
+
+/// Check that the covariance of `estimate`, the matrices of
+/// `observation_model` and `observation` agree with each other.
+///
+/// The length of the observation is the number of rows of `H`.
+pub(crate) fn check_observation<R, SS, OS>(
+    observation_model: &(impl ObservationModel<R, SS, OS> + ?Sized),
+    estimate: &StateAndCovariance<R, SS>,
+    observation: &OVector<R, OS>,
+) -> Result<(), Error>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    let n = estimate.state().nrows();
+    let m = observation_model.H().nrows();
+    check_shape((n, n), estimate.covariance().shape())?;
+    check_shape((m, n), observation_model.H().shape())?;
+    check_shape((n, m), observation_model.HT().shape())?;
+    check_shape((m, m), observation_model.R().shape())?;
+    check_shape((m, 1), observation.shape())
+}
+
+#[test]
+fn test_check_shape() {
+    assert!(check_shape((2, 3), (2, 3)).is_ok());
+    assert_eq!(
+        check_shape((2, 3), (4, 3)).unwrap_err().kind(),
+        &ErrorKind::DimensionMismatch {
+            expected: 2,
+            actual: 4
+        }
+    );
+    assert_eq!(
+        check_shape((2, 3), (2, 1)).unwrap_err().kind(),
+        &ErrorKind::DimensionMismatch {
+            expected: 3,
+            actual: 1
+        }
+    );
+}
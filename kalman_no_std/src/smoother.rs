@@ -0,0 +1,200 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, Dim, OMatrix, RealField};
+use nalgebra as na;
+
+use crate::StateAndCovariance;
+
+/// Smoothed estimate of one step with the smoother gain and the lag-one
+/// cross-covariance
+///
+/// Made by
+/// [`KalmanFilterNoControl::smooth_full`](struct.KalmanFilterNoControl.html#method.smooth_full).
+/// Besides the marginal estimate, these are the quantities needed for
+/// expectation-maximization learning of the model and for the uncertainty of
+/// the change of the state between steps.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "StateAndCovariance<R, SS>: serde::Serialize, \
+                     OMatrix<R, SS, SS>: serde::Serialize",
+        deserialize = "StateAndCovariance<R, SS>: serde::Deserialize<'de>, \
+                       OMatrix<R, SS, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct SmoothedEstimate<R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    pub(crate) estimate: StateAndCovariance<R, SS>,
+    pub(crate) gain: Option<OMatrix<R, SS, SS>>,
+    pub(crate) lag_one_covariance: Option<OMatrix<R, SS, SS>>,
+}
+
+impl<R, SS> SmoothedEstimate<R, SS>
+where
+    R: RealField,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `SmoothedEstimate`.
+    pub fn new(
+        estimate: StateAndCovariance<R, SS>,
+        gain: Option<OMatrix<R, SS, SS>>,
+        lag_one_covariance: Option<OMatrix<R, SS, SS>>,
+    ) -> Self {
+        Self {
+            estimate,
+            gain,
+            lag_one_covariance,
+        }
+    }
+    /// Get the smoothed state and covariance, given all data.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+    /// Get the smoother gain `J_t = P_t|t F^T P_t+1|t^-1`.
+    ///
+    /// The smoothed state is `x_t|T = x_t|t + J_t (x_t+1|T - x_t+1|t)`. This
+    /// is `None` for the last step.
+    #[inline]
+    pub fn gain(&self) -> Option<&OMatrix<R, SS, SS>> {
+        self.gain.as_ref()
+    }
+    /// Get the lag-one cross-covariance `Cov(x_t, x_t-1 | all data)`.
+    ///
+    /// This is `P_t|T J_t-1^T`, and `None` for the first step.
+    #[inline]
+    pub fn lag_one_covariance(&self) -> Option<&OMatrix<R, SS, SS>> {
+        self.lag_one_covariance.as_ref()
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_smooth_full() {
+    use crate::{KalmanFilterNoControl, ObservationModel, TransitionModelLinearNoControl};
+    use na::{Matrix1, Matrix1x2, Matrix1x4, Matrix2, Matrix4};
+    use na::{Vector1, Vector2, Vector4, U1, U4};
+
+    struct Linear<SS: Dim>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+        DefaultAllocator: Allocator<f64, U1, SS>,
+        DefaultAllocator: Allocator<f64, SS, U1>,
+    {
+        f: OMatrix<f64, SS, SS>,
+        ft: OMatrix<f64, SS, SS>,
+        q: OMatrix<f64, SS, SS>,
+        h: OMatrix<f64, U1, SS>,
+        ht: OMatrix<f64, SS, U1>,
+        r: Matrix1<f64>,
+    }
+    impl<SS: Dim> TransitionModelLinearNoControl<f64, SS> for Linear<SS>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+        DefaultAllocator: Allocator<f64, SS>,
+        DefaultAllocator: Allocator<f64, U1, SS>,
+        DefaultAllocator: Allocator<f64, SS, U1>,
+    {
+        fn F(&self) -> &OMatrix<f64, SS, SS> {
+            &self.f
+        }
+        fn FT(&self) -> &OMatrix<f64, SS, SS> {
+            &self.ft
+        }
+        fn Q(&self) -> &OMatrix<f64, SS, SS> {
+            &self.q
+        }
+    }
+    impl<SS: Dim> ObservationModel<f64, SS, U1> for Linear<SS>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+        DefaultAllocator: Allocator<f64, SS>,
+        DefaultAllocator: Allocator<f64, U1, SS>,
+        DefaultAllocator: Allocator<f64, SS, U1>,
+    {
+        fn H(&self) -> &OMatrix<f64, U1, SS> {
+            &self.h
+        }
+        fn HT(&self) -> &OMatrix<f64, SS, U1> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    // Constant velocity, observing position.
+    let f = Matrix2::new(1.0, 0.1, 0.0, 1.0);
+    let q = Matrix2::new(1e-3 / 3.0, 5e-3, 5e-3, 0.1) * 0.5;
+    let h = Matrix1x2::new(1.0, 0.0);
+    let model = Linear {
+        f,
+        ft: f.transpose(),
+        q,
+        h,
+        ht: h.transpose(),
+        r: Matrix1::new(0.05),
+    };
+    let observations: Vec<Vector1<f64>> = (0..30)
+        .map(|i| {
+            let t = i as f64 * 0.1;
+            Vector1::new(t * t + 0.1 * (i as f64 * 1.7).sin())
+        })
+        .collect();
+    let initial = StateAndCovariance::new(Vector2::zeros(), Matrix2::identity());
+
+    let kf = KalmanFilterNoControl::new(&model, &model);
+    let smoothed = kf.smooth(&initial, &observations).unwrap();
+    let full = kf.smooth_full(&initial, &observations).unwrap();
+    assert_eq!(full.len(), observations.len());
+    for (a, b) in full.iter().zip(smoothed.iter()) {
+        assert_eq!(a.estimate().state(), b.state());
+        assert_eq!(a.estimate().covariance(), b.covariance());
+    }
+    assert!(full.first().unwrap().lag_one_covariance().is_none());
+    assert!(full.last().unwrap().gain().is_none());
+
+    // The gains relate consecutive smoothed states.
+    let filtered = kf.filter(&initial, &observations).unwrap();
+    for t in 0..observations.len() - 1 {
+        let prior = model.predict(&filtered[t]);
+        let gain = full[t].gain().unwrap();
+        let state = filtered[t].state() + gain * (full[t + 1].estimate().state() - prior.state());
+        approx::assert_relative_eq!(full[t].estimate().state(), &state, epsilon = 1e-12);
+    }
+
+    // Smoothing the augmented state `[x_t, x_t-1]` gives the lag-one
+    // cross-covariance directly.
+    let mut f_aug = Matrix4::zeros();
+    f_aug.fixed_view_mut::<2, 2>(0, 0).copy_from(&f);
+    f_aug
+        .fixed_view_mut::<2, 2>(2, 0)
+        .copy_from(&Matrix2::identity());
+    let mut q_aug = Matrix4::zeros();
+    q_aug.fixed_view_mut::<2, 2>(0, 0).copy_from(&q);
+    let h_aug = Matrix1x4::new(1.0, 0.0, 0.0, 0.0);
+    let augmented = Linear::<U4> {
+        f: f_aug,
+        ft: f_aug.transpose(),
+        q: q_aug,
+        h: h_aug,
+        ht: h_aug.transpose(),
+        r: model.r,
+    };
+    let initial_aug = StateAndCovariance::new(Vector4::zeros(), Matrix4::identity());
+    let kf_aug = KalmanFilterNoControl::new(&augmented, &augmented);
+    let smoothed_aug = kf_aug.smooth(&initial_aug, &observations).unwrap();
+    for t in 1..observations.len() {
+        let expected = smoothed_aug[t].covariance().fixed_view::<2, 2>(0, 2);
+        let lag_one = full[t].lag_one_covariance().unwrap();
+        approx::assert_relative_eq!(lag_one, &expected.into_owned(), epsilon = 1e-9);
+    }
+}
@@ -1,14 +1,30 @@
 use na::allocator::Allocator;
-use na::{DefaultAllocator, DimName, RealField};
+use na::{DefaultAllocator, Dim, DimName, RealField};
 use na::{OMatrix, OVector};
 use nalgebra as na;
 
 /// State and covariance pair for a given estimate
+///
+/// `SS` is a static dimension such as `U4`, or, with the `std` feature,
+/// nalgebra's `Dyn` for a state size chosen at runtime.
+///
+/// With the `serde` feature, it can be serialized, for example with postcard
+/// to checkpoint a filter. Serializing `Dyn` dimensions additionally needs
+/// nalgebra's `serde-serialize` feature.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "OVector<R, SS>: serde::Serialize, OMatrix<R, SS, SS>: serde::Serialize",
+        deserialize = "OVector<R, SS>: serde::Deserialize<'de>, \
+                       OMatrix<R, SS, SS>: serde::Deserialize<'de>"
+    ))
+)]
 pub struct StateAndCovariance<R, SS>
 where
     R: RealField,
-    SS: DimName,
+    SS: Dim,
     DefaultAllocator: Allocator<R, SS, SS>,
     DefaultAllocator: Allocator<R, SS>,
 {
@@ -19,7 +35,7 @@ where
 impl<R, SS> StateAndCovariance<R, SS>
 where
     R: RealField,
-    SS: DimName,
+    SS: Dim,
     DefaultAllocator: Allocator<R, SS, SS>,
     DefaultAllocator: Allocator<R, SS>,
 {
@@ -55,4 +71,77 @@ where
     pub fn inner(self) -> (OVector<R, SS>, OMatrix<R, SS, SS>) {
         (self.state, self.covariance)
     }
-}
\ No newline at end of file
+}
+
+impl<R, SS> StateAndCovariance<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a diffuse `StateAndCovariance`, expressing little knowledge.
+    ///
+    /// The state is zero and the covariance is `variance` times the identity.
+    /// `variance` should be large compared to the expected squared state.
+    pub fn diffuse(variance: R) -> Self {
+        Self::new(
+            OVector::<R, SS>::zeros(),
+            OMatrix::<R, SS, SS>::identity() * variance,
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use crate::{Autocorrelation, ChiSquareTest, ConsistencyReport, CovarianceUpdateMethod};
+    use na::{Matrix3, Vector3, U3};
+
+    let estimate = StateAndCovariance::<f64, U3>::new(
+        Vector3::new(1.0, -2.0, 0.5),
+        Matrix3::new(2.0, 0.1, 0.0, 0.1, 1.0, -0.2, 0.0, -0.2, 0.5),
+    );
+
+    // postcard works without `std` and an allocator.
+    let mut buffer = [0u8; 128];
+    let bytes = postcard::to_slice(&estimate, &mut buffer).unwrap();
+    assert_eq!(bytes.len(), 12 * 8);
+    let decoded: StateAndCovariance<f64, U3> = postcard::from_bytes(bytes).unwrap();
+    assert_eq!(decoded.state(), estimate.state());
+    assert_eq!(decoded.covariance(), estimate.covariance());
+
+    let json = serde_json::to_string(&estimate).unwrap();
+    let decoded: StateAndCovariance<f64, U3> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded.state(), estimate.state());
+    assert_eq!(decoded.covariance(), estimate.covariance());
+
+    let json = serde_json::to_string(&CovarianceUpdateMethod::JosephForm).unwrap();
+    assert_eq!(json, "\"JosephForm\"");
+    let method: CovarianceUpdateMethod = serde_json::from_str(&json).unwrap();
+    assert_eq!(method, CovarianceUpdateMethod::JosephForm);
+
+    let report = ConsistencyReport::<f64, 3> {
+        confidence: 0.95,
+        nees: None,
+        nis: Some(ChiSquareTest {
+            count: 100,
+            average: 2.1,
+            band: (1.6, 2.5),
+            outside: 4,
+        }),
+        autocorrelation: Some(Autocorrelation {
+            values: [0.1, -0.05, 0.02],
+            bound: 0.2,
+        }),
+    };
+    let bytes = postcard::to_slice(&report, &mut buffer).unwrap();
+    let decoded: ConsistencyReport<f64, 3> = postcard::from_bytes(bytes).unwrap();
+    assert_eq!(decoded, report);
+    let json = serde_json::to_string(&report).unwrap();
+    let decoded: ConsistencyReport<f64, 3> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, report);
+    // An array of the wrong length is rejected.
+    let short = json.replace("[0.1,-0.05,0.02]", "[0.1,-0.05]");
+    assert!(serde_json::from_str::<ConsistencyReport<f64, 3>>(&short).is_err());
+}
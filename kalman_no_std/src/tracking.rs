@@ -0,0 +1,526 @@
+use core::array;
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_nan, CovarianceUpdateMethod, Error, ErrorKind, KalmanFilterNoControl, StateAndCovariance,
+};
+
+/// Solve the linear assignment problem by the Hungarian algorithm
+///
+/// `cost[i][j]` is the cost of assigning row `i` to column `j`, or `None` if
+/// that assignment is not allowed. Returns the column assigned to each row.
+/// The number of assignments is maximized first and the total cost second.
+///
+/// Runs in `O(N^3)` time without allocating.
+// Every index is a row or column below `N` into arrays of length `N`.
+#[allow(clippy::indexing_slicing)]
+pub fn assign<R: RealField, const N: usize>(cost: &[[Option<R>; N]; N]) -> [Option<usize>; N] {
+    let mut result = [None; N];
+
+    let mut bounds: Option<(R, R)> = None;
+    for c in cost.iter().flatten().flatten() {
+        bounds = Some(match bounds {
+            Some((min, max)) => (min.min(c.clone()), max.max(c.clone())),
+            None => (c.clone(), c.clone()),
+        });
+    }
+    let (min, max) = match bounds {
+        Some(v) => v,
+        None => return result,
+    };
+    // Shift costs to be non-negative and replace forbidden entries by a cost
+    // larger than any complete assignment of allowed entries.
+    let forbidden = (max - min.clone() + R::one()) * na::convert(N as f64 + 1.0);
+    let a = |i: usize, j: usize| match &cost[i][j] {
+        Some(c) => c.clone() - min.clone(),
+        None => forbidden.clone(),
+    };
+
+    // Row and column potentials.
+    let mut u: [R; N] = array::from_fn(|_| R::zero());
+    let mut v: [R; N] = array::from_fn(|_| R::zero());
+    // Row matched to each column.
+    let mut p: [Option<usize>; N] = [None; N];
+    // Previous column on the alternating path (`None` is the start).
+    let mut way: [Option<usize>; N] = [None; N];
+
+    for i in 0..N {
+        let mut minv: [Option<R>; N] = array::from_fn(|_| None);
+        let mut used = [false; N];
+        let mut j0: Option<usize> = None;
+        let mut i0 = i;
+
+        // Grow the alternating tree until a free column is found.
+        let free_column = loop {
+            let mut best: Option<(R, usize)> = None;
+            for j in 0..N {
+                if used[j] {
+                    continue;
+                }
+                let cur = a(i0, j) - u[i0].clone() - v[j].clone();
+                if minv[j].as_ref().is_none_or(|m| cur < *m) {
+                    minv[j] = Some(cur);
+                    way[j] = j0;
+                }
+                if let Some(m) = &minv[j] {
+                    if best.as_ref().is_none_or(|(delta, _)| m < delta) {
+                        best = Some((m.clone(), j));
+                    }
+                }
+            }
+            let (delta, j1) = match best {
+                Some(v) => v,
+                None => break None,
+            };
+
+            u[i] += delta.clone();
+            for j in 0..N {
+                if used[j] {
+                    if let Some(row) = p[j] {
+                        u[row] += delta.clone();
+                    }
+                    v[j] -= delta.clone();
+                } else if let Some(m) = &mut minv[j] {
+                    *m -= delta.clone();
+                }
+            }
+
+            used[j1] = true;
+            j0 = Some(j1);
+            match p[j1] {
+                Some(row) => i0 = row,
+                None => break Some(j1),
+            }
+        };
+
+        // Augment along the alternating path.
+        let mut j = free_column;
+        while let Some(jj) = j {
+            let prev = way[jj];
+            p[jj] = match prev {
+                Some(pj) => p[pj],
+                None => Some(i),
+            };
+            j = prev;
+        }
+    }
+
+    for (j, row) in p.iter().enumerate() {
+        if let Some(i) = *row {
+            if cost[i][j].is_some() {
+                result[i] = Some(j);
+            }
+        }
+    }
+    result
+}
+
+/// The lifecycle stage of a [`Track`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TrackStatus {
+    /// The track was recently initiated and has not yet been confirmed.
+    Tentative,
+    /// The track has been detected often enough to be considered a target.
+    Confirmed,
+}
+
+/// A single target track
+#[derive(Debug, Clone)]
+pub struct Track<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    id: u32,
+    status: TrackStatus,
+    estimate: StateAndCovariance<R, SS>,
+    /// Bit `i` is set if the track was detected `i` scans ago.
+    history: u32,
+    age: u32,
+    misses: u32,
+}
+
+impl<R, SS> Track<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    fn new(id: u32, estimate: StateAndCovariance<R, SS>) -> Self {
+        Self {
+            id,
+            status: TrackStatus::Tentative,
+            estimate,
+            history: 1,
+            age: 1,
+            misses: 0,
+        }
+    }
+    /// Get the unique identifier of this track.
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    /// Get the lifecycle stage of this track.
+    #[inline]
+    pub fn status(&self) -> TrackStatus {
+        self.status
+    }
+    /// Get a reference to the state and covariance of this track.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+    /// Get the number of scans since this track was initiated.
+    #[inline]
+    pub fn age(&self) -> u32 {
+        self.age
+    }
+
+    fn record(&mut self, detected: bool) {
+        self.history = (self.history << 1) | u32::from(detected);
+        self.age = self.age.saturating_add(1);
+        self.misses = if detected {
+            0
+        } else {
+            self.misses.saturating_add(1)
+        };
+    }
+}
+
+/// Parameters for gating, initiating, confirming and deleting tracks
+#[derive(Debug, Clone)]
+pub struct TrackManagement<R: RealField> {
+    gate: R,
+    confirm_hits: u32,
+    confirm_window: u32,
+    delete_misses: u32,
+}
+
+impl<R: RealField> TrackManagement<R> {
+    /// Create a new `TrackManagement`.
+    ///
+    /// A detection may only be assigned to a track if its normalized
+    /// innovation squared is at most `gate` (a chi-square threshold with as
+    /// many degrees of freedom as the observation). A tentative track is
+    /// confirmed once it has been detected in `confirm_hits` of the last
+    /// `confirm_window` scans (M-of-N logic) and deleted if this does not
+    /// happen within `confirm_window` scans. A confirmed track is deleted after
+    /// `delete_misses` consecutive missed detections. `confirm_window` is
+    /// limited to 32 scans.
+    pub fn new(gate: R, confirm_hits: u32, confirm_window: u32, delete_misses: u32) -> Self {
+        Self {
+            gate,
+            confirm_hits,
+            confirm_window: confirm_window.min(u32::BITS),
+            delete_misses,
+        }
+    }
+
+    /// Update the status of a track and return `false` if it should be deleted.
+    fn review<SS>(&self, track: &mut Track<R, SS>) -> bool
+    where
+        SS: DimName,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+    {
+        match track.status {
+            TrackStatus::Tentative => {
+                let window_mask = u32::MAX
+                    .checked_shr(u32::BITS - self.confirm_window)
+                    .unwrap_or(0);
+                if (track.history & window_mask).count_ones() >= self.confirm_hits {
+                    track.status = TrackStatus::Confirmed;
+                    true
+                } else {
+                    track.age < self.confirm_window
+                }
+            }
+            TrackStatus::Confirmed => track.misses < self.delete_misses,
+        }
+    }
+}
+
+/// A multi-target tracker using global nearest neighbour data association
+///
+/// Each track is estimated by the models of a
+/// [`KalmanFilterNoControl`](struct.KalmanFilterNoControl.html). On every scan,
+/// tracks are predicted, gated detections are assigned to tracks by the
+/// Hungarian algorithm ([`assign`]) minimizing the total normalized innovation
+/// squared, and unassigned detections initiate new tentative tracks. At most
+/// `N` tracks are held and at most `N` detections per scan are accepted.
+pub struct MultiTargetTracker<'a, R, SS, OS, const N: usize>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    filter: KalmanFilterNoControl<'a, R, SS, OS>,
+    management: TrackManagement<R>,
+    tracks: [Option<Track<R, SS>>; N],
+    next_id: u32,
+}
+
+impl<'a, R, SS, OS, const N: usize> MultiTargetTracker<'a, R, SS, OS, N>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `MultiTargetTracker` with no tracks.
+    pub fn new(
+        filter: KalmanFilterNoControl<'a, R, SS, OS>,
+        management: TrackManagement<R>,
+    ) -> Self {
+        Self {
+            filter,
+            management,
+            tracks: array::from_fn(|_| None),
+            next_id: 0,
+        }
+    }
+
+    /// Process one scan of detections
+    ///
+    /// `initiate` creates the initial estimate of a new track from an
+    /// unassigned detection. Detections with a NaN component are ignored.
+    ///
+    /// Returns `ErrorKind::DimensionMismatch` if there are more than `N`
+    /// detections. If gating or updating any track fails, the error is
+    /// returned and the tracker is left unchanged.
+    pub fn step<F>(&mut self, detections: &[OVector<R, OS>], mut initiate: F) -> Result<(), Error>
+    where
+        F: FnMut(&OVector<R, OS>) -> StateAndCovariance<R, SS>,
+    {
+        if detections.len() > N {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: N,
+                actual: detections.len(),
+            }
+            .into());
+        }
+        let transition_model = self.filter.transition_model();
+        let observation_model = self.filter.observation_model();
+
+        let mut priors: [Option<StateAndCovariance<R, SS>>; N] = array::from_fn(|_| None);
+        for (slot, prior) in self.tracks.iter().zip(priors.iter_mut()) {
+            *prior = slot
+                .as_ref()
+                .map(|track| transition_model.predict(&track.estimate));
+        }
+
+        // Gate every track against every detection.
+        let mut cost: [[Option<R>; N]; N] = array::from_fn(|_| array::from_fn(|_| None));
+        for (prior, row) in priors.iter().zip(cost.iter_mut()) {
+            if let Some(prior) = prior {
+                for (detection, entry) in detections.iter().zip(row.iter_mut()) {
+                    if detection.iter().any(|x| is_nan(x.clone())) {
+                        continue;
+                    }
+                    let nis = observation_model.innovation(prior, detection).nis()?;
+                    if nis <= self.management.gate {
+                        *entry = Some(nis);
+                    }
+                }
+            }
+        }
+
+        // Update every assigned track before changing any of them.
+        let assignment = assign(&cost);
+        let mut posteriors: [Option<StateAndCovariance<R, SS>>; N] = array::from_fn(|_| None);
+        let mut detection_used = [false; N];
+        for ((prior, assigned), posterior) in
+            priors.iter().zip(assignment).zip(posteriors.iter_mut())
+        {
+            let assigned =
+                assigned.and_then(|j| Some((detections.get(j)?, detection_used.get_mut(j)?)));
+            if let (Some(prior), Some((detection, used))) = (prior, assigned) {
+                *posterior = Some(observation_model.update(
+                    prior,
+                    detection,
+                    CovarianceUpdateMethod::JosephForm,
+                )?);
+                *used = true;
+            }
+        }
+
+        for ((slot, prior), posterior) in self.tracks.iter_mut().zip(priors).zip(posteriors) {
+            if let (Some(track), Some(prior)) = (slot.as_mut(), prior) {
+                track.record(posterior.is_some());
+                track.estimate = posterior.unwrap_or(prior);
+                if !self.management.review(track) {
+                    *slot = None;
+                }
+            }
+        }
+
+        for (detection, used) in detections.iter().zip(detection_used) {
+            if used || detection.iter().any(|x| is_nan(x.clone())) {
+                continue;
+            }
+            if let Some(slot) = self.tracks.iter_mut().find(|slot| slot.is_none()) {
+                *slot = Some(Track::new(self.next_id, initiate(detection)));
+                self.next_id = self.next_id.wrapping_add(1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterate over all tracks, tentative and confirmed.
+    pub fn tracks(&self) -> impl Iterator<Item = &Track<R, SS>> {
+        self.tracks.iter().flatten()
+    }
+
+    /// Iterate over the confirmed tracks.
+    pub fn confirmed_tracks(&self) -> impl Iterator<Item = &Track<R, SS>> {
+        self.tracks()
+            .filter(|track| track.status == TrackStatus::Confirmed)
+    }
+
+    /// Get the track with the given identifier.
+    pub fn track(&self, id: u32) -> Option<&Track<R, SS>> {
+        self.tracks().find(|track| track.id == id)
+    }
+
+    /// Remove all tracks.
+    pub fn clear(&mut self) {
+        self.tracks.iter_mut().for_each(|slot| *slot = None);
+    }
+}
+
+#[test]
+fn test_assign() {
+    // Greedy assignment would pick (0, 0) and leave row 1 with cost 10.
+    let cost = [
+        [Some(1.0), Some(2.0), None],
+        [Some(1.5), Some(10.0), None],
+        [None, None, None],
+    ];
+    assert_eq!(assign(&cost), [Some(1), Some(0), None]);
+
+    // The number of assignments is maximized before the cost.
+    let cost = [[Some(0.0), Some(5.0)], [Some(0.0), None]];
+    assert_eq!(assign(&cost), [Some(1), Some(0)]);
+}
+
+#[test]
+fn test_multi_target_tracker() {
+    use na::{Matrix1, Matrix1x2, Matrix2, Matrix2x1, Vector1, Vector2, U1, U2};
+
+    use crate::{ObservationModel, TransitionModelLinearNoControl};
+
+    struct Model {
+        f: Matrix2<f64>,
+        ft: Matrix2<f64>,
+        q: Matrix2<f64>,
+        h: Matrix1x2<f64>,
+        ht: Matrix2x1<f64>,
+        r: Matrix1<f64>,
+    }
+    impl TransitionModelLinearNoControl<f64, U2> for Model {
+        fn F(&self) -> &Matrix2<f64> {
+            &self.f
+        }
+        fn FT(&self) -> &Matrix2<f64> {
+            &self.ft
+        }
+        fn Q(&self) -> &Matrix2<f64> {
+            &self.q
+        }
+    }
+    impl ObservationModel<f64, U2, U1> for Model {
+        fn H(&self) -> &Matrix1x2<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix2x1<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix1<f64> {
+            &self.r
+        }
+    }
+
+    let f = Matrix2::new(1.0, 1.0, 0.0, 1.0);
+    let h = Matrix1x2::new(1.0, 0.0);
+    let model = Model {
+        f,
+        ft: f.transpose(),
+        q: Matrix2::identity() * 1e-3,
+        h,
+        ht: h.transpose(),
+        r: Matrix1::new(1e-2),
+    };
+    let filter = KalmanFilterNoControl::new(&model, &model);
+    let mut tracker =
+        MultiTargetTracker::<_, _, _, 4>::new(filter, TrackManagement::new(9.0, 3, 4, 2));
+    let initiate = |z: &Vector1<f64>| {
+        StateAndCovariance::new(Vector2::new(z[0], 0.0), Matrix2::new(1e-2, 0.0, 0.0, 4.0))
+    };
+
+    // Two targets approach each other; a clutter detection appears once.
+    for t in 0..6 {
+        let t = t as f64;
+        let mut detections = [
+            Vector1::new(10.0 - t),
+            Vector1::new(t),
+            Vector1::new(f64::NAN),
+        ];
+        if t == 1.0 {
+            detections[2] = Vector1::new(100.0);
+        }
+        if t > 2.0 {
+            detections.swap(0, 1);
+        }
+        tracker.step(&detections, initiate).unwrap();
+    }
+
+    assert_eq!(tracker.confirmed_tracks().count(), 2);
+    assert_eq!(tracker.tracks().count(), 2);
+    let rising = tracker.track(1).unwrap();
+    approx::assert_relative_eq!(rising.estimate().state()[0], 5.0, epsilon = 1e-2);
+    approx::assert_relative_eq!(rising.estimate().state()[1], 1.0, epsilon = 1e-1);
+    let falling = tracker.track(0).unwrap();
+    approx::assert_relative_eq!(falling.estimate().state()[1], -1.0, epsilon = 1e-1);
+
+    // Too many detections are rejected rather than truncated.
+    let detections = [0.0, 1.0, 2.0, 3.0, 4.0].map(Vector1::new);
+    assert_eq!(
+        tracker.step(&detections, initiate).unwrap_err().kind(),
+        &ErrorKind::DimensionMismatch {
+            expected: 4,
+            actual: 5
+        }
+    );
+
+    // A failing track leaves every track as it was.
+    let degenerate = |_: &Vector1<f64>| {
+        StateAndCovariance::new(Vector2::new(50.0, 0.0), Matrix2::from_element(f64::NAN))
+    };
+    tracker.step(&[Vector1::new(50.0)], degenerate).unwrap();
+    let snapshot = |tracker: &MultiTargetTracker<_, _, _, 4>| {
+        tracker.tracks.each_ref().map(|slot| {
+            slot.as_ref()
+                .map(|track| (track.id(), track.age(), track.estimate().state()[0]))
+        })
+    };
+    let before = snapshot(&tracker);
+    assert_eq!(tracker.tracks().count(), 3);
+    assert!(tracker.step(&[Vector1::new(6.0)], initiate).is_err());
+    assert_eq!(snapshot(&tracker), before);
+}
@@ -0,0 +1,336 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, Dim, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{shape, Error, StateAndCovariance, TransitionModelLinearNoControl};
+
+/// Preallocated storage for the in-place prediction and update steps
+///
+/// Holds every intermediate of
+/// [`ObservationModel::update_in_place`](trait.ObservationModel.html#method.update_in_place)
+/// and
+/// [`KalmanFilterNoControl::step_in_place`](struct.KalmanFilterNoControl.html#method.step_in_place),
+/// so that with `Dyn` dimensions a filter allocates only when the workspace
+/// is created. One workspace serves any number of steps of models with the
+/// same dimensions.
+///
+/// The workspace is used only with `Dyn` dimensions. With static dimensions
+/// nothing is allocated anyway, so the in-place steps fall back to the
+/// value-returning
+/// [`ObservationModel::update`](trait.ObservationModel.html#method.update),
+/// whose temporaries the compiler keeps in registers, and the workspace is
+/// left untouched.
+#[derive(Debug, Clone)]
+pub struct UpdateWorkspace<R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    /// `H P`, and `R K^T` in the Joseph form.
+    pub(crate) hp: OMatrix<R, OS, SS>,
+    /// The transposed gain, `K^T = S^-1 H P`.
+    pub(crate) gain_t: OMatrix<R, OS, SS>,
+    /// The innovation covariance `S`, overwritten by its Cholesky factor.
+    /// `None` only after a failed factorization consumed the storage.
+    pub(crate) s: Option<OMatrix<R, OS, OS>>,
+    pub(crate) innovation: OVector<R, OS>,
+    pub(crate) state: OVector<R, SS>,
+    /// `(I - K H)^T` in the Joseph form.
+    pub(crate) one_minus_kh_t: OMatrix<R, SS, SS>,
+    pub(crate) product: OMatrix<R, SS, SS>,
+}
+
+impl<R, SS, OS> UpdateWorkspace<R, SS, OS>
+where
+    R: RealField,
+    SS: Dim,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    /// Create a new `UpdateWorkspace` for `state_dim` states and
+    /// `observation_dim` observations.
+    ///
+    /// For static dimensions, pass the dimension types, such as
+    /// `UpdateWorkspace::new(U4, U2)`. For runtime sizes, pass `Dyn(n)`.
+    pub fn new(state_dim: SS, observation_dim: OS) -> Self {
+        Self {
+            hp: OMatrix::zeros_generic(observation_dim, state_dim),
+            gain_t: OMatrix::zeros_generic(observation_dim, state_dim),
+            s: Some(OMatrix::zeros_generic(observation_dim, observation_dim)),
+            innovation: OVector::zeros_generic(observation_dim, na::Const::<1>),
+            state: OVector::zeros_generic(state_dim, na::Const::<1>),
+            one_minus_kh_t: OMatrix::zeros_generic(state_dim, state_dim),
+            product: OMatrix::zeros_generic(state_dim, state_dim),
+        }
+    }
+
+    /// Check that the workspace was created for `state_dim` states and
+    /// `observation_dim` observations.
+    pub(crate) fn check_shape(
+        &self,
+        state_dim: usize,
+        observation_dim: usize,
+    ) -> Result<(), Error> {
+        shape::check_shape((observation_dim, state_dim), self.hp.shape())?;
+        shape::check_shape((state_dim, state_dim), self.product.shape())
+    }
+
+    /// Take the storage for `S`, recreating it after a failed factorization.
+    pub(crate) fn take_s(&mut self) -> OMatrix<R, OS, OS> {
+        match self.s.take() {
+            Some(s) => s,
+            None => {
+                let (rows, _) = self.hp.shape_generic();
+                OMatrix::zeros_generic(rows, rows)
+            }
+        }
+    }
+}
+
+/// Solve `L L^T X = B` in place, with `factor` holding `L` in its lower
+/// triangle.
+///
+/// The substitutions update whole rows of `B`, which for the few rows of an
+/// observation is faster than solving column by column.
+pub(crate) fn cholesky_solve_mut<R, OS, SS>(factor: &OMatrix<R, OS, OS>, b: &mut OMatrix<R, OS, SS>)
+where
+    R: RealField,
+    OS: Dim,
+    SS: Dim,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+{
+    let n = factor.nrows();
+    let entry = |i: usize, j: usize| factor.get((i, j)).cloned().unwrap_or_else(R::one);
+    // Forward substitution, `L Y = B`.
+    for i in 0..n {
+        for k in 0..i {
+            let l_ik = entry(i, k);
+            let (mut row_i, row_k) = b.rows_range_pair_mut(i, k);
+            row_i.zip_apply(&row_k, |x, y| *x -= l_ik.clone() * y);
+        }
+        b.row_mut(i).unscale_mut(entry(i, i));
+    }
+    // Back substitution, `L^T X = Y`.
+    for i in (0..n).rev() {
+        for k in i + 1..n {
+            let l_ki = entry(k, i);
+            let (mut row_i, row_k) = b.rows_range_pair_mut(i, k);
+            row_i.zip_apply(&row_k, |x, y| *x -= l_ki.clone() * y);
+        }
+        b.row_mut(i).unscale_mut(entry(i, i));
+    }
+}
+
+/// Whether both dimensions are known at compile time, in which case the
+/// value-returning steps are faster than the workspace.
+#[inline]
+pub(crate) fn is_static<SS: Dim, OS: Dim>() -> bool {
+    SS::try_to_usize().is_some() && OS::try_to_usize().is_some()
+}
+
+/// Predict in place, `x = F x` and `P = F P F^T + Q`.
+pub(crate) fn predict_in_place<R, SS, OS>(
+    transition_model: &(impl TransitionModelLinearNoControl<R, SS> + ?Sized),
+    estimate: &mut StateAndCovariance<R, SS>,
+    workspace: &mut UpdateWorkspace<R, SS, OS>,
+) where
+    R: RealField,
+    SS: Dim,
+    OS: Dim,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    if is_static::<SS, OS>() {
+        *estimate = transition_model.predict(estimate);
+        return;
+    }
+    let f = transition_model.F();
+    f.mul_to(estimate.state(), &mut workspace.state);
+    estimate.state_mut().copy_from(&workspace.state);
+    f.mul_to(estimate.covariance(), &mut workspace.product);
+    let covariance = estimate.covariance_mut();
+    workspace.product.mul_to(transition_model.FT(), covariance);
+    *covariance += transition_model.Q();
+}
+
+#[test]
+fn test_update_in_place() {
+    use crate::{CovarianceUpdateMethod, ErrorKind, ObservationModel};
+    use na::{Matrix2, Matrix2x4, Matrix4, Matrix4x2, Vector2, Vector4, U2, U4};
+
+    struct Partial {
+        h: Matrix2x4<f64>,
+        ht: Matrix4x2<f64>,
+        r: Matrix2<f64>,
+    }
+    impl ObservationModel<f64, U4, U2> for Partial {
+        fn H(&self) -> &Matrix2x4<f64> {
+            &self.h
+        }
+        fn HT(&self) -> &Matrix4x2<f64> {
+            &self.ht
+        }
+        fn R(&self) -> &Matrix2<f64> {
+            &self.r
+        }
+    }
+
+    #[rustfmt::skip]
+    let h = Matrix2x4::new(
+        1.0, 0.0, 0.5, 0.0,
+        0.0, 1.0, 0.0, -0.5,
+    );
+    let model = Partial {
+        h,
+        ht: h.transpose(),
+        r: Matrix2::new(0.3, 0.1, 0.1, 0.2),
+    };
+    #[rustfmt::skip]
+    let l = Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.2, 0.8, 0.0, 0.0,
+        -0.3, 0.1, 0.6, 0.0,
+        0.4, -0.2, 0.3, 0.5,
+    );
+    let prior = StateAndCovariance::new(Vector4::new(1.0, -2.0, 0.5, 3.0), l * l.transpose());
+    let observation = Vector2::new(1.5, -3.0);
+
+    // The textbook update with an explicit inverse.
+    let p = prior.covariance();
+    let s = h * p * h.transpose() + model.r;
+    let k = p * h.transpose() * s.try_inverse().unwrap();
+    let state = prior.state() + k * (observation - h * prior.state());
+    let one_minus_kh = Matrix4::identity() - k * h;
+    let joseph = one_minus_kh * p * one_minus_kh.transpose() + k * model.r * k.transpose();
+
+    let mut workspace = UpdateWorkspace::new(U4, U2);
+    for method in [
+        CovarianceUpdateMethod::JosephForm,
+        CovarianceUpdateMethod::OptimalKalman,
+        CovarianceUpdateMethod::OptimalKalmanForcedSymmetric,
+    ] {
+        let mut estimate = prior.clone();
+        model
+            .update_in_place(&mut estimate, &observation, method, &mut workspace)
+            .unwrap();
+        approx::assert_relative_eq!(estimate.state(), &state, epsilon = 1e-12);
+        approx::assert_relative_eq!(estimate.covariance(), &joseph, epsilon = 1e-12);
+    }
+
+    // A failed factorization leaves the estimate unchanged and the workspace
+    // usable.
+    let singular = Partial {
+        h,
+        ht: h.transpose(),
+        r: -Matrix2::identity() * 10.0,
+    };
+    let mut estimate = prior.clone();
+    let err = singular
+        .update_in_place(
+            &mut estimate,
+            &observation,
+            CovarianceUpdateMethod::JosephForm,
+            &mut workspace,
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::SingularInnovationCovariance);
+    assert_eq!(estimate.state(), prior.state());
+    assert_eq!(estimate.covariance(), prior.covariance());
+    model
+        .update_in_place(
+            &mut estimate,
+            &observation,
+            CovarianceUpdateMethod::JosephForm,
+            &mut workspace,
+        )
+        .unwrap();
+    approx::assert_relative_eq!(estimate.state(), &state, epsilon = 1e-12);
+
+    // With static dimensions the workspace is unused, so check it with
+    // runtime dimensions as well.
+    #[cfg(feature = "std")]
+    {
+        use na::{DMatrix, DVector, Dyn};
+
+        struct DynPartial {
+            h: DMatrix<f64>,
+            ht: DMatrix<f64>,
+            r: DMatrix<f64>,
+        }
+        impl ObservationModel<f64, Dyn, Dyn> for DynPartial {
+            fn H(&self) -> &DMatrix<f64> {
+                &self.h
+            }
+            fn HT(&self) -> &DMatrix<f64> {
+                &self.ht
+            }
+            fn R(&self) -> &DMatrix<f64> {
+                &self.r
+            }
+        }
+        let dynamic = |r: &Matrix2<f64>| DynPartial {
+            h: DMatrix::from_column_slice(2, 4, h.as_slice()),
+            ht: DMatrix::from_column_slice(4, 2, h.transpose().as_slice()),
+            r: DMatrix::from_column_slice(2, 2, r.as_slice()),
+        };
+        let model = dynamic(&model.r);
+        let prior = StateAndCovariance::new(
+            DVector::from_column_slice(prior.state().as_slice()),
+            DMatrix::from_column_slice(4, 4, prior.covariance().as_slice()),
+        );
+        let observation = DVector::from_column_slice(observation.as_slice());
+        let state = DVector::from_column_slice(state.as_slice());
+        let joseph = DMatrix::from_column_slice(4, 4, joseph.as_slice());
+
+        let mut workspace = UpdateWorkspace::new(Dyn(4), Dyn(2));
+        for method in [
+            CovarianceUpdateMethod::JosephForm,
+            CovarianceUpdateMethod::OptimalKalman,
+            CovarianceUpdateMethod::OptimalKalmanForcedSymmetric,
+        ] {
+            let mut estimate = prior.clone();
+            model
+                .update_in_place(&mut estimate, &observation, method, &mut workspace)
+                .unwrap();
+            approx::assert_relative_eq!(estimate.state(), &state, epsilon = 1e-12);
+            approx::assert_relative_eq!(estimate.covariance(), &joseph, epsilon = 1e-12);
+        }
+
+        let singular = dynamic(&singular.r);
+        let mut estimate = prior.clone();
+        let err = singular
+            .update_in_place(
+                &mut estimate,
+                &observation,
+                CovarianceUpdateMethod::JosephForm,
+                &mut workspace,
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::SingularInnovationCovariance);
+        assert_eq!(estimate.state(), prior.state());
+        assert_eq!(estimate.covariance(), prior.covariance());
+        model
+            .update_in_place(
+                &mut estimate,
+                &observation,
+                CovarianceUpdateMethod::JosephForm,
+                &mut workspace,
+            )
+            .unwrap();
+        approx::assert_relative_eq!(estimate.state(), &state, epsilon = 1e-12);
+    }
+}
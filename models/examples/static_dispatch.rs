@@ -0,0 +1,95 @@
+//! Compare the run time of `KalmanFilterNoControl` (dynamic dispatch) with
+//! `KalmanFilter` (static dispatch) on the constant velocity model.
+//!
+//! Run with `cargo run --release --example static_dispatch`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use nalgebra::{dimension::U4, Matrix2, Matrix4, Vector2, Vector4};
+
+use kalman_no_std::{KalmanFilter, KalmanFilterNoControl, StateAndCovariance};
+use models::motion_model::{ConstantVelocity2DModel, PositionObservationModel};
+
+type MyType = f64;
+
+const STEPS: usize = 1_000;
+const REPEATS: usize = 200;
+
+/// Deterministic, roughly uniform noise in `[-0.5, 0.5)`.
+fn noise(seed: &mut u64) -> MyType {
+    *seed = seed
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    (*seed >> 11) as MyType / (1u64 << 53) as MyType - 0.5
+}
+
+/// Run `step` over all observations `REPEATS` times, returning the fastest run.
+fn profile<F>(
+    name: &str,
+    initial: &StateAndCovariance<MyType, U4>,
+    observations: &[Vector2<MyType>],
+    step: F,
+) -> Duration
+where
+    F: Fn(
+        &StateAndCovariance<MyType, U4>,
+        &Vector2<MyType>,
+    ) -> Result<StateAndCovariance<MyType, U4>, kalman_no_std::Error>,
+{
+    let mut best = Duration::MAX;
+    let mut last = initial.clone();
+    for _ in 0..REPEATS {
+        let start = Instant::now();
+        let mut estimate = initial.clone();
+        for observation in observations {
+            estimate = step(black_box(&estimate), black_box(observation)).unwrap();
+        }
+        best = best.min(start.elapsed());
+        last = estimate;
+    }
+    println!(
+        "{:>8}: {:8.1} ns/step, final state {:?}",
+        name,
+        best.as_nanos() as f64 / observations.len() as f64,
+        last.state().as_slice()
+    );
+    best
+}
+
+fn main() {
+    let dt = 0.01;
+    let motion_model = ConstantVelocity2DModel::new(dt, 100.0);
+    let observation_model = PositionObservationModel::new(Matrix2::identity() * 0.01);
+
+    let mut seed = 1;
+    let mut state = Vector4::<MyType>::new(0.0, 0.0, 10.0, -5.0);
+    let observations: Vec<Vector2<MyType>> = (0..STEPS)
+        .map(|_| {
+            state = motion_model.transition_model * state;
+            Vector2::new(
+                state.x + 0.1 * noise(&mut seed),
+                state.y + 0.1 * noise(&mut seed),
+            )
+        })
+        .collect();
+    let initial = StateAndCovariance::new(Vector4::zeros(), Matrix4::identity() * 0.1);
+
+    let kf_dyn = KalmanFilterNoControl::new(&motion_model, &observation_model);
+    let dynamic = profile("dyn", &initial, &observations, |estimate, observation| {
+        kf_dyn.step(estimate, observation)
+    });
+
+    let kf_static = KalmanFilter::new(&motion_model, &observation_model);
+    let generic = profile(
+        "static",
+        &initial,
+        &observations,
+        |estimate, observation| kf_static.step(estimate, observation),
+    );
+
+    println!(
+        "static / dyn: {:.3}",
+        generic.as_secs_f64() / dynamic.as_secs_f64()
+    );
+}
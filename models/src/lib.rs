@@ -1 +1,2 @@
-pub mod motion_model;
\ No newline at end of file
+pub mod motion_model;
+pub mod tuning;
@@ -2,13 +2,21 @@ use nalgebra::{
     allocator::Allocator,
     convert,
     dimension::{U2, U4},
-    DefaultAllocator, OMatrix, RealField,
+    DefaultAllocator, Matrix2, OMatrix, RealField, Vector2, Vector4,
 };
 
-use kalman_no_std::TransitionModelLinearNoControl;
+use kalman_no_std::{
+    ContinuousTransitionModel, Error, ErrorKind, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
 
 // motion model -------
 
+/// Constant velocity 2D model with a fixed interval
+///
+/// With the `serde` feature, the model is serialized as its parameters,
+/// `dt` and `noise_scale`, and the matrices are rebuilt by
+/// [`new`](#method.new) when it is deserialized.
 #[allow(dead_code)]
 pub struct ConstantVelocity2DModel<R>
 where
@@ -19,6 +27,8 @@ where
     pub transition_model: OMatrix<R, U4, U4>,
     pub transition_model_transpose: OMatrix<R, U4, U4>,
     pub transition_noise_covariance: OMatrix<R, U4, U4>,
+    dt: R,
+    noise_scale: R,
 }
 
 impl<R> ConstantVelocity2DModel<R>
@@ -53,8 +63,53 @@ where
             transition_model,
             transition_model_transpose: transition_model.transpose(),
             transition_noise_covariance,
+            dt,
+            noise_scale,
         }
     }
+
+    /// Get the interval between steps, as passed to [`new`](#method.new).
+    pub fn dt(&self) -> R {
+        self.dt
+    }
+
+    /// Get the scale of the process noise, as passed to [`new`](#method.new).
+    pub fn noise_scale(&self) -> R {
+        self.noise_scale
+    }
+
+    /// Initialize an estimate from two position observations by two-point
+    /// differencing.
+    ///
+    /// The position is the second observation and the velocity is the
+    /// difference of the observations divided by `dt`, the time between them.
+    /// Both observations have the noise covariance
+    /// `observation_noise_covariance`. The estimate is at the time of the
+    /// second observation and neglects process noise between the two.
+    ///
+    /// Returns `ErrorKind::NonPositiveTimeStep` if `dt` is not positive.
+    #[allow(dead_code)]
+    pub fn initialize_two_point(
+        first: &Vector2<R>,
+        second: &Vector2<R>,
+        dt: R,
+        observation_noise_covariance: &Matrix2<R>,
+    ) -> Result<StateAndCovariance<R, U4>, Error> {
+        if dt.partial_cmp(&R::zero()) != Some(core::cmp::Ordering::Greater) {
+            return Err(ErrorKind::NonPositiveTimeStep.into());
+        }
+        let velocity = (second - first) / dt;
+        let r = observation_noise_covariance;
+        let state = Vector4::new(second.x, second.y, velocity.x, velocity.y);
+        let mut covariance = OMatrix::<R, U4, U4>::zeros();
+        covariance.fixed_view_mut::<2, 2>(0, 0).copy_from(r);
+        covariance.fixed_view_mut::<2, 2>(0, 2).copy_from(&(r / dt));
+        covariance.fixed_view_mut::<2, 2>(2, 0).copy_from(&(r / dt));
+        covariance
+            .fixed_view_mut::<2, 2>(2, 2)
+            .copy_from(&(r * convert::<f64, R>(2.0) / (dt * dt)));
+        Ok(StateAndCovariance::new(state, covariance))
+    }
 }
 
 impl<R> TransitionModelLinearNoControl<R, U4> for ConstantVelocity2DModel<R>
@@ -75,4 +130,184 @@ where
     fn Q(&self) -> &OMatrix<R, U4, U4> {
         &self.transition_noise_covariance
     }
-}
\ No newline at end of file
+}
+/// The serialized form of [`ConstantVelocity2DModel`]
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "ConstantVelocity2DModel")]
+struct ConstantVelocity2DParameters<R> {
+    dt: R,
+    noise_scale: R,
+}
+
+#[cfg(feature = "serde")]
+impl<R> serde::Serialize for ConstantVelocity2DModel<R>
+where
+    R: RealField + Copy + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConstantVelocity2DParameters {
+            dt: self.dt,
+            noise_scale: self.noise_scale,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, R> serde::Deserialize<'de> for ConstantVelocity2DModel<R>
+where
+    R: RealField + Copy + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let parameters = ConstantVelocity2DParameters::deserialize(deserializer)?;
+        Ok(Self::new(parameters.dt, parameters.noise_scale))
+    }
+}
+
+/// Constant velocity 2D model which can be discretized for any interval
+///
+/// Use with `kalman_no_std::OnlineTracker` when observations are not evenly
+/// spaced in time.
+#[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContinuousConstantVelocity2DModel<R>
+where
+    R: RealField,
+{
+    pub noise_scale: R,
+}
+
+impl<R> ContinuousConstantVelocity2DModel<R>
+where
+    R: RealField + Copy,
+{
+    #[allow(dead_code)]
+    pub fn new(noise_scale: R) -> Self {
+        Self { noise_scale }
+    }
+}
+
+impl<R> ContinuousTransitionModel<R, U4> for ContinuousConstantVelocity2DModel<R>
+where
+    R: RealField + Copy,
+    DefaultAllocator: Allocator<R, U4, U4>,
+    DefaultAllocator: Allocator<R, U4>,
+{
+    type Discrete = ConstantVelocity2DModel<R>;
+
+    fn discretize(&self, dt: R) -> ConstantVelocity2DModel<R> {
+        ConstantVelocity2DModel::new(dt, self.noise_scale)
+    }
+}
+
+// observation model -------
+
+/// Observation of the 2D position of the constant velocity models
+pub struct PositionObservationModel<R>
+where
+    R: RealField,
+    DefaultAllocator: Allocator<R, U2, U4>,
+    DefaultAllocator: Allocator<R, U4, U2>,
+    DefaultAllocator: Allocator<R, U2, U2>,
+{
+    observation_matrix: OMatrix<R, U2, U4>,
+    observation_matrix_transpose: OMatrix<R, U4, U2>,
+    observation_noise_covariance: OMatrix<R, U2, U2>,
+}
+
+impl<R> PositionObservationModel<R>
+where
+    R: RealField + Copy,
+{
+    /// Create a new `PositionObservationModel` with the observation noise
+    /// covariance `R`.
+    pub fn new(observation_noise_covariance: Matrix2<R>) -> Self {
+        let one = convert(1.0);
+        let zero = convert(0.0);
+        #[rustfmt::skip]
+        let observation_matrix = OMatrix::<R,U2,U4>::new(one, zero, zero, zero,
+                                                         zero, one, zero, zero);
+        Self {
+            observation_matrix,
+            observation_matrix_transpose: observation_matrix.transpose(),
+            observation_noise_covariance,
+        }
+    }
+}
+
+impl<R> ObservationModel<R, U4, U2> for PositionObservationModel<R>
+where
+    R: RealField,
+    DefaultAllocator: Allocator<R, U4, U4>,
+    DefaultAllocator: Allocator<R, U2, U4>,
+    DefaultAllocator: Allocator<R, U4, U2>,
+    DefaultAllocator: Allocator<R, U2, U2>,
+    DefaultAllocator: Allocator<R, U4>,
+    DefaultAllocator: Allocator<R, U2>,
+{
+    fn H(&self) -> &OMatrix<R, U2, U4> {
+        &self.observation_matrix
+    }
+    fn HT(&self) -> &OMatrix<R, U4, U2> {
+        &self.observation_matrix_transpose
+    }
+    fn R(&self) -> &OMatrix<R, U2, U2> {
+        &self.observation_noise_covariance
+    }
+}
+
+#[test]
+fn test_initialize_two_point() {
+    let r = Matrix2::new(0.04, 0.01, 0.01, 0.09);
+    let first = Vector2::new(1.0, 2.0);
+    let second = Vector2::new(1.5, 1.0);
+    let estimate = ConstantVelocity2DModel::initialize_two_point(&first, &second, 0.5, &r).unwrap();
+    assert_eq!(estimate.state(), &Vector4::new(1.5, 1.0, 1.0, -2.0));
+
+    // The covariance of [z2, (z2 - z1) / dt] for independent z1 and z2.
+    let mut jacobian = OMatrix::<f64, U4, U4>::zeros();
+    jacobian
+        .fixed_view_mut::<2, 2>(0, 2)
+        .copy_from(&Matrix2::identity());
+    jacobian
+        .fixed_view_mut::<2, 2>(2, 0)
+        .copy_from(&(Matrix2::identity() * -2.0));
+    jacobian
+        .fixed_view_mut::<2, 2>(2, 2)
+        .copy_from(&(Matrix2::identity() * 2.0));
+    let mut joint = OMatrix::<f64, U4, U4>::zeros();
+    joint.fixed_view_mut::<2, 2>(0, 0).copy_from(&r);
+    joint.fixed_view_mut::<2, 2>(2, 2).copy_from(&r);
+    let expected = jacobian * joint * jacobian.transpose();
+    assert!((estimate.covariance() - expected).amax() < 1e-12);
+
+    for dt in [0.0, -0.5, f64::NAN] {
+        let err =
+            ConstantVelocity2DModel::initialize_two_point(&first, &second, dt, &r).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::NonPositiveTimeStep);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let model = ConstantVelocity2DModel::new(0.1, 2.0);
+    let json = serde_json::to_string(&model).unwrap();
+    assert_eq!(json, r#"{"dt":0.1,"noise_scale":2.0}"#);
+    let restored: ConstantVelocity2DModel<f64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.transition_model, model.transition_model);
+    assert_eq!(
+        restored.transition_model_transpose,
+        model.transition_model_transpose
+    );
+    assert_eq!(
+        restored.transition_noise_covariance,
+        model.transition_noise_covariance
+    );
+
+    let continuous = ContinuousConstantVelocity2DModel::new(2.0);
+    let json = serde_json::to_string(&continuous).unwrap();
+    let restored: ContinuousConstantVelocity2DModel<f64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.noise_scale, continuous.noise_scale);
+}
@@ -0,0 +1,403 @@
+//! Automatic tuning of the noise parameters of the constant velocity model
+//!
+//! The process noise scale and the observation noise variances of a
+//! [`ConstantVelocity2DModel`] observing 2D position are found from a
+//! recorded dataset by Nelder-Mead search over their logarithms.
+
+use nalgebra::{dimension::U4, Matrix2, Vector2, Vector3};
+
+use kalman_no_std::{
+    CovarianceUpdateMethod, Error, ErrorKind, ObservationModel, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+use crate::motion_model::{ConstantVelocity2DModel, PositionObservationModel};
+
+/// Relative change of the objective over the simplex below which the search
+/// has converged.
+const TOLERANCE: f64 = 1e-9;
+
+/// Initial step of each logarithmic parameter, a factor of about 1.65.
+const INITIAL_STEP: f64 = 0.5;
+
+/// Noise parameters of a constant velocity model observing 2D position
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoiseParameters {
+    /// Scale of the process noise covariance, as passed to
+    /// [`ConstantVelocity2DModel::new`].
+    pub noise_scale: f64,
+    /// Diagonal of the observation noise covariance `R`.
+    pub observation_variance: Vector2<f64>,
+}
+
+impl NoiseParameters {
+    /// Create new `NoiseParameters`. All values must be positive.
+    pub fn new(noise_scale: f64, observation_variance: Vector2<f64>) -> Self {
+        Self {
+            noise_scale,
+            observation_variance,
+        }
+    }
+
+    /// Create the motion model for observations `dt` apart.
+    pub fn motion_model(&self, dt: f64) -> ConstantVelocity2DModel<f64> {
+        ConstantVelocity2DModel::new(dt, self.noise_scale)
+    }
+
+    /// Get the observation noise covariance `R`.
+    pub fn observation_noise_covariance(&self) -> Matrix2<f64> {
+        Matrix2::from_diagonal(&self.observation_variance)
+    }
+
+    fn to_log(self) -> Vector3<f64> {
+        Vector3::new(
+            self.noise_scale.ln(),
+            self.observation_variance.x.ln(),
+            self.observation_variance.y.ln(),
+        )
+    }
+
+    fn from_log(x: &Vector3<f64>) -> Self {
+        Self::new(x.x.exp(), Vector2::new(x.y.exp(), x.z.exp()))
+    }
+}
+
+/// The quantity optimized by [`autotune`]
+#[derive(Debug, Clone, Copy)]
+pub enum TuningObjective<'a> {
+    /// Maximize the log-likelihood of the innovations.
+    LogLikelihood,
+    /// Minimize the root mean square error of the updated position against
+    /// the true positions, one per observation.
+    Rmse(&'a [Vector2<f64>]),
+}
+
+/// How the search of [`autotune`] ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvergenceReport {
+    /// Number of iterations.
+    pub iterations: usize,
+    /// Number of times the filter was run over the dataset.
+    pub evaluations: usize,
+    /// Whether the objective converged before the iteration limit.
+    pub converged: bool,
+    /// Objective of the initial guess.
+    pub initial_objective: f64,
+    /// Objective of the tuned parameters.
+    ///
+    /// This is the negative log-likelihood or the RMSE, so that smaller is
+    /// better for both.
+    pub objective: f64,
+}
+
+/// Run the filter over the dataset and compute the objective.
+fn evaluate(
+    dt: f64,
+    initial_estimate: &StateAndCovariance<f64, U4>,
+    observations: &[Vector2<f64>],
+    objective: TuningObjective,
+    parameters: &NoiseParameters,
+) -> Result<f64, Error> {
+    let motion_model = parameters.motion_model(dt);
+    let observation_model =
+        PositionObservationModel::new(parameters.observation_noise_covariance());
+    let mut estimate = initial_estimate.clone();
+    let mut total = 0.0;
+    for (i, observation) in observations.iter().enumerate() {
+        let prior = motion_model.predict(&estimate);
+        if observation.iter().any(|v| v.is_nan()) {
+            // A missing observation only advances the estimate.
+            estimate = prior;
+        } else {
+            if let TuningObjective::LogLikelihood = objective {
+                total -= observation_model
+                    .innovation(&prior, observation)
+                    .log_likelihood()?;
+            }
+            estimate = observation_model.update(
+                &prior,
+                observation,
+                CovarianceUpdateMethod::JosephForm,
+            )?;
+        }
+        if let TuningObjective::Rmse(truth) = objective {
+            let error = estimate.state().fixed_rows::<2>(0) - truth[i];
+            total += error.norm_squared();
+        }
+    }
+    Ok(match objective {
+        TuningObjective::LogLikelihood => total,
+        TuningObjective::Rmse(_) => (total / observations.len() as f64).sqrt(),
+    })
+}
+
+/// Tune the noise parameters of a constant velocity model to a dataset.
+///
+/// `observations` are 2D positions `dt` apart, and the filter starts from
+/// `initial_estimate` before the first of them. The Nelder-Mead search
+/// starts at `initial_guess` and stops after `max_iterations` iterations or
+/// when the objective varies by less than a relative `1e-9` over the
+/// simplex. Observations containing NaN are treated as missing: the filter
+/// only predicts over them. Parameters for which the filter fails are treated
+/// as infinitely bad.
+///
+/// Returns `ErrorKind::InsufficientObservations` if there are no
+/// observations and `ErrorKind::DimensionMismatch` if the number of true
+/// positions differs from the number of observations. The error of the filter
+/// is returned if it fails for `initial_guess`, and
+/// `ErrorKind::NonFiniteState` if the objective of `initial_guess` is not
+/// finite, since the search cannot make progress from there.
+pub fn autotune(
+    dt: f64,
+    initial_estimate: &StateAndCovariance<f64, U4>,
+    observations: &[Vector2<f64>],
+    objective: TuningObjective,
+    initial_guess: NoiseParameters,
+    max_iterations: usize,
+) -> Result<(NoiseParameters, ConvergenceReport), Error> {
+    if observations.is_empty() {
+        return Err(ErrorKind::InsufficientObservations.into());
+    }
+    if let TuningObjective::Rmse(truth) = objective {
+        if truth.len() != observations.len() {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: observations.len(),
+                actual: truth.len(),
+            }
+            .into());
+        }
+    }
+
+    let initial_objective = evaluate(
+        dt,
+        initial_estimate,
+        observations,
+        objective,
+        &initial_guess,
+    )?;
+    if !initial_objective.is_finite() {
+        return Err(ErrorKind::NonFiniteState.into());
+    }
+
+    let mut evaluations = 1;
+    let mut cost = |x: &Vector3<f64>| -> f64 {
+        evaluations += 1;
+        let parameters = NoiseParameters::from_log(x);
+        match evaluate(dt, initial_estimate, observations, objective, &parameters) {
+            Ok(value) if value.is_finite() => value,
+            _ => f64::INFINITY,
+        }
+    };
+
+    let start = initial_guess.to_log();
+    let mut simplex: Vec<(Vector3<f64>, f64)> = vec![(start, initial_objective)];
+    for i in 0..3 {
+        let mut vertex = start;
+        vertex[i] += INITIAL_STEP;
+        simplex.push((vertex, cost(&vertex)));
+    }
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, second_worst, worst) = (simplex[0].1, simplex[2].1, simplex[3].1);
+        if worst - best <= TOLERANCE * (best.abs() + TOLERANCE) {
+            converged = true;
+            break;
+        }
+        iterations += 1;
+
+        let centroid = simplex[..3].iter().map(|(x, _)| x).sum::<Vector3<f64>>() / 3.0;
+        let worst_vertex = simplex[3].0;
+        let reflected = centroid + (centroid - worst_vertex);
+        let f_reflected = cost(&reflected);
+        if f_reflected < best {
+            let expanded = centroid + (centroid - worst_vertex) * 2.0;
+            let f_expanded = cost(&expanded);
+            simplex[3] = if f_expanded < f_reflected {
+                (expanded, f_expanded)
+            } else {
+                (reflected, f_reflected)
+            };
+        } else if f_reflected < second_worst {
+            simplex[3] = (reflected, f_reflected);
+        } else {
+            let contracted = if f_reflected < worst {
+                centroid + (reflected - centroid) * 0.5
+            } else {
+                centroid + (worst_vertex - centroid) * 0.5
+            };
+            let f_contracted = cost(&contracted);
+            if f_contracted < f_reflected.min(worst) {
+                simplex[3] = (contracted, f_contracted);
+            } else {
+                // Shrink towards the best vertex.
+                let best_vertex = simplex[0].0;
+                for vertex in simplex.iter_mut().skip(1) {
+                    let shrunk = best_vertex + (vertex.0 - best_vertex) * 0.5;
+                    *vertex = (shrunk, cost(&shrunk));
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let (x, objective) = simplex[0];
+    let report = ConvergenceReport {
+        iterations,
+        evaluations,
+        converged,
+        initial_objective,
+        objective,
+    };
+    Ok((NoiseParameters::from_log(&x), report))
+}
+
+#[test]
+fn test_autotune() {
+    use nalgebra::{Matrix4, Vector4};
+
+    // Deterministic standard normal noise by the Box-Muller transform.
+    let mut seed: u64 = 1;
+    let mut uniform = move || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((seed >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    };
+    let mut normal = move || {
+        let (u, v) = (uniform(), uniform());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    };
+
+    let dt = 0.1;
+    let truth_parameters = NoiseParameters::new(2.0, Vector2::new(0.04, 0.09));
+    let motion_model = truth_parameters.motion_model(dt);
+    // Factor of the process noise covariance of one axis.
+    let l11 = (dt * dt * dt / 3.0).sqrt();
+    let l21 = dt * dt / 2.0 / l11;
+    let l22 = (dt - l21 * l21).sqrt();
+    let sigma = truth_parameters.noise_scale.sqrt();
+
+    let mut state = Vector4::new(0.0, 0.0, 1.0, -1.0);
+    let mut positions = Vec::new();
+    let mut observations = Vec::new();
+    for _ in 0..300 {
+        let (a, b, c, d) = (normal(), normal(), normal(), normal());
+        let noise = Vector4::new(l11 * a, l11 * b, l21 * a + l22 * c, l21 * b + l22 * d);
+        state = motion_model.transition_model * state + noise * sigma;
+        let position = Vector2::new(state.x, state.y);
+        positions.push(position);
+        observations.push(
+            position
+                + Vector2::new(
+                    truth_parameters.observation_variance.x.sqrt() * normal(),
+                    truth_parameters.observation_variance.y.sqrt() * normal(),
+                ),
+        );
+    }
+    let initial = StateAndCovariance::new(Vector4::zeros(), Matrix4::identity() * 10.0);
+    let guess = NoiseParameters::new(0.1, Vector2::new(1.0, 1.0));
+
+    let (tuned, report) = autotune(
+        dt,
+        &initial,
+        &observations,
+        TuningObjective::LogLikelihood,
+        guess,
+        500,
+    )
+    .unwrap();
+    assert!(report.converged, "{:?}", report);
+    assert!(report.objective < report.initial_objective);
+    let ratio = |a: f64, b: f64| (a / b - 1.0).abs();
+    assert!(
+        ratio(tuned.noise_scale, truth_parameters.noise_scale) < 0.5,
+        "{:?}",
+        tuned
+    );
+    for i in 0..2 {
+        let variance = tuned.observation_variance[i];
+        assert!(
+            ratio(variance, truth_parameters.observation_variance[i]) < 0.25,
+            "{:?}",
+            tuned
+        );
+    }
+
+    let (tuned, report) = autotune(
+        dt,
+        &initial,
+        &observations,
+        TuningObjective::Rmse(&positions),
+        guess,
+        500,
+    )
+    .unwrap();
+    assert!(report.converged, "{:?}", report);
+    assert!(report.objective < report.initial_objective);
+    let rmse = |parameters: &NoiseParameters| {
+        evaluate(
+            dt,
+            &initial,
+            &observations,
+            TuningObjective::Rmse(&positions),
+            parameters,
+        )
+        .unwrap()
+    };
+    assert!(rmse(&tuned) <= rmse(&truth_parameters) * 1.001);
+
+    // Dropouts are skipped rather than making every objective infinite.
+    let mut dropouts = observations.clone();
+    for observation in dropouts.iter_mut().step_by(10) {
+        *observation = Vector2::new(f64::NAN, f64::NAN);
+    }
+    let (tuned_dropouts, report) = autotune(
+        dt,
+        &initial,
+        &dropouts,
+        TuningObjective::LogLikelihood,
+        guess,
+        500,
+    )
+    .unwrap();
+    assert!(report.converged, "{:?}", report);
+    assert!(report.objective.is_finite());
+    assert!(
+        ratio(tuned_dropouts.noise_scale, truth_parameters.noise_scale) < 0.5,
+        "{:?}",
+        tuned_dropouts
+    );
+
+    // Parameters for which the filter fails cannot start the search.
+    let singular = NoiseParameters::new(0.0, Vector2::zeros());
+    let result = autotune(
+        dt,
+        &StateAndCovariance::new(Vector4::zeros(), Matrix4::zeros()),
+        &observations,
+        TuningObjective::LogLikelihood,
+        singular,
+        500,
+    );
+    assert!(result.is_err(), "{:?}", result);
+
+    assert_eq!(
+        autotune(
+            dt,
+            &initial,
+            &observations,
+            TuningObjective::Rmse(&positions[1..]),
+            guess,
+            500
+        )
+        .unwrap_err()
+        .kind(),
+        &ErrorKind::DimensionMismatch {
+            expected: 300,
+            actual: 299
+        }
+    );
+}
@@ -5,11 +5,14 @@ use na::{
     dimension::DimMin,
     dimension::{U2, U4},
     DefaultAllocator, Matrix1x2, Matrix1x4, Matrix2, Matrix2x4, Matrix4, Matrix4x2, OVector,
-    Vector2, Vector4,
+    RealField, Vector2, Vector4,
 };
 use nalgebra_rand_mvn::rand_mvn;
 
-use kalman_no_std::{KalmanFilterNoControl, ObservationModel};
+use kalman_no_std::{
+    dual_jacobian, ConsistencyEvaluator, KalmanFilterNoControl, ObservationModel,
+    TransitionModelLinearNoControl,
+};
 use models::motion_model;
 
 
@@ -17,6 +20,13 @@ type MyType = f64;
 
 // observation model -------
 /// The observation is [x**3, xy].
+///
+/// Generic over the scalar type so that it can be differentiated with
+/// `Dual` numbers.
+fn observe<R: RealField + Copy>(state: &OVector<R, U4>) -> OVector<R, U2> {
+    Vector2::new(state.x * state.x * state.x, state.x * state.y)
+}
+
 struct NonlinearObservationModel {}
 
 impl NonlinearObservationModel {
@@ -26,21 +36,13 @@ impl NonlinearObservationModel {
     }
     /// Construct a new `LinearizedObservationModel` by linearizing around `state`.
     fn linearize_at(&self, state: &OVector<MyType, U4>) -> LinearizedObservationModel {
-        let evaluation_func = |state: &OVector<MyType, U4>| {
-            Vector2::<MyType>::new(state.x * state.x * state.x, state.x * state.y)
-        };
-
-        // Create Jacobian of the observation model. We only observe the position.
-        #[rustfmt::skip]
-        let observation_matrix = Matrix2x4::<MyType>::new(
-            3.0 * state.x * state.x, 0.0, 0.0, 0.0,
-            state.y, state.x, 0.0, 0.0,
-        );
+        // Jacobian of the observation model, by automatic differentiation.
+        let (_, observation_matrix) = dual_jacobian(observe, state);
         let observation_matrix_transpose = observation_matrix.transpose();
         let observation_noise_covariance = Matrix2::<MyType>::new(0.01, 0.0, 0.0, 0.01);
 
         LinearizedObservationModel {
-            evaluation_func: Box::new(evaluation_func),
+            evaluation_func: Box::new(observe::<MyType>),
             observation_matrix,
             observation_matrix_transpose,
             observation_noise_covariance,
@@ -142,14 +144,19 @@ fn main() -> Result<(), anyhow::Error> {
         kalman_no_std::StateAndCovariance::new(true_initial_state, initial_covariance);
 
     let mut state_estimates = vec![];
-    for this_observation in observation.iter() {
+    let mut consistency = ConsistencyEvaluator::<MyType, U2, 5>::new(0.95);
+    for (true_state, this_observation) in state.iter().zip(observation.iter()) {
         let observation_model = observation_model_gen.linearize_at(previous_estimate.state());
         let kf = KalmanFilterNoControl::new(&motion_model, &observation_model);
 
+        let prior = motion_model.predict(&previous_estimate);
+        consistency.add_innovation(&observation_model.innovation(&prior, this_observation))?;
         let this_estimate = kf.step(&previous_estimate, this_observation)?;
+        consistency.add_estimate(true_state, &this_estimate)?;
         state_estimates.push(*this_estimate.state());
         previous_estimate = this_estimate;
     }
+    log::info!("filter consistency:\n{}", consistency.report());
     println!(&times, &state, &observation, &state_estimates);
     Ok(())
 }